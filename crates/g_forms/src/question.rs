@@ -723,6 +723,43 @@ impl QuestionType {
             }),
         }
     }
+    /// Select an option by its label instead of its position
+    pub fn fill_option_label(&mut self, label: &str) -> FillResult {
+        let idx = self.options().map(|options| {
+            options
+                .iter()
+                .position(|opt| opt.trim().eq_ignore_ascii_case(label.trim()))
+        });
+
+        match idx {
+            Some(Some(idx)) => self.fill_option(idx),
+            Some(None) => Err(FillError::Other(
+                format!("no option labelled '{}'", label).into(),
+            )),
+            // let fill_option report the incorrect question type
+            None => self.fill_option(0),
+        }
+    }
+
+    /// Labels of the available options, for selection type questions
+    pub fn options(&self) -> Option<Vec<&str>> {
+        let inner = match self {
+            QuestionType::MultipleChoice(qn) => &qn.inner,
+            QuestionType::DropDown(qn) => &qn.inner,
+            QuestionType::CheckBox(qn) => &qn.inner,
+            QuestionType::LinearScale(qn) => &qn.inner,
+            _ => return None,
+        };
+
+        Some(
+            inner
+                .iter()
+                .flatten()
+                .map(|opt| opt.answer.as_str())
+                .collect(),
+        )
+    }
+
     pub fn fill_date(&mut self, resp: NaiveDateTime) -> FillResult {
         match self {
             QuestionType::Date(qn) => qn._fill_date(resp),
@@ -842,6 +879,40 @@ mod tests {
         assert_eq!(stringified, "09:18:27");
     }
 
+    #[test]
+    fn test_fill_option_label() {
+        let qn = Question::<MultipleChoice> {
+            inner: Some(
+                ["Training", "Competition"]
+                    .iter()
+                    .map(|label| crate::form::SingleSelection {
+                        answer: label.to_string(),
+                        selected: false,
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let mut qn = QuestionType::MultipleChoice(qn);
+
+        assert_eq!(qn.options(), Some(vec!["Training", "Competition"]));
+
+        qn.fill_option_label(" competition ").unwrap();
+        let QuestionType::MultipleChoice(inner) = &qn else {
+            panic!()
+        };
+        assert_eq!(inner.form_response().unwrap(), "Competition");
+
+        assert!(qn.fill_option_label("Leisure").is_err());
+
+        let mut qn = QuestionType::ShortAnswer(Question::<ShortAnswer>::default());
+        assert!(qn.options().is_none());
+        assert!(matches!(
+            qn.fill_option_label("Training"),
+            Err(FillError::IncorrectQuestionType { .. })
+        ));
+    }
+
     #[test]
     fn asd() {
         let x = reqwest::Url::try_from("http://asd.com");
@@ -0,0 +1,741 @@
+//! Google sheets API (v4) backend.
+//!
+//! Unlike [HttpSource](crate::HttpSource), spreadsheets do not need to be
+//! publicly accessible. They only need to be shared with a service account,
+//! which authenticates by signing a JWT and exchanging it for an access token.
+
+use std::{fmt::Debug, path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use polars::prelude::DataFrame;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{csv_to_dataframe, Cell, Error, Result, SheetSource, SheetWriter};
+
+/// Where the sheets API is hosted
+const SHEETS_API_URL: &str = "https://sheets.googleapis.com";
+
+/// Scope requested for each access token
+const SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+
+/// Lifetime of each signed JWT. Google does not accept anything above 1 hour.
+const JWT_LIFETIME: Duration = Duration::hours(1);
+
+/// Access tokens are refreshed this long before they expire
+const TOKEN_REFRESH_MARGIN: Duration = Duration::minutes(1);
+
+/// Fields used from a service account key file.
+///
+/// Key files are created from the google cloud console, and contain
+/// more fields than the ones listed here.
+#[derive(Clone, Deserialize)]
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    pub private_key: String,
+    pub token_uri: String,
+}
+
+impl Debug for ServiceAccountKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceAccountKey")
+            .field("client_email", &self.client_email)
+            .field("token_uri", &self.token_uri)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Claims sent to the token endpoint
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+/// Response from the token endpoint
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+}
+
+/// Cached access token
+#[derive(Clone, Debug)]
+struct AccessToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// A range of values, as returned by `values.get` and `values.batchGet`
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ValueRange {
+    pub range: String,
+    #[serde(default)]
+    pub values: Vec<Vec<String>>,
+}
+
+/// Response body of `values.batchGet`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchGetResponse {
+    #[serde(default)]
+    value_ranges: Vec<ValueRange>,
+}
+
+/// Response body of `spreadsheets.get`, trimmed to sheet titles
+#[derive(Deserialize)]
+struct SpreadsheetResponse {
+    #[serde(default)]
+    sheets: Vec<SheetResponse>,
+}
+
+#[derive(Deserialize)]
+struct SheetResponse {
+    properties: SheetProperties,
+}
+
+#[derive(Deserialize)]
+struct SheetProperties {
+    title: String,
+}
+
+/// Reads sheets through the google sheets API, as a service account.
+///
+/// Access tokens are cached and shared between clones of this struct.
+#[derive(Clone)]
+pub struct ApiSource {
+    key: ServiceAccountKey,
+    encoding_key: EncodingKey,
+    base_url: String,
+    client: reqwest::Client,
+    token: Arc<RwLock<Option<AccessToken>>>,
+}
+
+impl Debug for ApiSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiSource")
+            .field("key", &self.key)
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ApiSource {
+    /// Create a source from a service account key
+    pub fn new(key: ServiceAccountKey) -> Result<Self> {
+        let encoding_key = EncodingKey::from_rsa_pem(key.private_key.as_bytes())
+            .map_err(|e| Error::Auth(format!("invalid private key: {}", e)))?;
+
+        Ok(Self {
+            key,
+            encoding_key,
+            base_url: SHEETS_API_URL.to_string(),
+            client: reqwest::Client::new(),
+            token: Default::default(),
+        })
+    }
+
+    /// Create a source from the path to a service account key file
+    pub fn from_key_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::Auth(format!("unable to read {}: {}", path.display(), e)))?;
+        let key: ServiceAccountKey = serde_json::from_str(&contents)
+            .map_err(|e| Error::Auth(format!("invalid key file {}: {}", path.display(), e)))?;
+
+        Self::new(key)
+    }
+
+    /// Point the source to some other host for the sheets API, e.g. a mock server.
+    ///
+    /// The token endpoint is taken from the key.
+    pub fn with_base_url<T: ToString>(mut self, base_url: T) -> Self {
+        self.base_url = base_url.to_string().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Returns a valid access token, requesting a new one if the cached
+    /// token is missing or about to expire.
+    async fn access_token(&self) -> Result<String> {
+        let now = Utc::now();
+
+        if let Some(token) = &*self.token.read().await {
+            if token.expires_at - TOKEN_REFRESH_MARGIN > now {
+                return Ok(token.token.clone());
+            }
+        }
+
+        let mut lock = self.token.write().await;
+
+        // another task may have refreshed the token while waiting for the lock
+        if let Some(token) = &*lock {
+            if token.expires_at - TOKEN_REFRESH_MARGIN > now {
+                return Ok(token.token.clone());
+            }
+        }
+
+        let claims = Claims {
+            iss: &self.key.client_email,
+            scope: SCOPE,
+            aud: &self.key.token_uri,
+            iat: now.timestamp(),
+            exp: (now + JWT_LIFETIME).timestamp(),
+        };
+        let assertion =
+            jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &self.encoding_key)
+                .map_err(|e| Error::Auth(format!("unable to sign token: {}", e)))?;
+
+        let resp = self
+            .client
+            .post(&self.key.token_uri)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", &assertion),
+            ])
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            return Err(Error::Auth(format!(
+                "token request failed with status {}: {}",
+                resp.status(),
+                resp.text().await.unwrap_or_default()
+            )));
+        }
+
+        let token: TokenResponse = resp
+            .json()
+            .await
+            .map_err(|e| Error::Auth(format!("invalid token response: {}", e)))?;
+
+        *lock = Some(AccessToken {
+            token: token.access_token.clone(),
+            expires_at: now + Duration::seconds(token.expires_in),
+        });
+
+        Ok(token.access_token)
+    }
+
+    /// Build an url to the API from path segments
+    fn url(&self, segments: &[&str]) -> Result<Url> {
+        let mut url = Url::parse(&self.base_url).map_err(|e| {
+            Error::Transport(format!("invalid url {}: {}", self.base_url, e).into())
+        })?;
+
+        url.path_segments_mut()
+            .map_err(|_| Error::Transport(format!("invalid url {}", self.base_url).into()))?
+            .pop_if_empty()
+            .extend(segments);
+
+        Ok(url)
+    }
+
+    /// Perform an authenticated GET request and deserialize the response body.
+    ///
+    /// `range` is reported in [Error::SheetNotFound] if the API is unable to parse it.
+    async fn get_json<T: serde::de::DeserializeOwned>(
+        &self,
+        url: Url,
+        range: Option<&str>,
+    ) -> Result<T> {
+        self.send_json(self.client.get(url), range).await
+    }
+
+    /// Authenticate and send a request, then deserialize the response body.
+    async fn send_json<T: serde::de::DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+        range: Option<&str>,
+    ) -> Result<T> {
+        let token = self.access_token().await?;
+        let resp = request.bearer_auth(token).send().await?;
+
+        match (resp.status(), range) {
+            (StatusCode::BAD_REQUEST, Some(range)) => {
+                let body = resp.text().await.unwrap_or_default();
+                match body.contains("Unable to parse range") {
+                    true => Err(Error::SheetNotFound(range.to_string())),
+                    false => Err(Error::Status(StatusCode::BAD_REQUEST)),
+                }
+            }
+            _ => Ok(resp.error_for_status()?.json().await?),
+        }
+    }
+
+    /// Returns the titles of all sheets inside a spreadsheet, in order
+    pub async fn sheet_names(&self, sheet_id: &str) -> Result<Vec<String>> {
+        let mut url = self.url(&["v4", "spreadsheets", sheet_id])?;
+        url.query_pairs_mut()
+            .append_pair("fields", "sheets.properties.title");
+
+        let spreadsheet: SpreadsheetResponse = self.get_json(url, None).await?;
+
+        Ok(spreadsheet
+            .sheets
+            .into_iter()
+            .map(|s| s.properties.title)
+            .collect())
+    }
+
+    /// Read a range of values with `values.get`.
+    ///
+    /// `range` is in A1 notation, e.g. `configs` or `'Jul-2023'!A1:C10`.
+    pub async fn values_get(&self, sheet_id: &str, range: &str) -> Result<ValueRange> {
+        let url = self.url(&["v4", "spreadsheets", sheet_id, "values", range])?;
+
+        self.get_json(url, Some(range)).await
+    }
+
+    /// Read multiple ranges of values in a single request with `values.batchGet`.
+    ///
+    /// Ranges are returned in the order they were requested.
+    pub async fn batch_get(&self, sheet_id: &str, ranges: &[&str]) -> Result<Vec<ValueRange>> {
+        let mut url = self.url(&["v4", "spreadsheets", sheet_id, "values:batchGet"])?;
+        url.query_pairs_mut()
+            .extend_pairs(ranges.iter().map(|r| ("ranges", r)));
+
+        let resp: BatchGetResponse = self.get_json(url, Some(&ranges.join(", "))).await?;
+
+        Ok(resp.value_ranges)
+    }
+
+    /// Overwrite a range of values with `values.update`.
+    ///
+    /// Values are parsed as if they were typed in by a user.
+    pub async fn values_update(
+        &self,
+        sheet_id: &str,
+        range: &str,
+        values: Vec<Vec<String>>,
+    ) -> Result<()> {
+        let mut url = self.url(&["v4", "spreadsheets", sheet_id, "values", range])?;
+        url.query_pairs_mut()
+            .append_pair("valueInputOption", "USER_ENTERED");
+
+        let body = serde_json::json!({
+            "range": range,
+            "majorDimension": "ROWS",
+            "values": values,
+        });
+
+        let _: serde::de::IgnoredAny = self
+            .send_json(self.client.put(url).json(&body), Some(range))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Add rows after the table found in `range` with `values.append`.
+    pub async fn values_append(
+        &self,
+        sheet_id: &str,
+        range: &str,
+        values: Vec<Vec<String>>,
+    ) -> Result<()> {
+        let append = format!("{}:append", range);
+        let mut url = self.url(&["v4", "spreadsheets", sheet_id, "values", &append])?;
+        url.query_pairs_mut()
+            .append_pair("valueInputOption", "USER_ENTERED")
+            .append_pair("insertDataOption", "INSERT_ROWS");
+
+        let body = serde_json::json!({
+            "majorDimension": "ROWS",
+            "values": values,
+        });
+
+        let _: serde::de::IgnoredAny = self
+            .send_json(self.client.post(url).json(&body), Some(range))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Read multiple sheets from a spreadsheet in a single request
+    pub async fn batch_get_as_dataframes(
+        &self,
+        sheet_id: &str,
+        sheet_names: &[&str],
+    ) -> Result<Vec<DataFrame>> {
+        let ranges: Vec<String> = sheet_names.iter().map(|s| quote_sheet_name(s)).collect();
+        let ranges: Vec<&str> = ranges.iter().map(|s| s.as_str()).collect();
+
+        self.batch_get(sheet_id, &ranges)
+            .await?
+            .iter()
+            .map(|r| csv_to_dataframe(values_to_csv(&r.values)?))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl SheetSource for ApiSource {
+    async fn get_as_dataframe(
+        &self,
+        sheet_id: &str,
+        sheet_name: Option<&str>,
+    ) -> Result<DataFrame> {
+        let name = match sheet_name {
+            Some(name) => name.to_string(),
+            None => self
+                .sheet_names(sheet_id)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or(Error::Empty)?,
+        };
+
+        let values = self
+            .values_get(sheet_id, &quote_sheet_name(&name))
+            .await
+            .map_err(|e| match e {
+                Error::SheetNotFound(_) => Error::SheetNotFound(name.clone()),
+                other => other,
+            })?;
+
+        csv_to_dataframe(values_to_csv(&values.values)?)
+    }
+
+    fn as_writer(&self) -> Option<&dyn SheetWriter> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl SheetWriter for ApiSource {
+    async fn update_range(
+        &self,
+        sheet_id: &str,
+        sheet_name: &str,
+        start: Cell,
+        values: Vec<Vec<String>>,
+    ) -> Result<()> {
+        let range = format!("{}!{}", quote_sheet_name(sheet_name), start);
+
+        self.values_update(sheet_id, &range, values)
+            .await
+            .map_err(|e| match e {
+                Error::SheetNotFound(_) => Error::SheetNotFound(sheet_name.to_string()),
+                other => other,
+            })
+    }
+
+    async fn append_rows(
+        &self,
+        sheet_id: &str,
+        sheet_name: &str,
+        rows: Vec<Vec<String>>,
+    ) -> Result<()> {
+        self.values_append(sheet_id, &quote_sheet_name(sheet_name), rows)
+            .await
+            .map_err(|e| match e {
+                Error::SheetNotFound(_) => Error::SheetNotFound(sheet_name.to_string()),
+                other => other,
+            })
+    }
+}
+
+/// Quote a sheet name for use as an A1 range, covering the entire sheet
+fn quote_sheet_name(name: &str) -> String {
+    format!("'{}'", name.replace('\'', "''"))
+}
+
+/// Convert rows of values to a csv string.
+///
+/// The API omits trailing empty cells, so rows are padded to the widest row.
+fn values_to_csv(values: &[Vec<String>]) -> Result<String> {
+    let width = values.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    for row in values {
+        let padded = row
+            .iter()
+            .map(|cell| cell.as_str())
+            .chain(std::iter::repeat_n("", width - row.len()));
+
+        writer
+            .write_record(padded)
+            .map_err(|e| Error::Transport(e.into()))?;
+    }
+
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| Error::Transport(e.to_string().into()))?;
+
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PRIVATE_KEY: &str = include_str!("../fixtures/test_service_account.pem");
+
+    /// Creates a source pointing to a mock server for both the token and API endpoints
+    fn mock_source(server: &mockito::Server) -> ApiSource {
+        let key = ServiceAccountKey {
+            client_email: "canoebot@some-project.iam.gserviceaccount.com".to_string(),
+            private_key: TEST_PRIVATE_KEY.to_string(),
+            token_uri: format!("{}/token", server.url()),
+        };
+
+        ApiSource::new(key).unwrap().with_base_url(server.url())
+    }
+
+    async fn mock_token(server: &mut mockito::Server) -> mockito::Mock {
+        server
+            .mock("POST", "/token")
+            .match_body(mockito::Matcher::Regex(
+                "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer&assertion="
+                    .into(),
+            ))
+            .with_body(r#"{"access_token":"some_token","expires_in":3599,"token_type":"Bearer"}"#)
+            .expect(1)
+            .create_async()
+            .await
+    }
+
+    #[test]
+    fn test_values_to_csv() {
+        let values = vec![
+            vec!["name".to_string(), "a".to_string(), "b".to_string()],
+            vec!["asd".to_string(), "Y".to_string()],
+            vec![],
+        ];
+
+        assert_eq!(values_to_csv(&values).unwrap(), "name,a,b\nasd,Y,\n,,\n");
+        assert_eq!(values_to_csv(&[]).unwrap(), "");
+    }
+
+    #[test]
+    fn test_quote_sheet_name() {
+        assert_eq!(quote_sheet_name("Jul-2023"), "'Jul-2023'");
+        assert_eq!(quote_sheet_name("asd's sheet"), "'asd''s sheet'");
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let key = ServiceAccountKey {
+            client_email: "".to_string(),
+            private_key: "not a key".to_string(),
+            token_uri: "".to_string(),
+        };
+
+        assert!(matches!(ApiSource::new(key), Err(Error::Auth(_))));
+    }
+
+    /// Token is requested once and reused across requests
+    #[tokio::test]
+    async fn test_values_get_with_cached_token() {
+        let mut server = mockito::Server::new_async().await;
+        let token = mock_token(&mut server).await;
+        let values = server
+            .mock("GET", "/v4/spreadsheets/some_id/values/'Jul-2023'")
+            .match_header("authorization", "Bearer some_token")
+            .with_body(
+                r#"{"range":"'Jul-2023'!A1:C3","majorDimension":"ROWS",
+                "values":[["name","a","b"],["asd","Y"],["qwe","","Y"]]}"#,
+            )
+            .expect(2)
+            .create_async()
+            .await;
+
+        let source = mock_source(&server);
+
+        for _ in 0..2 {
+            let df = source
+                .get_as_dataframe("some_id", Some("Jul-2023"))
+                .await
+                .unwrap();
+            assert_eq!(df.shape(), (2, 3));
+        }
+
+        token.assert_async().await;
+        values.assert_async().await;
+    }
+
+    /// Tokens about to expire are refreshed before the next request
+    #[tokio::test]
+    async fn test_token_refresh() {
+        let mut server = mockito::Server::new_async().await;
+        let token = server
+            .mock("POST", "/token")
+            .with_body(r#"{"access_token":"short_lived","expires_in":30,"token_type":"Bearer"}"#)
+            .expect(2)
+            .create_async()
+            .await;
+        let _values = server
+            .mock("GET", "/v4/spreadsheets/some_id/values/'configs'")
+            .match_header("authorization", "Bearer short_lived")
+            .with_body(r#"{"range":"configs!A1:B2","values":[["name","boat_1"],["asd","K1"]]}"#)
+            .create_async()
+            .await;
+
+        let source = mock_source(&server);
+        for _ in 0..2 {
+            source
+                .get_as_dataframe("some_id", Some("configs"))
+                .await
+                .unwrap();
+        }
+
+        token.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_values_get_sheet_not_found() {
+        let mut server = mockito::Server::new_async().await;
+        let _token = mock_token(&mut server).await;
+        let _values = server
+            .mock("GET", "/v4/spreadsheets/some_id/values/'Nov-2026'")
+            .with_status(400)
+            .with_body(
+                r#"{"error":{"code":400,"message":"Unable to parse range: 'Nov-2026'","status":"INVALID_ARGUMENT"}}"#,
+            )
+            .create_async()
+            .await;
+
+        let source = mock_source(&server);
+        let res = source.get_as_dataframe("some_id", Some("Nov-2026")).await;
+
+        assert!(matches!(res, Err(Error::SheetNotFound(name)) if name == "Nov-2026"));
+    }
+
+    /// Without a sheet name, the first sheet is read
+    #[tokio::test]
+    async fn test_first_sheet() {
+        let mut server = mockito::Server::new_async().await;
+        let _token = mock_token(&mut server).await;
+        let _spreadsheet = server
+            .mock("GET", "/v4/spreadsheets/some_id")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "fields".into(),
+                "sheets.properties.title".into(),
+            ))
+            .with_body(
+                r#"{"sheets":[{"properties":{"title":"prog"}},{"properties":{"title":"old"}}]}"#,
+            )
+            .create_async()
+            .await;
+        let _values = server
+            .mock("GET", "/v4/spreadsheets/some_id/values/'prog'")
+            .with_body(r#"{"range":"prog!A1:B2","values":[["date","prog"],["2023-07-01","asd"]]}"#)
+            .create_async()
+            .await;
+
+        let source = mock_source(&server);
+        let df = source.get_as_dataframe("some_id", None).await.unwrap();
+
+        assert_eq!(df.shape(), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_batch_get() {
+        let mut server = mockito::Server::new_async().await;
+        let _token = mock_token(&mut server).await;
+        let _values = server
+            .mock("GET", "/v4/spreadsheets/some_id/values:batchGet")
+            .match_query(mockito::Matcher::Exact(
+                "ranges=%27configs%27&ranges=%27Jul-2023%27".into(),
+            ))
+            .with_body(
+                r#"{"spreadsheetId":"some_id","valueRanges":[
+                    {"range":"configs!A1:B2","values":[["name","boat_1"],["asd","K1"]]},
+                    {"range":"'Jul-2023'!A1:C3","values":[["name","a","b"],["asd","Y"],["qwe","","Y"]]}
+                ]}"#,
+            )
+            .create_async()
+            .await;
+
+        let source = mock_source(&server);
+        let dfs = source
+            .batch_get_as_dataframes("some_id", &["configs", "Jul-2023"])
+            .await
+            .unwrap();
+
+        assert_eq!(dfs.len(), 2);
+        assert_eq!(dfs[0].shape(), (1, 2));
+        assert_eq!(dfs[1].shape(), (2, 3));
+    }
+
+    #[tokio::test]
+    async fn test_update_cell() {
+        let mut server = mockito::Server::new_async().await;
+        let _token = mock_token(&mut server).await;
+        let update = server
+            .mock("PUT", "/v4/spreadsheets/some_id/values/'Jul-2023'!D5")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "valueInputOption".into(),
+                "USER_ENTERED".into(),
+            ))
+            .match_header("authorization", "Bearer some_token")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"range":"'Jul-2023'!D5","values":[["Y"]]}"#.into(),
+            ))
+            .with_body(r#"{"spreadsheetId":"some_id","updatedCells":1}"#)
+            .create_async()
+            .await;
+
+        let source = mock_source(&server);
+        source
+            .update_cell("some_id", "Jul-2023", Cell::new(4, 3), "Y")
+            .await
+            .unwrap();
+
+        update.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_append_rows() {
+        let mut server = mockito::Server::new_async().await;
+        let _token = mock_token(&mut server).await;
+        let append = server
+            .mock("POST", "/v4/spreadsheets/some_id/values/'logs':append")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("valueInputOption".into(), "USER_ENTERED".into()),
+                mockito::Matcher::UrlEncoded("insertDataOption".into(), "INSERT_ROWS".into()),
+            ]))
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"values":[["2023-07-01","AM"],["2023-07-01","PM"]]}"#.into(),
+            ))
+            .with_body(r#"{"spreadsheetId":"some_id"}"#)
+            .create_async()
+            .await;
+
+        let source = mock_source(&server);
+        source
+            .append_rows(
+                "some_id",
+                "logs",
+                vec![
+                    vec!["2023-07-01".to_string(), "AM".to_string()],
+                    vec!["2023-07-01".to_string(), "PM".to_string()],
+                ],
+            )
+            .await
+            .unwrap();
+
+        append.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_token_rejected() {
+        let mut server = mockito::Server::new_async().await;
+        let _token = server
+            .mock("POST", "/token")
+            .with_status(400)
+            .with_body(r#"{"error":"invalid_grant"}"#)
+            .create_async()
+            .await;
+
+        let source = mock_source(&server);
+        let res = source.get_as_dataframe("some_id", Some("configs")).await;
+
+        assert!(matches!(res, Err(Error::Auth(_))));
+    }
+}
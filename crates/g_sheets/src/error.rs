@@ -0,0 +1,90 @@
+//! Errors returned when fetching sheets
+
+use std::fmt::Display;
+
+use polars::error::PolarsError;
+use reqwest::StatusCode;
+
+/// Alias for results returned by this crate
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Possible errors when fetching a sheet
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to reach or read from the sheet backend
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+
+    /// Backend responded with a non-success status code
+    Status(StatusCode),
+
+    /// The requested sheet does not exist inside the spreadsheet.
+    ///
+    /// Google returns the first sheet instead of failing when the
+    /// requested sheet name is invalid, which also falls under this.
+    SheetNotFound(String),
+
+    /// Sheet contents could not be parsed as CSV
+    Parse(PolarsError),
+
+    /// Sheet contains no data
+    Empty,
+
+    /// Failed to authenticate with the sheets API
+    Auth(String),
+
+    /// The backend does not support writing
+    ReadOnly,
+
+    /// Sheet contents do not have the expected layout
+    Layout(String),
+
+    /// A name looked up in the sheet is not in it
+    NameNotFound(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "unable to reach sheet: {}", e),
+            Error::Status(code) => write!(f, "sheet request failed with status {}", code),
+            Error::SheetNotFound(name) => write!(f, "sheet \"{}\" does not exist", name),
+            Error::Parse(e) => write!(f, "unable to parse sheet: {}", e),
+            Error::Empty => write!(f, "sheet is empty"),
+            Error::Auth(e) => write!(f, "unable to authenticate: {}", e),
+            Error::ReadOnly => write!(f, "sheets cannot be written to"),
+            Error::Layout(sheet) => write!(f, "sheet \"{}\" has an unexpected layout", sheet),
+            Error::NameNotFound(name) => write!(f, "\"{}\" is not in the sheet", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(e) => Some(e.as_ref()),
+            Error::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(value: reqwest::Error) -> Self {
+        match value.status() {
+            Some(code) => Error::Status(code),
+            None => Error::Transport(Box::new(value)),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Transport(Box::new(value))
+    }
+}
+
+impl From<PolarsError> for Error {
+    fn from(value: PolarsError) -> Self {
+        Error::Parse(value)
+    }
+}
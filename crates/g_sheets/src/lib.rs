@@ -1,46 +1,61 @@
 //! Google sheets fetch interface
 // #![allow(unused)]
 
+mod api;
+mod error;
+mod source;
+mod write;
+
 use std::io::Cursor;
 
 use polars::prelude::{CsvReader, DataFrame, SerReader};
 
+pub use api::{ApiSource, ServiceAccountKey, ValueRange};
+pub use error::{Error, Result};
+pub use source::{DirSource, HttpSource, SheetSource};
+pub use write::{Cell, SheetWriter};
+
 /// Returns the contents of a sheet as a csv object
 ///
 /// - `sheet_id`: the resource ID for a sheet. sheet needs to
 /// be publicly accessible!
 /// - `sheet_name`: the exact sheet name to fetch. passing an
-/// invalid name/None will not result in a failure; google will instead
-/// return the first sheet created for that sheet_id.
-pub async fn get_as_csv<T: ToString, U: ToString>(sheet_id: T, sheet_name: Option<U>) -> String {
-    let url = format!(
-        "https://docs.google.com/spreadsheets/d/{}/gviz/tq?tqx=out:csv&sheet={}",
-        sheet_id.to_string(),
-        sheet_name
-            .and_then(|s| Some(s.to_string()))
-            .unwrap_or("".to_string())
-    );
-
-    let resp = match reqwest::get(url).await {
-        Ok(_resp) => _resp,
-        Err(_) => panic!(),
-    };
-
-    resp.text().await.unwrap()
+/// invalid name returns [Error::SheetNotFound]. passing None
+/// returns the first sheet created for that sheet_id.
+pub async fn get_as_csv<T: ToString, U: ToString>(
+    sheet_id: T,
+    sheet_name: Option<U>,
+) -> Result<String> {
+    let sheet_name = sheet_name.map(|s| s.to_string());
+
+    HttpSource::default()
+        .get_as_csv(&sheet_id.to_string(), sheet_name.as_deref())
+        .await
 }
 
 /// Returns the contents of a sheet as a polars dataframe
 pub async fn get_as_dataframe<T: ToString, U: ToString>(
     sheet_id: T,
     sheet_name: Option<U>,
-) -> DataFrame {
-    let csv_str = get_as_csv(sheet_id, sheet_name).await;
+) -> Result<DataFrame> {
+    let csv_str = get_as_csv(sheet_id, sheet_name).await?;
 
-    let curs = Cursor::new(csv_str);
+    csv_to_dataframe(csv_str)
+}
 
-    let df = CsvReader::new(curs).finish().unwrap();
+/// Parse the contents of a csv file into a dataframe
+fn csv_to_dataframe(csv_str: String) -> Result<DataFrame> {
+    if csv_str.trim().is_empty() {
+        return Err(Error::Empty);
+    }
+
+    let curs = Cursor::new(csv_str);
+    let df = CsvReader::new(curs).finish()?;
 
-    df
+    match df.width() {
+        0 => Err(Error::Empty),
+        _ => Ok(df),
+    }
 }
 
 #[cfg(test)]
@@ -94,7 +109,7 @@ mod tests {
 
         const NAME: Option<&str> = Some("JUL-2023");
 
-        let resp = get_as_csv(SHEET_ID, NAME).await;
+        let resp = get_as_csv(SHEET_ID, NAME).await.unwrap();
 
         // let csv_str = resp.().await;
 
@@ -129,10 +144,25 @@ mod tests {
 
         const NAME: Option<&str> = Some("JUL-2023");
 
-        let df = get_as_dataframe(SHEET_ID, NAME).await;
+        let df = get_as_dataframe(SHEET_ID, NAME).await.unwrap();
 
         println!("{:?}", df.head(None));
     }
+
+    #[test]
+    fn test_csv_to_dataframe() {
+        let df = csv_to_dataframe("name,boat\nasd,K1\nqwe,K2\n".to_string()).unwrap();
+        assert_eq!(df.shape(), (2, 2));
+
+        assert!(matches!(
+            csv_to_dataframe("".to_string()),
+            Err(Error::Empty)
+        ));
+        assert!(matches!(
+            csv_to_dataframe("\n\n".to_string()),
+            Err(Error::Empty)
+        ));
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,502 @@
+//! Pluggable sheet backends.
+//!
+//! Everything that reads spreadsheet data should go through [SheetSource],
+//! so that the same logic can be run against google sheets or local fixtures.
+
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use polars::prelude::DataFrame;
+
+use crate::{csv_to_dataframe, Cell, Error, Result, SheetWriter};
+
+/// A source of spreadsheet data.
+#[async_trait]
+pub trait SheetSource: Send + Sync {
+    /// Returns the contents of a sheet as a polars dataframe.
+    ///
+    /// - `sheet_id`: the resource ID for a spreadsheet.
+    /// - `sheet_name`: the sheet (tab) inside the spreadsheet to fetch.
+    /// Passing [None] returns the first sheet.
+    async fn get_as_dataframe(&self, sheet_id: &str, sheet_name: Option<&str>)
+        -> Result<DataFrame>;
+
+    /// Returns this source as a [SheetWriter], if it supports writing.
+    fn as_writer(&self) -> Option<&dyn SheetWriter> {
+        None
+    }
+}
+
+/// Where google sheets are hosted
+const GOOGLE_DOCS_URL: &str = "https://docs.google.com";
+
+/// Fetches sheets from the public gviz CSV endpoint.
+///
+/// Spreadsheets need to be publicly accessible!
+#[derive(Clone, Debug)]
+pub struct HttpSource {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl Default for HttpSource {
+    fn default() -> Self {
+        Self::with_base_url(GOOGLE_DOCS_URL)
+    }
+}
+
+impl HttpSource {
+    /// Create a source that fetches from some other host, e.g. a mock server
+    pub fn with_base_url<T: ToString>(base_url: T) -> Self {
+        Self {
+            base_url: base_url.to_string().trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Returns the contents of a sheet as a csv string.
+    ///
+    /// Google returns the first sheet when asked for a sheet that does not exist,
+    /// so named sheets are checked against the sheet listing of the spreadsheet.
+    /// The check is skipped if there is no listing, or it cannot be fetched.
+    pub async fn get_as_csv(&self, sheet_id: &str, sheet_name: Option<&str>) -> Result<String> {
+        let url = format!(
+            "{}/spreadsheets/d/{}/gviz/tq?tqx=out:csv&sheet={}",
+            self.base_url,
+            sheet_id,
+            sheet_name.unwrap_or("")
+        );
+
+        let name = match sheet_name {
+            Some(name) => name,
+            None => return self.get_text(&url).await,
+        };
+
+        let (csv, sheets) = tokio::join!(self.get_text(&url), self.sheet_names(sheet_id));
+
+        match sheets {
+            Ok(sheets) if sheets.is_empty() => {
+                // a single sheet, or the html view has changed
+                log::debug!(
+                    "no sheet listing for {}, not checking for {}",
+                    sheet_id,
+                    name
+                )
+            }
+            Ok(sheets) => {
+                if !sheets.iter().any(|s| s.eq_ignore_ascii_case(name)) {
+                    return Err(Error::SheetNotFound(name.to_string()));
+                }
+            }
+            Err(e) => log::warn!(
+                "unable to list sheets of {}, not checking for {}: {}",
+                sheet_id,
+                name,
+                e
+            ),
+        }
+
+        csv
+    }
+
+    /// Returns the names of all sheets inside a spreadsheet, in order.
+    ///
+    /// The listing is empty if the spreadsheet only has one sheet.
+    pub async fn sheet_names(&self, sheet_id: &str) -> Result<Vec<String>> {
+        let url = format!("{}/spreadsheets/d/{}/htmlview", self.base_url, sheet_id);
+        let html = self.get_text(&url).await?;
+
+        Ok(parse_sheet_names(&html))
+    }
+
+    /// Perform a GET request and return the body
+    async fn get_text(&self, url: &str) -> Result<String> {
+        let resp = self.client.get(url).send().await?.error_for_status()?;
+
+        Ok(resp.text().await?)
+    }
+}
+
+/// Extract sheet names from the html view of a spreadsheet
+fn parse_sheet_names(html: &str) -> Vec<String> {
+    let doc = scraper::Html::parse_document(html);
+
+    doc.select(&scraper::Selector::parse("#sheet-menu li a").unwrap())
+        .map(|elem| elem.text().collect::<String>().trim().to_string())
+        .collect()
+}
+
+#[async_trait]
+impl SheetSource for HttpSource {
+    async fn get_as_dataframe(
+        &self,
+        sheet_id: &str,
+        sheet_name: Option<&str>,
+    ) -> Result<DataFrame> {
+        let csv_str = self.get_as_csv(sheet_id, sheet_name).await?;
+
+        csv_to_dataframe(csv_str)
+    }
+}
+
+/// Reads sheets from CSV files in a directory.
+///
+/// Each spreadsheet is a subdirectory named after its sheet id,
+/// and each sheet is a CSV file inside it:
+/// ```text
+/// <root>/<sheet_id>/<sheet_name>.csv
+/// ```
+///
+/// Requesting a sheet without a name returns the first CSV file
+/// (sorted by file name) in the spreadsheet directory.
+#[derive(Clone, Debug)]
+pub struct DirSource {
+    root: PathBuf,
+}
+
+impl DirSource {
+    /// Create a source rooted at some directory
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Resolve the path to a sheet, if it exists.
+    fn sheet_path(&self, sheet_id: &str, sheet_name: Option<&str>) -> Option<PathBuf> {
+        let spreadsheet = self.root.join(sheet_id);
+
+        match sheet_name {
+            Some(name) => {
+                let path = spreadsheet.join(format!("{}.csv", name));
+                path.is_file().then_some(path)
+            }
+            None => {
+                let mut sheets = std::fs::read_dir(&spreadsheet)
+                    .ok()?
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+                    .collect::<Vec<_>>();
+
+                sheets.sort();
+                sheets.into_iter().next()
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SheetSource for DirSource {
+    async fn get_as_dataframe(
+        &self,
+        sheet_id: &str,
+        sheet_name: Option<&str>,
+    ) -> Result<DataFrame> {
+        let path = self
+            .sheet_path(sheet_id, sheet_name)
+            .ok_or_else(|| Error::SheetNotFound(sheet_name.unwrap_or(sheet_id).to_string()))?;
+
+        let contents = std::fs::read_to_string(path)?;
+
+        csv_to_dataframe(contents)
+    }
+
+    fn as_writer(&self) -> Option<&dyn SheetWriter> {
+        Some(self)
+    }
+}
+
+impl DirSource {
+    /// Read a sheet as rows of cells
+    fn read_cells(&self, path: &Path) -> Result<Vec<Vec<String>>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)
+            .map_err(|e| Error::Transport(e.into()))?;
+
+        reader
+            .records()
+            .map(|rec| {
+                rec.map(|r| r.iter().map(|cell| cell.to_string()).collect())
+                    .map_err(|e| Error::Transport(e.into()))
+            })
+            .collect()
+    }
+
+    /// Overwrite a sheet with rows of cells, padding each row to the same width
+    fn write_cells(&self, path: &Path, cells: &[Vec<String>]) -> Result<()> {
+        let width = cells.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut writer = csv::Writer::from_path(path).map_err(|e| Error::Transport(e.into()))?;
+
+        for row in cells {
+            let padded = row
+                .iter()
+                .map(|cell| cell.as_str())
+                .chain(std::iter::repeat_n("", width - row.len()));
+
+            writer
+                .write_record(padded)
+                .map_err(|e| Error::Transport(e.into()))?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SheetWriter for DirSource {
+    async fn update_range(
+        &self,
+        sheet_id: &str,
+        sheet_name: &str,
+        start: Cell,
+        values: Vec<Vec<String>>,
+    ) -> Result<()> {
+        let path = self
+            .sheet_path(sheet_id, Some(sheet_name))
+            .ok_or_else(|| Error::SheetNotFound(sheet_name.to_string()))?;
+        let mut cells = self.read_cells(&path)?;
+
+        for (row_offset, row_values) in values.into_iter().enumerate() {
+            let row_idx = start.row + row_offset;
+            if cells.len() <= row_idx {
+                cells.resize(row_idx + 1, Vec::new());
+            }
+
+            let row = &mut cells[row_idx];
+            for (col_offset, value) in row_values.into_iter().enumerate() {
+                let col_idx = start.col + col_offset;
+                if row.len() <= col_idx {
+                    row.resize(col_idx + 1, String::new());
+                }
+                row[col_idx] = value;
+            }
+        }
+
+        self.write_cells(&path, &cells)
+    }
+
+    async fn append_rows(
+        &self,
+        sheet_id: &str,
+        sheet_name: &str,
+        rows: Vec<Vec<String>>,
+    ) -> Result<()> {
+        let path = self
+            .sheet_path(sheet_id, Some(sheet_name))
+            .ok_or_else(|| Error::SheetNotFound(sheet_name.to_string()))?;
+        let mut cells = self.read_cells(&path)?;
+
+        // drop trailing empty rows, so rows are added right after the data
+        while cells
+            .last()
+            .is_some_and(|row| row.iter().all(|cell| cell.is_empty()))
+        {
+            cells.pop();
+        }
+        cells.extend(rows);
+
+        self.write_cells(&path, &cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a fixture spreadsheet with 2 sheets inside a temp directory
+    fn create_fixture(dir_name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(dir_name);
+        let spreadsheet = root.join("some_id");
+        std::fs::create_dir_all(&spreadsheet).unwrap();
+
+        std::fs::write(spreadsheet.join("configs.csv"), "name,boat_1\nasd,K1\n").unwrap();
+        std::fs::write(
+            spreadsheet.join("Jul-2023.csv"),
+            "name,a,b\nasd,Y,\nqwe,,Y\n",
+        )
+        .unwrap();
+
+        root
+    }
+
+    #[tokio::test]
+    async fn test_dir_source_named_sheet() {
+        let root = create_fixture("g_sheets_test_dir_source_named_sheet");
+        let source = DirSource::new(&root);
+
+        let df = source
+            .get_as_dataframe("some_id", Some("configs"))
+            .await
+            .unwrap();
+        assert_eq!(df.shape(), (1, 2));
+
+        let df = source
+            .get_as_dataframe("some_id", Some("Jul-2023"))
+            .await
+            .unwrap();
+        assert_eq!(df.shape(), (2, 3));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dir_source_missing_sheet() {
+        let root = create_fixture("g_sheets_test_dir_source_missing_sheet");
+        let source = DirSource::new(&root);
+
+        let res = source.get_as_dataframe("some_id", Some("Aug-2023")).await;
+        assert!(matches!(res, Err(Error::SheetNotFound(name)) if name == "Aug-2023"));
+
+        let res = source.get_as_dataframe("other_id", Some("configs")).await;
+        assert!(matches!(res, Err(Error::SheetNotFound(_))));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    /// Trimmed down html view of a spreadsheet with 2 sheets
+    const HTMLVIEW: &str = r##"<html><body><div id="top-bar">
+        <ul id="sheet-menu" role="navigation">
+            <li id="sheet-button-0"><a href="#">configs</a></li>
+            <li id="sheet-button-1234"><a href="#"> Jul-2023 </a></li>
+        </ul>
+    </div></body></html>"##;
+
+    #[test]
+    fn test_parse_sheet_names() {
+        assert_eq!(parse_sheet_names(HTMLVIEW), vec!["configs", "Jul-2023"]);
+        assert!(parse_sheet_names("<html><body></body></html>").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_http_source_sheet_not_found() {
+        let mut server = mockito::Server::new_async().await;
+        let _listing = server
+            .mock("GET", "/spreadsheets/d/some_id/htmlview")
+            .with_body(HTMLVIEW)
+            .create_async()
+            .await;
+        // google responds with the first sheet regardless of the name
+        let _csv = server
+            .mock("GET", "/spreadsheets/d/some_id/gviz/tq")
+            .match_query(mockito::Matcher::Any)
+            .with_body("name,boat_1\nasd,K1\n")
+            .create_async()
+            .await;
+
+        let source = HttpSource::with_base_url(server.url());
+
+        let df = source
+            .get_as_dataframe("some_id", Some("jul-2023"))
+            .await
+            .unwrap();
+        assert_eq!(df.shape(), (1, 2));
+
+        let df = source.get_as_dataframe("some_id", None).await.unwrap();
+        assert_eq!(df.shape(), (1, 2));
+
+        let res = source.get_as_dataframe("some_id", Some("Nov-2026")).await;
+        assert!(matches!(res, Err(Error::SheetNotFound(name)) if name == "Nov-2026"));
+    }
+
+    #[tokio::test]
+    async fn test_http_source_no_listing() {
+        let mut server = mockito::Server::new_async().await;
+        let _failed = server
+            .mock("GET", "/spreadsheets/d/failed_id/htmlview")
+            .with_status(500)
+            .create_async()
+            .await;
+        let _changed = server
+            .mock("GET", "/spreadsheets/d/changed_id/htmlview")
+            .with_body("<html><body><div id=\"tabs\"></div></body></html>")
+            .create_async()
+            .await;
+        let _csv = server
+            .mock("GET", mockito::Matcher::Regex("/gviz/tq".to_string()))
+            .match_query(mockito::Matcher::Any)
+            .with_body("name,boat_1\nasd,K1\n")
+            .create_async()
+            .await;
+
+        let source = HttpSource::with_base_url(server.url());
+
+        // the sheet is fetched without checking that it exists
+        for sheet_id in ["failed_id", "changed_id"] {
+            let df = source
+                .get_as_dataframe(sheet_id, Some("Nov-2026"))
+                .await
+                .unwrap();
+            assert_eq!(df.shape(), (1, 2));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_source_status() {
+        let mut server = mockito::Server::new_async().await;
+        let _missing = server
+            .mock("GET", mockito::Matcher::Any)
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let source = HttpSource::with_base_url(server.url());
+
+        let res = source.get_as_dataframe("other_id", None).await;
+        assert!(matches!(res, Err(Error::Status(code)) if code == 404));
+    }
+
+    #[tokio::test]
+    async fn test_dir_source_write() {
+        let root = create_fixture("g_sheets_test_dir_source_write");
+        let source = DirSource::new(&root);
+
+        source
+            .update_cell("some_id", "Jul-2023", Cell::new(2, 1), "Y")
+            .await
+            .unwrap();
+        source
+            .update_range(
+                "some_id",
+                "Jul-2023",
+                Cell::new(1, 2),
+                vec![vec!["Y".to_string(), "extra".to_string()]],
+            )
+            .await
+            .unwrap();
+        source
+            .append_rows(
+                "some_id",
+                "Jul-2023",
+                vec![vec!["zxc".to_string(), "".to_string(), "Y".to_string()]],
+            )
+            .await
+            .unwrap();
+
+        let contents = std::fs::read_to_string(root.join("some_id/Jul-2023.csv")).unwrap();
+        assert_eq!(contents, "name,a,b,\nasd,Y,Y,extra\nqwe,Y,Y,\nzxc,,Y,\n");
+
+        let res = source
+            .update_cell("some_id", "Aug-2023", Cell::new(0, 0), "Y")
+            .await;
+        assert!(matches!(res, Err(Error::SheetNotFound(_))));
+
+        assert!(HttpSource::default().as_writer().is_none());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dir_source_first_sheet() {
+        let root = create_fixture("g_sheets_test_dir_source_first_sheet");
+        let source = DirSource::new(&root);
+
+        // "Jul-2023.csv" sorts before "configs.csv"
+        let df = source.get_as_dataframe("some_id", None).await.unwrap();
+        assert_eq!(df.shape(), (2, 3));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+//! Writing back to sheets.
+//!
+//! Only authenticated backends (and local files) support writing;
+//! the public CSV export is read-only.
+
+use std::fmt::Display;
+
+use async_trait::async_trait;
+
+use crate::Result;
+
+/// Position of a cell inside a sheet, 0-indexed.
+///
+/// ```
+/// use g_sheets::Cell;
+///
+/// assert_eq!(Cell::new(0, 0).to_string(), "A1");
+/// assert_eq!(Cell::new(9, 27).to_string(), "AB10");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl Cell {
+    pub fn new(row: usize, col: usize) -> Self {
+        Self { row, col }
+    }
+
+    /// Returns the column letters of this cell, e.g. `A` or `AB`
+    pub fn col_letters(&self) -> String {
+        let mut letters = Vec::new();
+        let mut col = self.col + 1;
+
+        while col > 0 {
+            let rem = (col - 1) % 26;
+            letters.push((b'A' + rem as u8) as char);
+            col = (col - 1) / 26;
+        }
+
+        letters.iter().rev().collect()
+    }
+}
+
+/// Displays the cell in A1 notation
+impl Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.col_letters(), self.row + 1)
+    }
+}
+
+/// A destination for spreadsheet data.
+#[async_trait]
+pub trait SheetWriter: Send + Sync {
+    /// Overwrite a block of cells, with `start` as the top-left cell.
+    ///
+    /// Each inner vector is one row.
+    async fn update_range(
+        &self,
+        sheet_id: &str,
+        sheet_name: &str,
+        start: Cell,
+        values: Vec<Vec<String>>,
+    ) -> Result<()>;
+
+    /// Overwrite a single cell
+    async fn update_cell(
+        &self,
+        sheet_id: &str,
+        sheet_name: &str,
+        cell: Cell,
+        value: &str,
+    ) -> Result<()> {
+        self.update_range(sheet_id, sheet_name, cell, vec![vec![value.to_string()]])
+            .await
+    }
+
+    /// Add rows after the last row that contains data
+    async fn append_rows(
+        &self,
+        sheet_id: &str,
+        sheet_name: &str,
+        rows: Vec<Vec<String>>,
+    ) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_a1_notation() {
+        assert_eq!(Cell::new(0, 0).to_string(), "A1");
+        assert_eq!(Cell::new(4, 25).to_string(), "Z5");
+        assert_eq!(Cell::new(0, 26).to_string(), "AA1");
+        assert_eq!(Cell::new(0, 51).to_string(), "AZ1");
+        assert_eq!(Cell::new(0, 52).to_string(), "BA1");
+        assert_eq!(Cell::new(0, 701).to_string(), "ZZ1");
+        assert_eq!(Cell::new(0, 702).to_string(), "AAA1");
+    }
+}
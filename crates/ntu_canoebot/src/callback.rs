@@ -1,16 +1,20 @@
+mod attend;
 mod breakdown;
 pub mod callbacks;
+mod envelope;
 mod land;
 mod logsheet;
 mod namelist;
+mod onboarding;
 mod paddling;
 mod ping;
 // pub mod src;
+mod store;
 mod training;
 mod whatactually;
 
+use std::error::Error;
 use std::str::FromStr;
-use std::{error::Error, time::Duration};
 
 use anyhow::anyhow;
 use async_trait::async_trait;
@@ -18,16 +22,20 @@ use base64::engine::GeneralPurpose;
 use base64::Engine;
 use bincode::ErrorKind;
 use chrono::{Datelike, NaiveDate, NaiveTime, Timelike};
+use ntu_canoebot_attd::SheetError;
 use ntu_canoebot_traits::{DeriveEnumParent, EnumParent};
 use ntu_canoebot_util::debug_println;
+use ntu_canoebot_util::metrics::Counter;
 use serde::{Deserialize, Serialize};
 use teloxide::prelude::*;
 const BASE64_ENGINE: GeneralPurpose = base64::engine::general_purpose::STANDARD;
 
+pub use attend::attend_get;
 pub use breakdown::{breakdown_get, Breakdown};
 pub use land::land_get;
 pub use logsheet::{logsheet_start, LogSheet};
 pub use namelist::namelist_get;
+pub use onboarding::{onboarding_start, Onboarding};
 pub use paddling::{paddling_get, Paddling};
 pub use ping::ping_start;
 use teloxide::types::MaybeInaccessibleMessage;
@@ -35,7 +43,10 @@ pub use training::training_get;
 pub use whatactually::whatactually_get;
 
 use crate::{
+    error_report::{self, TaskOrigin},
     frame::construct_keyboard_tuple,
+    monitor::variant_name,
+    permissions,
     threadmonitor::{DynResult, THREAD_WATCH},
 };
 
@@ -65,6 +76,8 @@ pub enum Callback {
     LogSheet(logsheet::LogSheet),
     Ping(ping::Ping),
     WhatActually(whatactually::WhatActually),
+    Attend(attend::Attend),
+    Onboarding(onboarding::Onboarding),
     /// Custom callback handlers that might not be linked
     /// to a particular command.
     Custom,
@@ -132,6 +145,8 @@ impl HandleCallback for Callback {
             Callback::LogSheet(call) => call.handle_callback(bot, query).await,
             Callback::Ping(call) => call.handle_callback(bot, query).await,
             Callback::WhatActually(call) => call.handle_callback(bot, query).await,
+            Callback::Attend(call) => call.handle_callback(bot, query).await,
+            Callback::Onboarding(call) => call.handle_callback(bot, query).await,
             // testing
 
             // to catch unimpl'd callbacks
@@ -225,14 +240,11 @@ impl TryFrom<&Vec<u8>> for Callback {
     /// successfully serialize and deserialize the Callback type, or inline
     /// markup buttons won't work!
     fn try_from(value: &Vec<u8>) -> Result<Self, Self::Error> {
+        // long callbacks are stored, with a key in the button
+        let value = store::expand(value)?;
         let bin_decoded = BASE64_ENGINE.decode(value)?;
 
-        let bin_inflated = inflate::inflate_bytes(&bin_decoded)?;
-
-        match bincode::deserialize::<Callback>(&bin_inflated) {
-            Ok(_callback) => Ok(_callback),
-            Err(_err) => Err(_err),
-        }
+        Ok(envelope::open(&bin_decoded)?)
     }
 }
 
@@ -289,7 +301,7 @@ impl TryFrom<&Callback> for Vec<u8> {
             }
         };
 
-        let bin_deflate = deflate::deflate_bytes(&bin_data);
+        let bin_deflate = envelope::seal(&bin_data);
         debug_println!("size of callback data: {} bytes", bin_deflate.len());
         log::trace!("size of callback data: {} bytes", bin_deflate.len());
 
@@ -299,7 +311,7 @@ impl TryFrom<&Callback> for Vec<u8> {
             bin_chars.len(),
             &bin_chars
         );
-        Ok(bin_chars.as_bytes().to_owned())
+        Ok(store::shorten(bin_chars.into_bytes()))
     }
 }
 
@@ -373,35 +385,113 @@ pub async fn replace_with_whitespace(
     Ok(())
 }
 
+/// Tell the user that sheet data could not be fetched, then pass the error on.
+///
+/// A missing sheet is expected and passed on as [Answered](error_report::Answered),
+/// so that it only gets logged and counted. Other errors are reported to the exco.
+///
+/// Edits the message if `is_callback` is set, otherwise sends a new message.
+pub async fn reply_sheet_error(
+    bot: Bot,
+    msg: &Message,
+    is_callback: bool,
+    err: SheetError,
+) -> DynResult {
+    let text = match &err {
+        SheetError::SheetNotFound(name) => {
+            format!("The \"{}\" sheet doesn't exist yet.", name)
+        }
+        _ => format!("Unable to fetch sheet data: {}", err),
+    };
+
+    match is_callback {
+        true => bot.edit_message_text(msg.chat.id, msg.id, text).await?,
+        false => bot.send_message(msg.chat.id, text).await?,
+    };
+
+    match err {
+        SheetError::SheetNotFound(_) => Err(error_report::answered(err)),
+        _ => Err(err.into()),
+    }
+}
+
+const CALLBACKS: Counter =
+    Counter::new("canoebot_callbacks_total", "callbacks handled, by callback");
+
 /// Main callback handler
 pub async fn callback_handler(
     bot: Bot,
     query: CallbackQuery,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     /// Inner async fn
-    async fn inner_handler(bot: Bot, query: CallbackQuery) -> DynResult {
-        // answer the callback query once at the top
-        bot.answer_callback_query(&query.id).await?;
+    async fn inner_handler(
+        bot: Bot,
+        query: CallbackQuery,
+        callback_data: Callback,
+        expired: bool,
+        outdated: Option<Box<envelope::Outdated>>,
+    ) -> DynResult {
+        // long callbacks only last a while
+        if expired {
+            bot.answer_callback_query(&query.id)
+                .text("this menu has expired, please run the command again")
+                .show_alert(true)
+                .await?;
+            return Ok(());
+        }
 
-        let callback_data: Callback = {
-            if let Some(data) = &query.data {
-                let data_vec = data.as_bytes().to_owned();
-                match (&data_vec).try_into() {
-                    Ok(d) => d,
-                    Err(_) => Callback::Empty,
-                }
-            } else {
-                Callback::Empty
-            }
-        };
+        // buttons from before the callback layout changed
+        if let Some(outdated) = outdated {
+            log::info!("{} from {}", outdated, query.from.id);
+            bot.answer_callback_query(&query.id).await?;
+            let msg = message_from_callback_query(&query)?;
+            bot.edit_message_text(msg.chat.id, msg.id, outdated.reply())
+                .await?;
+            return Ok(());
+        }
 
         log::info!("{:?}", callback_data);
+
+        // answer the callback query once, with the reason if it is refused
+        if let Some(reason) = permissions::check(&callback_data, Some(&query.from)).await {
+            log::info!("refused {:?} for {}", callback_data, query.from.id);
+            bot.answer_callback_query(&query.id)
+                .text(reason)
+                .show_alert(true)
+                .await?;
+            return Ok(());
+        }
+        bot.answer_callback_query(&query.id).await?;
+
+        CALLBACKS.increment(&[("callback", &variant_name(&callback_data))]);
         callback_data.handle_callback(bot, query).await
     }
 
-    let handle = tokio::spawn(inner_handler(bot, query));
+    // (callback, is expired, is from an older version)
+    let (callback_data, expired, outdated) = match &query.data {
+        Some(data) => {
+            let data_vec = data.as_bytes().to_owned();
+            match Callback::try_from(&data_vec) {
+                Ok(d) => (d, false, None),
+                Err(e) => (
+                    Callback::Empty,
+                    e.is::<store::Expired>(),
+                    e.downcast::<envelope::Outdated>().ok(),
+                ),
+            }
+        }
+        None => (Callback::Empty, false, None),
+    };
+
+    let origin = TaskOrigin {
+        bot: bot.clone(),
+        name: format!("{} button", variant_name(&callback_data)),
+        chat: query.message.as_ref().map(|m| m.chat().id),
+    };
 
-    tokio::spawn(THREAD_WATCH.push(handle, Duration::from_secs(5)));
+    let handle = tokio::spawn(inner_handler(bot, query, callback_data, expired, outdated));
+
+    THREAD_WATCH.push_with(handle, origin);
 
     Ok(())
 }
@@ -435,4 +525,86 @@ mod test {
 
         assert_eq!(callback, deserialized);
     }
+
+    /// Crew swaps fit in callback data, and are exco only
+    #[test]
+    fn test_crew_swap_callback() {
+        use crate::permissions::{RequiredRole, Role};
+
+        let swap = Callback::Paddling(Paddling::CrewSwap {
+            date: chrono::Local::now().date_naive().into(),
+            time_slot: false,
+            freshies: false,
+            excluded: Default::default(),
+            action: paddling::CrewAction::Pick(63),
+        });
+
+        assert!(String::from(swap.clone()).len() <= 64);
+        assert_eq!(swap.required_role(), Role::Exco);
+    }
+
+    /// Callbacks too long for a button are stored, and expire
+    #[test]
+    fn test_stored_callback() {
+        let mut rng = fastrand::Rng::with_seed(18);
+        let excluded = (0..300).filter(|_| rng.bool()).collect();
+        let callback = Callback::Paddling(Paddling::Get {
+            date: chrono::Local::now().date_naive().into(),
+            time_slot: false,
+            freshies: false,
+            deconflict: true,
+            refresh: false,
+            excluded: ntu_canoebot_attd::BitIndices::from_vec(excluded),
+            show_blanks: true,
+        });
+
+        let serialized = String::from(callback.clone());
+        assert!(serialized.len() <= store::CALLBACK_DATA_LIMIT);
+        assert!(serialized.starts_with(store::KEY_PREFIX));
+        assert_eq!(Callback::try_from(serialized).unwrap(), callback);
+
+        let missing = format!("{}AAAAAAAAAAA", store::KEY_PREFIX);
+        let err = Callback::try_from(missing).unwrap_err();
+        assert!(err.is::<store::Expired>());
+    }
+
+    /// Excluding people past the first 64 still fits in callback data
+    #[test]
+    fn test_paddling_exclusions_callback() {
+        let get = |excluded: Vec<usize>| {
+            Callback::Paddling(Paddling::Get {
+                date: chrono::Local::now().date_naive().into(),
+                time_slot: true,
+                freshies: true,
+                deconflict: true,
+                refresh: false,
+                excluded: ntu_canoebot_attd::BitIndices::from_vec(excluded),
+                show_blanks: true,
+            })
+        };
+
+        for excluded in [vec![], vec![3, 70, 120], (0..150).collect()] {
+            let callback = get(excluded);
+            let serialized = String::from(callback.clone());
+
+            assert!(serialized.len() <= 64);
+            assert_eq!(Callback::try_from(serialized).unwrap(), callback);
+        }
+
+        // about 200 names with random exclusions does not compress into a button
+        let mut store = store::CALLBACK_STORE.lock().unwrap();
+        if store.is_none() {
+            *store = Some(store::Store::new(100, chrono::Duration::days(1)));
+        }
+        drop(store);
+
+        let mut rng = fastrand::Rng::with_seed(200);
+        let excluded: Vec<usize> = (0..200).filter(|_| rng.bool()).collect();
+        let callback = get(excluded);
+        let serialized = String::from(callback.clone());
+        // kept in the store, the button only has the key
+        assert!(serialized.starts_with(store::KEY_PREFIX));
+        assert!(serialized.len() <= store::CALLBACK_DATA_LIMIT);
+        assert_eq!(Callback::try_from(serialized).unwrap(), callback);
+    }
 }
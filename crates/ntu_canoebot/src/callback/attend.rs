@@ -0,0 +1,284 @@
+//! Self-service attendance marking for /attend
+
+use std::error::Error;
+
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate};
+use ntu_canoebot_attd::SheetError;
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, ParseMode},
+};
+
+use crate::error_report;
+use crate::frame::{calendar_month_gen, calendar_year_gen, date_am_pm_navigation};
+use crate::registry::linked_name;
+
+use super::{
+    message_from_callback_query, replace_with_whitespace, reply_sheet_error, Callback, Date,
+    HandleCallback,
+};
+
+/// Callbacks for /attend
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Attend {
+    /// Show the namelist and attendance of the user for a session
+    Get {
+        date: Date,
+        time_slot: bool,
+        refresh: bool,
+    },
+
+    /// Mark or unmark the user for a session
+    Toggle {
+        date: Date,
+        time_slot: bool,
+        attending: bool,
+    },
+
+    MonthSelect {
+        date: Date,
+    },
+
+    YearSelect {
+        date: Date,
+    },
+}
+
+#[async_trait]
+impl HandleCallback for Attend {
+    async fn handle_callback(
+        &self,
+        bot: Bot,
+        query: CallbackQuery,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let msg = message_from_callback_query(&query)?;
+        // the user pressing the button, not the sender of the message
+        let user = &query.from;
+
+        match self {
+            Attend::Get {
+                date,
+                time_slot,
+                refresh,
+            } => {
+                replace_with_whitespace(bot.clone(), msg, 3).await?;
+                attend_get(*date, *time_slot, *refresh, user, bot, msg, true).await?
+            }
+            Attend::Toggle {
+                date,
+                time_slot,
+                attending,
+            } => {
+                let date_naive: NaiveDate = (*date).into();
+
+                let name = match linked_name(user, date_naive).await {
+                    Some(name) => name,
+                    None => {
+                        return attend_get(*date, *time_slot, false, user, bot, msg, true).await
+                    }
+                };
+
+                // closed sessions are shown as-is, without changes
+                if !ntu_canoebot_attd::session_closed(date_naive, *time_slot).await {
+                    replace_with_whitespace(bot.clone(), msg, 3).await?;
+
+                    if let Err(e) = ntu_canoebot_attd::mark_attendance(
+                        &name, date_naive, *time_slot, *attending,
+                    )
+                    .await
+                    {
+                        bot.edit_message_text(
+                            msg.chat.id,
+                            msg.id,
+                            format!("Unable to update attendance: {}", e),
+                        )
+                        .await?;
+                        return match e {
+                            SheetError::SheetNotFound(_) => Err(error_report::answered(e)),
+                            _ => Err(e.into()),
+                        };
+                    }
+                }
+
+                attend_get(*date, *time_slot, false, user, bot, msg, true).await?
+            }
+            Attend::MonthSelect { date } => {
+                let start = NaiveDate::from_ymd_opt(date.year, date.month, 1).unwrap();
+                let days: Vec<Callback> = (0..31)
+                    .map(|d| {
+                        Callback::Attend(Attend::Get {
+                            date: (start + Duration::days(d as i64)).into(),
+                            time_slot: false,
+                            refresh: false,
+                        })
+                    })
+                    .collect();
+
+                let year = Callback::Attend(Attend::YearSelect { date: *date });
+                let prev = Callback::Attend(Attend::MonthSelect {
+                    date: (start - Duration::days(1)).into(),
+                });
+                let next = Callback::Attend(Attend::MonthSelect {
+                    date: (start + Duration::days(33)).into(),
+                });
+
+                let keyboard = calendar_month_gen((*date).into(), &days, year, next, prev, None);
+
+                bot.edit_message_text(msg.chat.id, msg.id, "attend")
+                    .reply_markup(keyboard)
+                    .await?;
+            }
+            Attend::YearSelect { date } => {
+                let months: Vec<Callback> = (0..12)
+                    .map(|m| {
+                        Callback::Attend(Attend::MonthSelect {
+                            date: Date {
+                                year: date.year,
+                                month: 1 + m,
+                                day: 1,
+                            },
+                        })
+                    })
+                    .collect();
+
+                let next = Callback::Attend(Attend::YearSelect {
+                    date: Date {
+                        year: date.year + 1,
+                        month: 1,
+                        day: 1,
+                    },
+                });
+                let prev = Callback::Attend(Attend::YearSelect {
+                    date: Date {
+                        year: date.year - 1,
+                        month: 1,
+                        day: 1,
+                    },
+                });
+
+                let keyboard = calendar_year_gen((*date).into(), &months, next, prev, None);
+
+                bot.edit_message_text(msg.chat.id, msg.id, "attend")
+                    .reply_markup(keyboard)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Show the namelist for a session, along with buttons to mark the user's attendance.
+pub async fn attend_get(
+    date: Date,
+    time_slot: bool,
+    refresh: bool,
+    user: &teloxide::types::User,
+    bot: Bot,
+    msg: &Message,
+    is_callback: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let date: NaiveDate = date.into();
+
+    let name = match linked_name(user, date).await {
+        Some(name) => name,
+        None => {
+            let text = "You are not linked to anyone in the attendance sheet yet. Use /start <your name> to link your account.";
+            match is_callback {
+                true => bot.edit_message_text(msg.chat.id, msg.id, text).await?,
+                false => bot.send_message(msg.chat.id, text).await?,
+            };
+            return Ok(());
+        }
+    };
+
+    if refresh {
+        if let Err(e) = ntu_canoebot_attd::refresh_attd_sheet_cache(true).await {
+            return reply_sheet_error(bot, msg, is_callback, e).await;
+        }
+    }
+
+    let list = match ntu_canoebot_attd::namelist(date, time_slot, false).await {
+        Ok(list) => list.unwrap_or(ntu_canoebot_attd::NameList::from_date_time(date, time_slot)),
+        Err(e) => return reply_sheet_error(bot, msg, is_callback, e).await,
+    };
+
+    // names in the namelist are shortened
+    let name_short = ntu_canoebot_attd::short_name(&name, date).await;
+    let attending = list.names.contains(&name_short);
+    let closed = ntu_canoebot_attd::session_closed(date, time_slot).await;
+
+    let status = match (attending, closed) {
+        (true, false) => "coming",
+        (false, false) => "not coming",
+        (true, true) => "coming (closed)",
+        (false, true) => "not coming (closed)",
+    };
+
+    let toggle = match (attending, closed) {
+        (_, true) => InlineKeyboardButton::callback("closed", Callback::Empty),
+        (true, false) => InlineKeyboardButton::callback(
+            "not coming",
+            Callback::Attend(Attend::Toggle {
+                date: date.into(),
+                time_slot,
+                attending: false,
+            }),
+        ),
+        (false, false) => InlineKeyboardButton::callback(
+            "coming",
+            Callback::Attend(Attend::Toggle {
+                date: date.into(),
+                time_slot,
+                attending: true,
+            }),
+        ),
+    };
+
+    // generate keyboard
+    let prev = Callback::Attend(Attend::Get {
+        date: (date - Duration::days(1)).into(),
+        time_slot,
+        refresh: false,
+    });
+    let next = Callback::Attend(Attend::Get {
+        date: (date + Duration::days(1)).into(),
+        time_slot,
+        refresh: false,
+    });
+    let refresh = Callback::Attend(Attend::Get {
+        date: date.into(),
+        time_slot,
+        refresh: true,
+    });
+    let time = Callback::Attend(Attend::Get {
+        date: date.into(),
+        time_slot: !time_slot,
+        refresh: false,
+    });
+    let calendar = Callback::Attend(Attend::MonthSelect { date: date.into() });
+
+    let mut keyboard = date_am_pm_navigation(date, refresh, next, prev, time, calendar, !time_slot);
+    keyboard.inline_keyboard.insert(0, vec![toggle]);
+
+    let contents = format!("```\n{}\n{}: {}```", list, name_short, status);
+
+    match is_callback {
+        true => {
+            bot.edit_message_text(msg.chat.id, msg.id, contents)
+                .reply_markup(keyboard)
+                .parse_mode(ParseMode::MarkdownV2)
+                .await?;
+        }
+        false => {
+            bot.send_message(msg.chat.id, contents)
+                .reply_markup(keyboard)
+                .parse_mode(ParseMode::MarkdownV2)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -12,7 +12,10 @@ use crate::frame::{
     calendar_month_gen, calendar_year_gen, common_buttons::BLANK, date_am_pm_navigation,
 };
 
-use super::{message_from_callback_query, replace_with_whitespace, Callback, Date, HandleCallback};
+use super::{
+    message_from_callback_query, replace_with_whitespace, reply_sheet_error, Callback, Date,
+    HandleCallback,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Breakdown {
@@ -115,12 +118,15 @@ pub async fn breakdown_get(
     is_callback: bool,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     if refresh {
-        ntu_canoebot_attd::refresh_attd_sheet_cache(true)
-            .await
-            .unwrap();
+        if let Err(e) = ntu_canoebot_attd::refresh_attd_sheet_cache(true).await {
+            return reply_sheet_error(bot, msg, is_callback, e).await;
+        }
     }
 
-    let bd = ntu_canoebot_attd::breakdown(date, time_slot).await;
+    let bd = match ntu_canoebot_attd::breakdown(date, time_slot).await {
+        Ok(bd) => bd,
+        Err(e) => return reply_sheet_error(bot, msg, is_callback, e).await,
+    };
 
     let prev = Callback::Breakdown(Breakdown::Get {
         date: (date - Duration::days(7)).into(),
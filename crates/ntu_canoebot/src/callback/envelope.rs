@@ -0,0 +1,355 @@
+//! Versioned encoding of callback data.
+//!
+//! Callback data is a version tag followed by the deflated bincode of a
+//! [Callback], all in base64. Buttons stay in chat history long after the
+//! bot is updated, so when the layout of [Callback] changes, bump [VERSION]
+//! and keep a decoder here for the layout it replaced.
+//!
+//! Version 0 is the untagged data from before versioning, read with the
+//! layouts in [v0].
+
+use std::error::Error;
+use std::fmt::Display;
+
+use super::Callback;
+
+/// Version of the callback layout written by this bot
+pub const VERSION: u8 = 1;
+
+/// Callback data that this version of the bot cannot read
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outdated {
+    /// Command that sent the button, if known
+    pub command: Option<&'static str>,
+}
+
+impl Outdated {
+    /// Text to replace the outdated message with
+    pub fn reply(&self) -> String {
+        match self.command {
+            Some(command) => format!(
+                "this button is from an older bot version, please re-run /{}",
+                command
+            ),
+            None => {
+                "this button is from an older bot version, please run the command again".to_string()
+            }
+        }
+    }
+}
+
+impl Display for Outdated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "callback data is from an older bot version")
+    }
+}
+
+impl Error for Outdated {}
+
+/// Tag for a version.
+///
+/// The lowest 3 bits are a deflate block header with BFINAL unset and the
+/// reserved block type 0b11, which never starts untagged data.
+fn tag(version: u8) -> u8 {
+    (version << 3) | 0b110
+}
+
+/// Version in a tag, [None] for untagged data
+fn version_of(byte: u8) -> Option<u8> {
+    match byte & 0b111 == 0b110 {
+        true => Some(byte >> 3),
+        false => None,
+    }
+}
+
+/// Tag and compress a serialized [Callback]
+pub fn seal(bin: &[u8]) -> Vec<u8> {
+    let mut sealed = vec![tag(VERSION)];
+    sealed.extend(deflate::deflate_bytes(bin));
+    sealed
+}
+
+/// Decode tagged or untagged callback data
+pub fn open(bytes: &[u8]) -> Result<Callback, Outdated> {
+    let (version, deflated) = match bytes.split_first() {
+        Some((first, rest)) => match version_of(*first) {
+            Some(version) => (version, rest),
+            None => (0, bytes),
+        },
+        None => (0, bytes),
+    };
+
+    let bin = inflate::inflate_bytes(deflated).map_err(|_| Outdated { command: None })?;
+
+    let decoded = match version {
+        0 => v0::decode(&bin),
+        VERSION => bincode::deserialize::<Callback>(&bin).ok(),
+        _ => None,
+    };
+
+    decoded.ok_or(Outdated {
+        command: command_of(&bin),
+    })
+}
+
+/// Command for the outermost [Callback] variant, read from its bincode index.
+///
+/// Variants must only ever be added to the end of [Callback] for this to work
+/// across versions.
+fn command_of(bin: &[u8]) -> Option<&'static str> {
+    let command = match variant_of(bin)? {
+        1 => "namelist",
+        2 => "training",
+        3 => "paddling",
+        4 => "land",
+        5 => "weeklybreakdown",
+        6 => "logsheet",
+        7 => "ping",
+        8 => "whatactually",
+        9 => "attend",
+        10 => "start",
+        _ => return None,
+    };
+
+    Some(command)
+}
+
+/// Bincode index of the outermost [Callback] variant
+fn variant_of(bin: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(bin.get(..4)?.try_into().ok()?))
+}
+
+/// Layouts from before versioning
+mod v0 {
+    use ntu_canoebot_attd::BitIndices;
+    use serde::Deserialize;
+
+    use super::{variant_of, Callback};
+    use crate::callback::paddling::{self, FilterType};
+    use crate::callback::Date;
+
+    /// [paddling::Paddling], with a mask of the names kept
+    /// instead of the indices of names excluded
+    #[derive(Deserialize)]
+    enum Paddling {
+        Get {
+            date: Date,
+            time_slot: bool,
+            freshies: bool,
+            deconflict: bool,
+            refresh: bool,
+            excluded_fields: u64,
+            show_blanks: bool,
+        },
+        MonthSelect {
+            date: Date,
+            freshies: bool,
+        },
+        YearSelect {
+            date: Date,
+            freshies: bool,
+        },
+        ExcludeSelection {
+            date: Date,
+            time_slot: bool,
+            freshies: bool,
+            deconflict: bool,
+            refresh: bool,
+            excluded_fields: u64,
+            exclude_type: FilterType,
+        },
+    }
+
+    /// Names excluded, from a mask of the names kept.
+    ///
+    /// Names past the first 64 did not fit in the mask and were always
+    /// excluded, they are kept now.
+    fn excluded(kept: u64) -> BitIndices {
+        BitIndices::from_u64(!kept)
+    }
+
+    impl From<Paddling> for paddling::Paddling {
+        fn from(value: Paddling) -> Self {
+            match value {
+                Paddling::Get {
+                    date,
+                    time_slot,
+                    freshies,
+                    deconflict,
+                    refresh,
+                    excluded_fields,
+                    show_blanks,
+                } => paddling::Paddling::Get {
+                    date,
+                    time_slot,
+                    freshies,
+                    deconflict,
+                    refresh,
+                    excluded: excluded(excluded_fields),
+                    show_blanks,
+                },
+                Paddling::MonthSelect { date, freshies } => {
+                    paddling::Paddling::MonthSelect { date, freshies }
+                }
+                Paddling::YearSelect { date, freshies } => {
+                    paddling::Paddling::YearSelect { date, freshies }
+                }
+                Paddling::ExcludeSelection {
+                    date,
+                    time_slot,
+                    freshies,
+                    deconflict,
+                    refresh,
+                    excluded_fields,
+                    exclude_type,
+                } => paddling::Paddling::ExcludeSelection {
+                    date,
+                    time_slot,
+                    freshies,
+                    deconflict,
+                    refresh,
+                    excluded: excluded(excluded_fields),
+                    exclude_type,
+                },
+            }
+        }
+    }
+
+    /// Decode an untagged [Callback]
+    pub fn decode(bin: &[u8]) -> Option<Callback> {
+        match variant_of(bin)? {
+            3 => bincode::deserialize::<Paddling>(bin.get(4..)?)
+                .ok()
+                .map(|p| Callback::Paddling(p.into())),
+            // the other layouts have not changed
+            _ => bincode::deserialize::<Callback>(bin).ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callback::paddling::FilterType;
+    use crate::callback::ping::Ping;
+    use crate::callback::{Date, Paddling};
+    use ntu_canoebot_attd::BitIndices;
+
+    fn paddling() -> Callback {
+        Callback::Paddling(Paddling::Get {
+            date: chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap().into(),
+            time_slot: false,
+            freshies: false,
+            deconflict: true,
+            refresh: false,
+            excluded: Default::default(),
+            show_blanks: true,
+        })
+    }
+
+    #[test]
+    fn test_tags() {
+        for version in 0..32 {
+            assert_eq!(version_of(tag(version)), Some(version));
+        }
+
+        // untagged data starts with a deflate block of another type
+        let untagged = deflate::deflate_bytes(&bincode::serialize(&paddling()).unwrap());
+        assert_eq!(version_of(untagged[0]), None);
+    }
+
+    #[test]
+    fn test_open() {
+        let bin = bincode::serialize(&paddling()).unwrap();
+
+        assert_eq!(open(&seal(&bin)), Ok(paddling()));
+
+        // newer than this bot
+        let mut newer = seal(&bin);
+        newer[0] = tag(VERSION + 1);
+        assert_eq!(
+            open(&newer),
+            Err(Outdated {
+                command: Some("paddling")
+            })
+        );
+
+        // a layout that no longer decodes
+        let mut changed = bin.clone();
+        changed.truncate(6);
+        let outdated = open(&seal(&changed)).unwrap_err();
+        assert_eq!(outdated.command, Some("paddling"));
+        assert!(outdated.reply().ends_with("/paddling"));
+
+        assert_eq!(open(&[0xFF, 0xFF]), Err(Outdated { command: None }));
+    }
+
+    /// Bincode of an untagged `Paddling::Get` or `Paddling::ExcludeSelection`,
+    /// in the layout from before versioning
+    fn baseline_paddling(variant: u32, kept: u64, last: u32) -> Vec<u8> {
+        let mut bin = Vec::new();
+        bin.extend(3u32.to_le_bytes()); // Callback::Paddling
+        bin.extend(variant.to_le_bytes());
+        bin.extend(2024i32.to_le_bytes());
+        bin.extend(1u32.to_le_bytes());
+        bin.extend(31u32.to_le_bytes());
+        // time_slot, freshies, deconflict, refresh
+        bin.extend([0, 0, 1, 0]);
+        bin.extend(kept.to_le_bytes());
+        match variant {
+            // show_blanks
+            0 => bin.push(last as u8),
+            // exclude_type
+            _ => bin.extend(last.to_le_bytes()),
+        }
+        bin
+    }
+
+    #[test]
+    fn test_open_untagged() {
+        let date: Date = chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap().into();
+        let open_untagged = |bin: Vec<u8>| open(&deflate::deflate_bytes(&bin));
+
+        // everyone kept
+        let get = open_untagged(baseline_paddling(0, u64::MAX, 1));
+        assert_eq!(get, Ok(paddling()));
+
+        // names 0, 1 and 3 kept
+        let get = open_untagged(baseline_paddling(0, 0b1011, 1));
+        let expected: Vec<usize> = [2].into_iter().chain(4..64).collect();
+        assert_eq!(
+            get,
+            Ok(Callback::Paddling(Paddling::Get {
+                date,
+                time_slot: false,
+                freshies: false,
+                deconflict: true,
+                refresh: false,
+                excluded: BitIndices::from_vec(expected),
+                show_blanks: true,
+            }))
+        );
+
+        // no one kept, with FilterType::Add
+        let select = open_untagged(baseline_paddling(3, 0, 0));
+        assert_eq!(
+            select,
+            Ok(Callback::Paddling(Paddling::ExcludeSelection {
+                date,
+                time_slot: false,
+                freshies: false,
+                deconflict: true,
+                refresh: false,
+                excluded: BitIndices::from_vec((0..64).collect()),
+                exclude_type: FilterType::Add,
+            }))
+        );
+
+        // other callbacks have the same layout
+        let ping = Callback::Ping(Ping::Next {
+            confirm: false,
+            msg_id: 1,
+        });
+        assert_eq!(open_untagged(bincode::serialize(&ping).unwrap()), Ok(ping));
+    }
+}
@@ -16,7 +16,9 @@ use crate::{
     },
 };
 
-use super::{message_from_callback_query, replace_with_whitespace, Date, HandleCallback};
+use super::{
+    message_from_callback_query, replace_with_whitespace, reply_sheet_error, Date, HandleCallback,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Land {
@@ -150,8 +152,13 @@ pub async fn land_get(
         vec![(DATE, cal)],
     ]);
 
-    let mut prog = ntu_canoebot_attd::land(date, freshies).await;
-    prog.fill_prog(true).await.unwrap();
+    let mut prog = match ntu_canoebot_attd::land(date, freshies).await {
+        Ok(prog) => prog,
+        Err(e) => return reply_sheet_error(bot, msg, is_callback, e).await,
+    };
+    if let Err(e) = prog.fill_prog(true).await {
+        return reply_sheet_error(bot, msg, is_callback, e).await;
+    }
 
     let text = format!("```\n{}```", prog);
 
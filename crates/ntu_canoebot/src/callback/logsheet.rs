@@ -6,7 +6,6 @@ use anyhow::anyhow;
 use async_trait::async_trait;
 use chrono::{NaiveDate, NaiveTime};
 use ntu_canoebot_attd::{start_end_times, SUBMIT_LOCK};
-use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use teloxide::{
     prelude::*,
@@ -17,9 +16,11 @@ use crate::frame::{
     common_buttons::{REFRESH, TIME_AM, TIME_PM},
     construct_keyboard, construct_keyboard_tuple,
 };
+use crate::registry::linked_name;
 
 use super::{
-    message_from_callback_query, replace_with_whitespace, Callback, Date, HandleCallback, Time,
+    message_from_callback_query, replace_with_whitespace, reply_sheet_error, Callback, Date,
+    HandleCallback, Time,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -82,24 +83,24 @@ impl HandleCallback for LogSheet {
                 replace_with_whitespace(bot.clone(), &msg, 2).await?;
 
                 if *refresh {
-                    ntu_canoebot_attd::refresh_attd_sheet_cache(true)
-                        .await
-                        .unwrap();
+                    if let Err(e) = ntu_canoebot_attd::refresh_attd_sheet_cache(true).await {
+                        return reply_sheet_error(bot, msg, true, e).await;
+                    }
                 }
 
                 let date_naive = (*date).into();
 
-                let name_list = ntu_canoebot_attd::namelist(date_naive, *time_slot, false)
-                    .await
-                    .unwrap_or(ntu_canoebot_attd::NameList::from_date_time(
-                        date_naive, *time_slot,
-                    ));
-
-                let freshie_name_list = ntu_canoebot_attd::namelist(date_naive, *time_slot, true)
-                    .await
-                    .unwrap_or(ntu_canoebot_attd::NameList::from_date_time(
-                        date_naive, *time_slot,
-                    ));
+                let (name_list, freshie_name_list) = match (
+                    ntu_canoebot_attd::namelist(date_naive, *time_slot, false).await,
+                    ntu_canoebot_attd::namelist(date_naive, *time_slot, true).await,
+                ) {
+                    (Ok(list), Ok(freshie_list)) => {
+                        let blank =
+                            ntu_canoebot_attd::NameList::from_date_time(date_naive, *time_slot);
+                        (list.unwrap_or(blank.clone()), freshie_list.unwrap_or(blank))
+                    }
+                    (Err(e), _) | (_, Err(e)) => return reply_sheet_error(bot, msg, true, e).await,
+                };
 
                 let num_paddlers = name_list.names.len() + freshie_name_list.names.len();
 
@@ -188,6 +189,11 @@ impl HandleCallback for LogSheet {
                 // common message header used for responses below
                 let header = format!("Logsheet: {} {}", curr, time);
 
+                // recorded in the logsheet history
+                let submitted_by = linked_name(&query.from, curr)
+                    .await
+                    .unwrap_or_else(|| query.from.full_name());
+
                 // refac in prog
                 if &curr > prev {
                     match ntu_canoebot_attd::logsheet::send(
@@ -196,13 +202,23 @@ impl HandleCallback for LogSheet {
                         start_time.and_then(|s| Some(NaiveTime::from(s))),
                         end_time.and_then(|s| Some(NaiveTime::from(s))),
                         *participants_offset,
+                        query.from.id.0,
+                        &submitted_by,
                     )
                     .await
                     {
-                        Ok(response) => {
-                            *prev = curr;
-
-                            if StatusCode::is_success(&response.status()) {
+                        Ok(submission) if submission.mock => {
+                            bot.edit_message_text(
+                                msg.chat.id,
+                                msg.id,
+                                format!("{} filled but not sent (mock)", header),
+                            )
+                            .await?;
+                        }
+                        Ok(submission) => {
+                            // failed submissions can be retried, as after a restart
+                            if submission.is_success() {
+                                *prev = curr;
                                 bot.edit_message_text(
                                     msg.chat.id,
                                     msg.id,
@@ -215,15 +231,15 @@ impl HandleCallback for LogSheet {
                                     msg.id,
                                     format!(
                                         "{} sent with error, status code: {}",
-                                        header,
-                                        response.status()
+                                        header, submission.status
                                     ),
                                 )
                                 .await?;
-                                Err(anyhow!(
+                                return Err(anyhow!(
                                     "logsheet sent but failed to submit correctly, status code {}",
-                                    response.status()
-                                ))?;
+                                    submission.status
+                                )
+                                .into());
                             }
                         }
                         Err(e) => {
@@ -233,7 +249,7 @@ impl HandleCallback for LogSheet {
                                 format!("{} unable to be sent. \nError: {}", header, e),
                             )
                             .await?;
-                            Err(anyhow!("logsheet failed before sending: {}", e))?;
+                            return Err(anyhow!("logsheet failed before sending: {}", e).into());
                         }
                     }
                 } else {
@@ -308,17 +324,17 @@ impl HandleCallback for LogSheet {
                 let button_labels = vec![start_labels, end_labels, mod_participants, vec!["✔️"]];
 
                 let date_naive = (*date).into();
-                let name_list = ntu_canoebot_attd::namelist(date_naive, *time_slot, false)
-                    .await
-                    .unwrap_or(ntu_canoebot_attd::NameList::from_date_time(
-                        date_naive, *time_slot,
-                    ));
-
-                let freshie_name_list = ntu_canoebot_attd::namelist(date_naive, *time_slot, true)
-                    .await
-                    .unwrap_or(ntu_canoebot_attd::NameList::from_date_time(
-                        date_naive, *time_slot,
-                    ));
+                let (name_list, freshie_name_list) = match (
+                    ntu_canoebot_attd::namelist(date_naive, *time_slot, false).await,
+                    ntu_canoebot_attd::namelist(date_naive, *time_slot, true).await,
+                ) {
+                    (Ok(list), Ok(freshie_list)) => {
+                        let blank =
+                            ntu_canoebot_attd::NameList::from_date_time(date_naive, *time_slot);
+                        (list.unwrap_or(blank.clone()), freshie_list.unwrap_or(blank))
+                    }
+                    (Err(e), _) | (_, Err(e)) => return reply_sheet_error(bot, msg, true, e).await,
+                };
 
                 let num_paddlers = name_list.names.len() as i32
                     + freshie_name_list.names.len() as i32
@@ -369,7 +385,7 @@ impl HandleCallback for LogSheet {
                         "form link",
                         format!(
                             "https://docs.google.com/forms/d/e/{}/viewform",
-                            ntu_canoebot_config::FORMFILLER_FORM_ID
+                            ntu_canoebot_config::settings().formfiller.form_id
                         )
                         .as_str()
                         .try_into()
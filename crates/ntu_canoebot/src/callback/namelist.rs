@@ -14,7 +14,7 @@ use crate::{
     frame::{calendar_month_gen, calendar_year_gen, common_buttons::BLANK, date_am_pm_navigation},
 };
 
-use super::{replace_with_whitespace, Callback, Date, HandleCallback};
+use super::{replace_with_whitespace, reply_sheet_error, Callback, Date, HandleCallback};
 
 /// Callbacks for /namelist
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -142,14 +142,15 @@ pub async fn namelist_get(
     let date: NaiveDate = date.into();
 
     if refresh {
-        ntu_canoebot_attd::refresh_attd_sheet_cache(true)
-            .await
-            .unwrap();
+        if let Err(e) = ntu_canoebot_attd::refresh_attd_sheet_cache(true).await {
+            return reply_sheet_error(bot, msg, is_callback, e).await;
+        }
     }
 
-    let list = ntu_canoebot_attd::namelist(date, time_slot, false)
-        .await
-        .unwrap_or(ntu_canoebot_attd::NameList::from_date_time(date, time_slot));
+    let list = match ntu_canoebot_attd::namelist(date, time_slot, false).await {
+        Ok(list) => list.unwrap_or(ntu_canoebot_attd::NameList::from_date_time(date, time_slot)),
+        Err(e) => return reply_sheet_error(bot, msg, is_callback, e).await,
+    };
 
     // generate keyboard
     let prev = Callback::NameList(NameList::Get {
@@ -0,0 +1,321 @@
+//! Onboarding callbacks for /start
+
+use std::error::Error;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+use teloxide::types::{User, UserId};
+
+use crate::events::exco_chat_id;
+use crate::frame::construct_keyboard_tuple;
+use crate::registry::{search_names, verify_handle, Refused, Verification, REGISTRY};
+
+use super::{message_from_callback_query, Callback, HandleCallback};
+
+/// Max number of search results shown
+const MAX_RESULTS: usize = 6;
+
+/// Callbacks for /start.
+///
+/// Names are referred to by their index in [ntu_canoebot_attd::names],
+/// to keep callback data small.
+/// Variants must only be added to the end, see [super::envelope].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Onboarding {
+    /// Ask the user to confirm a name
+    Select {
+        index: u16,
+    },
+
+    /// Link the user to a name, from buttons sent before [Onboarding::Link]
+    Confirm {
+        index: u16,
+    },
+
+    Cancel,
+
+    /// Link the user to the name they were asked about.
+    ///
+    /// Names without a telegram handle in the configs sheet need exco approval.
+    Link {
+        name: String,
+    },
+
+    /// Exco approval of a link
+    Approve {
+        user: u64,
+        name: String,
+    },
+
+    /// Exco rejection of a link
+    Reject {
+        user: u64,
+        name: String,
+    },
+}
+
+#[async_trait]
+impl HandleCallback for Onboarding {
+    async fn handle_callback(
+        &self,
+        bot: Bot,
+        query: CallbackQuery,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let msg = message_from_callback_query(&query)?;
+        let today = chrono::Local::now().date_naive();
+        let names = ntu_canoebot_attd::names(today).await;
+
+        let text = match self {
+            Onboarding::Select { index } => {
+                let Some(name) = names.get(*index as usize) else {
+                    bot.edit_message_text(msg.chat.id, msg.id, NAMES_CHANGED)
+                        .await?;
+                    return Ok(());
+                };
+
+                let keyboard = construct_keyboard_tuple([[
+                    (
+                        "confirm",
+                        Callback::Onboarding(Onboarding::Link { name: name.clone() }),
+                    ),
+                    ("cancel", Callback::Onboarding(Onboarding::Cancel)),
+                ]]);
+
+                bot.edit_message_text(msg.chat.id, msg.id, format!("Are you {}?", name))
+                    .reply_markup(keyboard)
+                    .await?;
+                return Ok(());
+            }
+            // the name shown may no longer be at this index
+            Onboarding::Confirm { .. } => NAMES_CHANGED.to_string(),
+            Onboarding::Cancel => "Cancelled.".to_string(),
+            Onboarding::Link { name } if !names.contains(name) => NAMES_CHANGED.to_string(),
+            Onboarding::Link { name } => request_link(&bot, &query.from, name).await?,
+            Onboarding::Approve { user, name } => {
+                let text = match link_user(UserId(*user), None, name).await? {
+                    Ok(()) => format!("You are now linked to {}.", name),
+                    Err(refused) => refused,
+                };
+                notify(&bot, *user, &text).await;
+
+                format!(
+                    "{} approved linking user {} to {}: {}",
+                    query.from.full_name(),
+                    user,
+                    name,
+                    text
+                )
+            }
+            Onboarding::Reject { user, name } => {
+                notify(
+                    &bot,
+                    *user,
+                    &format!("An exco did not confirm that you are {}.", name),
+                )
+                .await;
+
+                format!(
+                    "{} rejected linking user {} to {}.",
+                    query.from.full_name(),
+                    user,
+                    name
+                )
+            }
+        };
+
+        bot.edit_message_text(msg.chat.id, msg.id, text).await?;
+
+        Ok(())
+    }
+}
+
+const NAMES_CHANGED: &str = "The list of names has changed, please /start again.";
+
+/// Link a user to a name if the configs sheet has their handle for it,
+/// otherwise ask the exco to approve the link.
+/// Returns the reply to show to the user.
+async fn request_link(
+    bot: &Bot,
+    user: &User,
+    name: &str,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    if let Some(refused) = REGISTRY.read().await.refuse_link(user.id, name) {
+        return Ok(refused_text(user.id, name, refused));
+    }
+
+    let today = chrono::Local::now().date_naive();
+    let sheet_handle = ntu_canoebot_attd::telegram_of_name(name, today).await;
+
+    match verify_handle(sheet_handle.as_deref(), user.username.as_deref()) {
+        Verification::Verified => Ok(
+            match link_user(user.id, user.username.clone(), name).await? {
+                Ok(()) => format!("You are now linked to {}.", name),
+                Err(refused) => refused,
+            },
+        ),
+        Verification::Mismatch => {
+            log::warn!(
+                "user {} tried to link to {}, whose telegram handle is different",
+                user.id,
+                name
+            );
+            Ok(format!(
+                "{} has a different telegram handle in the configs sheet. Ask an exco to update it.",
+                name
+            ))
+        }
+        Verification::Unverified => {
+            let Some(exco_chat) = exco_chat_id() else {
+                return Ok(format!(
+                    "{} has no telegram handle in the configs sheet. Ask an exco to add yours.",
+                    name
+                ));
+            };
+
+            let keyboard = construct_keyboard_tuple([[
+                (
+                    "approve",
+                    Callback::Onboarding(Onboarding::Approve {
+                        user: user.id.0,
+                        name: name.to_string(),
+                    }),
+                ),
+                (
+                    "reject",
+                    Callback::Onboarding(Onboarding::Reject {
+                        user: user.id.0,
+                        name: name.to_string(),
+                    }),
+                ),
+            ]]);
+            let handle = match &user.username {
+                Some(handle) => format!(" (@{})", handle),
+                None => String::new(),
+            };
+            bot.send_message(
+                ChatId(exco_chat),
+                format!(
+                    "{}{} wants to link their account to {}.",
+                    user.full_name(),
+                    handle,
+                    name
+                ),
+            )
+            .reply_markup(keyboard)
+            .await?;
+
+            Ok(format!(
+                "{} has no telegram handle in the configs sheet, an exco has been asked to confirm it's you.",
+                name
+            ))
+        }
+    }
+}
+
+/// Link a user to a name, if neither is linked yet.
+/// Returns why the link was refused, as a reply to show to the user.
+async fn link_user(
+    id: UserId,
+    username: Option<String>,
+    name: &str,
+) -> Result<Result<(), String>, Box<dyn Error + Send + Sync>> {
+    let mut registry = REGISTRY.write().await;
+
+    if let Some(refused) = registry.refuse_link(id, name) {
+        return Ok(Err(refused_text(id, name, refused)));
+    }
+
+    registry.link(id, name, username)?;
+    log::info!("linked user {} to {}", id, name);
+
+    Ok(Ok(()))
+}
+
+fn refused_text(id: UserId, name: &str, refused: Refused) -> String {
+    match refused {
+        Refused::AlreadyLinked(linked) => format!(
+            "You are already linked to {}. Ask an exco to /unlink you first.",
+            linked
+        ),
+        Refused::NameTaken => {
+            log::warn!("user {} tried to link to {}, which is taken", id, name);
+            format!(
+                "{} is already linked to another account. Ask an exco to /unlink it.",
+                name
+            )
+        }
+    }
+}
+
+/// Message a user privately, logging any failure
+async fn notify(bot: &Bot, user: u64, text: &str) {
+    if let Err(e) = bot.send_message(ChatId(user as i64), text).await {
+        log::warn!("unable to message user {}: {}", user, e);
+    }
+}
+
+/// Start onboarding: search for the user's name in the configs sheet.
+pub async fn onboarding_start(
+    search: &str,
+    user: &User,
+    bot: Bot,
+    msg: &Message,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(linked) = REGISTRY.read().await.get(user.id) {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "You are linked to {}. Ask an exco to /unlink you if this is wrong.",
+                linked.name
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let search = search.trim();
+    if search.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "Link your telegram account to your name in the attendance sheet with:\n/start <your name>",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let names = ntu_canoebot_attd::names(chrono::Local::now().date_naive()).await;
+    let results = search_names(search, &names, MAX_RESULTS);
+
+    if results.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "No names match \"{}\". Try /start <your name> again.",
+                search
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut buttons: Vec<Vec<(String, Callback)>> = results
+        .into_iter()
+        .map(|idx| {
+            vec![(
+                names[idx].clone(),
+                Callback::Onboarding(Onboarding::Select { index: idx as u16 }),
+            )]
+        })
+        .collect();
+    buttons.push(vec![(
+        "cancel".to_string(),
+        Callback::Onboarding(Onboarding::Cancel),
+    )]);
+
+    bot.send_message(msg.chat.id, "Which one are you?")
+        .reply_markup(construct_keyboard_tuple(buttons))
+        .await?;
+
+    Ok(())
+}
@@ -1,12 +1,14 @@
+use std::collections::HashMap;
 use std::error::Error;
-use std::ops;
 
 use async_trait::async_trait;
 use chrono::{Duration, NaiveDate};
+use lazy_static::lazy_static;
 use ntu_canoebot_attd::{BitIndices, NameList};
 use ntu_canoebot_traits::{DeriveEnumParent, EnumParent};
 use serde::{Deserialize, Serialize};
 use teloxide::{prelude::*, types::ParseMode};
+use tokio::sync::Mutex;
 
 use crate::frame::{
     calendar_month_gen, calendar_year_gen,
@@ -14,7 +16,65 @@ use crate::frame::{
     construct_keyboard_tuple, convert_to_2d,
 };
 
-use super::{message_from_callback_query, replace_with_whitespace, Callback, Date, HandleCallback};
+use super::{
+    message_from_callback_query, replace_with_whitespace, reply_sheet_error, Callback, Date,
+    HandleCallback,
+};
+
+/// Crew swaps are forgotten after the session is this many days old
+const CREW_SWAP_DAYS: i64 = 7;
+
+lazy_static! {
+    /// Crew swaps made on each paddling message, by (chat id, message id)
+    static ref CREW_SWAPS: Mutex<HashMap<(i64, i32), CrewSwaps>> = Default::default();
+}
+
+/// Session that crew swaps apply to
+#[derive(Clone, Debug, PartialEq)]
+struct SwapSession {
+    date: NaiveDate,
+    time_slot: bool,
+    freshies: bool,
+    excluded: BitIndices,
+}
+
+/// Swaps made on a paddling message
+#[derive(Clone, Debug)]
+struct CrewSwaps {
+    session: SwapSession,
+    /// Pairs of people whose boats are swapped, in order
+    swaps: Vec<(String, String)>,
+    /// First person picked for the next swap
+    picked: Option<String>,
+}
+
+impl CrewSwaps {
+    fn new(session: SwapSession) -> Self {
+        Self {
+            session,
+            swaps: Vec::new(),
+            picked: None,
+        }
+    }
+
+    fn apply(&self, name_list: &mut NameList) {
+        for (a, b) in self.swaps.iter() {
+            name_list.swap_boats(a, b);
+        }
+    }
+}
+
+/// Action on the crew swap keyboard
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum CrewAction {
+    /// Show the crew swap keyboard
+    Open,
+    /// Pick someone, by their index in the namelist.
+    /// Picking 2 people swaps their boats.
+    Pick(u16),
+    /// Undo all swaps
+    Reset,
+}
 
 /// Method to filter names by
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
@@ -40,7 +100,8 @@ pub enum Paddling {
         /// perform deconflict
         deconflict: bool,
         refresh: bool,
-        excluded_fields: u64,
+        /// Indices of names left out of the boat allocation
+        excluded: BitIndices,
         /// Show blank blocks when updating the message
         show_blanks: bool,
     },
@@ -67,11 +128,21 @@ pub enum Paddling {
         deconflict: bool,
         refresh: bool,
 
-        /// To be converted to [ntu_canoebot_attd::BitIndices]
-        excluded_fields: u64,
+        excluded: BitIndices,
 
         exclude_type: FilterType,
     },
+
+    /// Swap people between boats, to change crews.
+    ///
+    /// Swaps are remembered per message, and apply to the deconflicted allocation.
+    CrewSwap {
+        date: Date,
+        time_slot: bool,
+        freshies: bool,
+        excluded: BitIndices,
+        action: CrewAction,
+    },
 }
 
 #[async_trait]
@@ -90,7 +161,7 @@ impl HandleCallback for Paddling {
                 freshies,
                 deconflict,
                 refresh,
-                excluded_fields,
+                excluded,
                 show_blanks,
             } => {
                 if *show_blanks {
@@ -103,7 +174,7 @@ impl HandleCallback for Paddling {
                     *freshies,
                     *deconflict,
                     *refresh,
-                    *excluded_fields,
+                    excluded.clone(),
                     bot,
                     msg,
                     true,
@@ -123,7 +194,7 @@ impl HandleCallback for Paddling {
                             freshies: *freshies,
                             deconflict: true,
                             refresh: false,
-                            excluded_fields: u64::MAX,
+                            excluded: Default::default(),
                             show_blanks: true,
                         })
                     })
@@ -195,24 +266,26 @@ impl HandleCallback for Paddling {
                 freshies,
                 deconflict,
                 refresh,
-                excluded_fields,
+                excluded,
                 exclude_type,
             } => {
                 let date_n = (*date).into();
-                let mut name_list = ntu_canoebot_attd::namelist(date_n, *time_slot, *freshies)
-                    .await
-                    .unwrap_or(NameList::from_date_time(date_n, *time_slot));
+                let mut name_list =
+                    match ntu_canoebot_attd::namelist(date_n, *time_slot, *freshies).await {
+                        Ok(list) => list.unwrap_or(NameList::from_date_time(date_n, *time_slot)),
+                        Err(e) => return reply_sheet_error(bot, msg, true, e).await,
+                    };
 
                 // this is the original list of ppl
                 let original_names_order = name_list.names.clone();
                 let num_names = original_names_order.len();
 
-                let excluded = BitIndices::from_u64(*excluded_fields);
-
                 name_list.exclude(excluded);
                 name_list.assign_boats(*deconflict).await;
                 if !freshies {
-                    name_list.fill_prog(false).await.unwrap();
+                    if let Err(e) = name_list.fill_prog(false).await {
+                        return reply_sheet_error(bot, msg, true, e).await;
+                    }
                 }
 
                 let mut header_buttons = vec![
@@ -225,7 +298,7 @@ impl HandleCallback for Paddling {
                                 freshies: *freshies,
                                 deconflict: *deconflict,
                                 refresh: *refresh,
-                                excluded_fields: u64::MIN,
+                                excluded: BitIndices::from_vec((0..num_names).collect()),
                                 exclude_type: FilterType::Add,
                             }),
                         ),
@@ -237,7 +310,7 @@ impl HandleCallback for Paddling {
                                 freshies: *freshies,
                                 deconflict: *deconflict,
                                 refresh: *refresh,
-                                excluded_fields: u64::MAX,
+                                excluded: Default::default(),
                                 exclude_type: FilterType::Remove,
                             }),
                         ),
@@ -250,59 +323,37 @@ impl HandleCallback for Paddling {
                             freshies: *freshies,
                             deconflict: *deconflict,
                             refresh: *refresh,
-                            excluded_fields: *excluded_fields,
+                            excluded: excluded.clone(),
                             show_blanks: false,
                         }),
                     )],
                 ];
 
-                // when adding names we are setting one bit (bitor what was calculated)
-                // when removing names we are clearing one bit (bitand the inverse of what was calculated)
-                // LHS is the original exclude indices
-                // RHS is the new computed index
-                let (indices_of_names, merge_op): (BitIndices, fn((u64, u64)) -> u64) =
-                    match exclude_type {
-                        FilterType::Add => (
-                            BitIndices::from_u64(!excluded_fields),
-                            |(left, right): (u64, u64)| -> u64 { ops::BitOr::bitor(left, right) },
-                        ),
-                        FilterType::Remove => (
-                            BitIndices::from_u64(*excluded_fields),
-                            |(left, right): (u64, u64)| -> u64 {
-                                ops::BitAnd::bitand(left, !right)
-                            },
-                        ),
-                    };
-
-                let indices_of_names = indices_of_names
-                    .to_vec()
-                    .into_iter()
-                    .filter(|idx| *idx < num_names)
-                    .collect::<Vec<_>>();
-
-                // dbg!(&indices_of_names);
-
-                // include/exclude names button construction
+                // when adding names, show the excluded names, and each button
+                // takes one name off the exclude list.
+                // when removing names, show the included names, and each button
+                // puts one name on the exclude list.
                 let name_buttons = original_names_order
                     .iter()
                     .enumerate()
-                    .filter_map(
-                        |(idx, name)| match indices_of_names.binary_search(&idx).is_ok() {
-                            true => Some((
-                                name,
-                                merge_op((*excluded_fields, BitIndices::from_index(idx).to_u64())),
-                            )),
-                            false => None,
-                        },
-                    )
-                    .map(|(name, excl)| {
+                    .filter(|(idx, _)| match exclude_type {
+                        FilterType::Add => excluded.contains(*idx),
+                        FilterType::Remove => !excluded.contains(*idx),
+                    })
+                    .map(|(idx, name)| {
+                        let mut excl = excluded.clone();
+                        match exclude_type {
+                            FilterType::Add => excl.remove(idx),
+                            FilterType::Remove => excl.insert(idx),
+                        }
+
                         let callback = Self::enum_parent(Self::ExcludeSelection {
                             date: *date,
                             time_slot: *time_slot,
                             freshies: *freshies,
                             deconflict: *deconflict,
                             refresh: *refresh,
-                            excluded_fields: excl,
+                            excluded: excl,
                             exclude_type: *exclude_type,
                         });
 
@@ -321,6 +372,121 @@ impl HandleCallback for Paddling {
                     .parse_mode(ParseMode::MarkdownV2)
                     .await?;
             }
+
+            Paddling::CrewSwap {
+                date,
+                time_slot,
+                freshies,
+                excluded,
+                action,
+            } => {
+                let date_n = (*date).into();
+                let mut name_list =
+                    match ntu_canoebot_attd::namelist(date_n, *time_slot, *freshies).await {
+                        Ok(list) => list.unwrap_or(NameList::from_date_time(date_n, *time_slot)),
+                        Err(e) => return reply_sheet_error(bot, msg, true, e).await,
+                    };
+
+                name_list.exclude(excluded);
+                name_list.assign_boats(true).await;
+                if !freshies {
+                    if let Err(e) = name_list.fill_prog(false).await {
+                        return reply_sheet_error(bot, msg, true, e).await;
+                    }
+                }
+
+                let session = SwapSession {
+                    date: date_n,
+                    time_slot: *time_slot,
+                    freshies: *freshies,
+                    excluded: excluded.clone(),
+                };
+
+                let mut lock = CREW_SWAPS.lock().await;
+                let today = chrono::Local::now().date_naive();
+                lock.retain(|_, s| today - s.session.date <= Duration::days(CREW_SWAP_DAYS));
+
+                let swaps = lock
+                    .entry((msg.chat.id.0, msg.id.0))
+                    .or_insert_with(|| CrewSwaps::new(session.clone()));
+                if swaps.session != session {
+                    *swaps = CrewSwaps::new(session);
+                }
+
+                match action {
+                    CrewAction::Open => swaps.picked = None,
+                    CrewAction::Reset => {
+                        swaps.swaps.clear();
+                        swaps.picked = None;
+                    }
+                    CrewAction::Pick(idx) => {
+                        if let Some(name) = name_list.names.get(*idx as usize) {
+                            match swaps.picked.take() {
+                                // picking the same person again cancels
+                                Some(first) if first == *name => (),
+                                Some(first) => swaps.swaps.push((first, name.clone())),
+                                None => swaps.picked = Some(name.clone()),
+                            }
+                        }
+                    }
+                }
+
+                swaps.apply(&mut name_list);
+                let picked = swaps.picked.clone();
+                drop(lock);
+
+                let crew_swap = |action: CrewAction| {
+                    Self::enum_parent(Self::CrewSwap {
+                        date: *date,
+                        time_slot: *time_slot,
+                        freshies: *freshies,
+                        excluded: excluded.clone(),
+                        action,
+                    })
+                };
+
+                let mut buttons = vec![vec![
+                    (
+                        "done".to_string(),
+                        Self::enum_parent(Self::Get {
+                            date: *date,
+                            time_slot: *time_slot,
+                            freshies: *freshies,
+                            deconflict: true,
+                            refresh: false,
+                            excluded: excluded.clone(),
+                            show_blanks: false,
+                        }),
+                    ),
+                    ("reset".to_string(), crew_swap(CrewAction::Reset)),
+                ]];
+
+                let name_buttons: Vec<(String, Callback)> = name_list
+                    .names
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, name)| {
+                        let label = match picked.as_ref() == Some(name) {
+                            true => format!("» {}", name),
+                            false => name.clone(),
+                        };
+
+                        (label, crew_swap(CrewAction::Pick(idx as u16)))
+                    })
+                    .collect();
+                buttons.extend(convert_to_2d(&name_buttons, 2));
+
+                let hint = match &picked {
+                    Some(name) => format!("pick someone to swap boats with {}", name),
+                    None => "pick 2 people to swap their boats".to_string(),
+                };
+                let text = format!("```\n{}\n\n{}```", name_list, hint);
+
+                bot.edit_message_text(msg.chat.id, msg.id, text)
+                    .reply_markup(construct_keyboard_tuple(buttons))
+                    .parse_mode(ParseMode::MarkdownV2)
+                    .await?;
+            }
         }
 
         Ok(())
@@ -334,7 +500,7 @@ pub async fn paddling_get(
     freshies: bool,
     deconflict: bool,
     refresh: bool,
-    exclude_idx: u64,
+    excluded: BitIndices,
     bot: Bot,
     msg: &Message,
     is_callback: bool,
@@ -348,13 +514,16 @@ pub async fn paddling_get(
         ];
 
         for handle in handles {
-            let _ = handle.await.unwrap();
+            if let Err(e) = handle.await.unwrap() {
+                return reply_sheet_error(bot, msg, is_callback, e).await;
+            }
         }
     }
 
     let mut name_list = match ntu_canoebot_attd::namelist(date_n, time_slot, freshies).await {
-        Some(nl) => nl,
-        None => {
+        Ok(Some(nl)) => nl,
+        Err(e) => return reply_sheet_error(bot, msg, is_callback, e).await,
+        Ok(None) => {
             log::error!(
                 "namelist date: {} time slot: {} not found, defaulting to blank",
                 date_n,
@@ -364,13 +533,31 @@ pub async fn paddling_get(
         }
     };
 
-    let excluded = BitIndices::from_u64(exclude_idx);
-
-    name_list.exclude(excluded);
-    name_list.assign_boats(deconflict).await;
+    name_list.exclude(&excluded);
+    let mut unseated = name_list.assign_boats(deconflict).await;
+
+    // crews swapped on this message
+    if is_callback && deconflict {
+        let session = SwapSession {
+            date: date_n,
+            time_slot,
+            freshies,
+            excluded: excluded.clone(),
+        };
+        let lock = CREW_SWAPS.lock().await;
+        match lock.get(&(msg.chat.id.0, msg.id.0)) {
+            Some(swaps) if swaps.session == session => {
+                swaps.apply(&mut name_list);
+                unseated = name_list.unseated().await;
+            }
+            _ => (),
+        }
+    }
     // freshies do not follow prog
     if !freshies {
-        name_list.fill_prog(false).await.unwrap();
+        if let Err(e) = name_list.fill_prog(false).await {
+            return reply_sheet_error(bot, msg, is_callback, e).await;
+        }
     }
 
     let d: Date = date.into();
@@ -380,7 +567,7 @@ pub async fn paddling_get(
         freshies,
         deconflict,
         refresh: false,
-        excluded_fields: u64::MAX,
+        excluded: Default::default(),
         show_blanks: true,
     });
     let next = Paddling::enum_parent(Paddling::Get {
@@ -389,7 +576,7 @@ pub async fn paddling_get(
         freshies,
         deconflict,
         refresh: false,
-        excluded_fields: u64::MAX,
+        excluded: Default::default(),
         show_blanks: true,
     });
 
@@ -400,7 +587,7 @@ pub async fn paddling_get(
         freshies,
         deconflict,
         refresh: true,
-        excluded_fields: exclude_idx,
+        excluded: excluded.clone(),
         show_blanks: true,
     });
     let switch = Paddling::enum_parent(Paddling::Get {
@@ -409,7 +596,7 @@ pub async fn paddling_get(
         freshies,
         deconflict: !deconflict,
         refresh: false,
-        excluded_fields: exclude_idx,
+        excluded: excluded.clone(),
         show_blanks: true,
     });
     let time = Paddling::enum_parent(Paddling::Get {
@@ -418,11 +605,37 @@ pub async fn paddling_get(
         freshies,
         deconflict,
         refresh: false,
-        excluded_fields: u64::MAX,
+        excluded: Default::default(),
         show_blanks: true,
     });
     let month = Paddling::enum_parent(Paddling::MonthSelect { date: d, freshies });
 
+    let filter = Paddling::enum_parent(Paddling::ExcludeSelection {
+        date: d,
+        time_slot,
+        freshies,
+        deconflict,
+        refresh: false,
+        excluded: excluded.clone(),
+        exclude_type: Default::default(),
+    });
+
+    // crews only apply to the deconflicted allocation
+    let mut last_row = vec![(DATE, month)];
+    if deconflict {
+        last_row.push((
+            "crews",
+            Paddling::enum_parent(Paddling::CrewSwap {
+                date: d,
+                time_slot,
+                freshies,
+                excluded: excluded.clone(),
+                action: CrewAction::Open,
+            }),
+        ));
+    }
+    last_row.push(("filter", filter));
+
     let switch_label = if deconflict { "plain" } else { "deconf" };
     let time_label = if time_slot { TIME_AM } else { TIME_PM };
 
@@ -433,24 +646,21 @@ pub async fn paddling_get(
             (FORWARD_ARROW, next),
         ],
         vec![(switch_label, switch), (time_label, time)],
-        vec![
-            (DATE, month),
-            (
-                "filter",
-                Paddling::enum_parent(Paddling::ExcludeSelection {
-                    date: d,
-                    time_slot,
-                    freshies,
-                    deconflict,
-                    refresh: false,
-                    excluded_fields: exclude_idx,
-                    exclude_type: Default::default(),
-                }),
-            ),
-        ],
+        last_row,
     ]);
 
-    let text = format!("```\n{}```", name_list);
+    let text = match unseated.is_empty() {
+        true => format!("```\n{}```", name_list),
+        false => format!(
+            "```\n{}\n\nno boat:\n{}```",
+            name_list,
+            unseated
+                .iter()
+                .map(|u| u.to_string())
+                .collect::<Vec<String>>()
+                .join("\n")
+        ),
+    };
 
     match is_callback {
         true => {
@@ -0,0 +1,345 @@
+//! Server side storage for callback data too long for a button.
+//!
+//! Telegram limits `callback_data` to 64 bytes. Longer callbacks are kept
+//! here under a short random key, and the button only carries the key.
+//!
+//! Stored callbacks can be saved to `canoebot.data_dir`, as lines of
+//! `key expiry payload` appended to a file. The file is compacted on load,
+//! and whenever it grows to twice the store capacity.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use base64::engine::GeneralPurpose;
+use base64::Engine;
+use chrono::Duration;
+use lazy_static::lazy_static;
+
+use ntu_canoebot_config as config;
+
+/// Telegram's limit on callback data, in bytes
+pub const CALLBACK_DATA_LIMIT: usize = 64;
+
+/// Marks callback data as a key to this store.
+/// Not part of the base64 alphabet used for inline callback data.
+pub const KEY_PREFIX: char = '~';
+
+/// File name of the store, inside the data dir
+const STORE_FILE: &str = "callbacks.log";
+
+const KEY_ENGINE: GeneralPurpose = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+lazy_static! {
+    /// Callbacks that do not fit in a button
+    pub static ref CALLBACK_STORE: Mutex<Option<Store>> = Mutex::new(Store::from_settings());
+}
+
+/// A stored callback is missing, either expired or dropped to make space
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expired(pub String);
+
+impl Display for Expired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stored callback {} has expired", self.0)
+    }
+}
+
+impl Error for Expired {}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    payload: Vec<u8>,
+    /// Unix timestamp
+    expires: i64,
+    /// Tick of the last insert or lookup
+    used: u64,
+}
+
+/// Least recently used store of callback payloads, with expiry
+#[derive(Debug)]
+pub struct Store {
+    entries: HashMap<String, Entry>,
+    tick: u64,
+    capacity: usize,
+    expiry: Duration,
+    /// Append only file, if persisted
+    file: Option<PathBuf>,
+    /// Lines in the file
+    lines: usize,
+}
+
+impl Store {
+    /// In-memory store
+    pub fn new(capacity: usize, expiry: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            tick: 0,
+            capacity: capacity.max(1),
+            expiry,
+            file: None,
+            lines: 0,
+        }
+    }
+
+    /// Store backed by a file, with entries from a previous run.
+    ///
+    /// Starts empty if the file does not exist yet.
+    pub fn load<P: Into<PathBuf>>(capacity: usize, expiry: Duration, path: P) -> Self {
+        let path = path.into();
+        let mut store = Self::new(capacity, expiry);
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                let now = now();
+                for line in contents.lines() {
+                    let mut parts = line.splitn(3, ' ');
+                    let (Some(key), Some(expires), Some(payload)) =
+                        (parts.next(), parts.next(), parts.next())
+                    else {
+                        continue;
+                    };
+                    let Ok(expires) = expires.parse::<i64>() else {
+                        continue;
+                    };
+
+                    if expires > now {
+                        store.put(key.to_string(), payload.as_bytes().to_vec(), expires);
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => log::error!("unable to read callback store {}: {}", path.display(), e),
+        }
+
+        store.file = Some(path);
+        if let Err(e) = store.compact() {
+            log::error!("unable to write callback store: {}", e);
+        }
+
+        store
+    }
+
+    /// Store from `canoebot.callback_store`, [None] if disabled
+    fn from_settings() -> Option<Self> {
+        let settings = config::settings();
+        let store = &settings.canoebot.callback_store;
+
+        if !store.enabled {
+            return None;
+        }
+
+        let expiry = Duration::days(store.expiry_days);
+        // tests stay out of the data dir
+        let store = match store.persist && !cfg!(test) {
+            true => Self::load(
+                store.capacity,
+                expiry,
+                Path::new(&settings.canoebot.data_dir).join(STORE_FILE),
+            ),
+            false => Self::new(store.capacity, expiry),
+        };
+
+        Some(store)
+    }
+
+    /// Store a payload, returning its key
+    pub fn insert(&mut self, payload: Vec<u8>) -> String {
+        let key = loop {
+            let key = KEY_ENGINE.encode(fastrand::u64(..).to_le_bytes());
+            if !self.entries.contains_key(&key) {
+                break key;
+            }
+        };
+        let expires = now() + self.expiry.num_seconds();
+
+        self.put(key.clone(), payload, expires);
+        if let Err(e) = self.append(&key) {
+            log::error!("unable to save stored callback: {}", e);
+        }
+
+        key
+    }
+
+    /// Payload stored under a key, if it has not expired
+    pub fn get(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.tick += 1;
+
+        let entry = self.entries.get_mut(key)?;
+        if entry.expires <= now() {
+            self.entries.remove(key);
+            return None;
+        }
+
+        entry.used = self.tick;
+        Some(entry.payload.clone())
+    }
+
+    /// Insert, dropping the least recently used entry if full
+    fn put(&mut self, key: String, payload: Vec<u8>, expires: i64) {
+        self.tick += 1;
+
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let now = now();
+            self.entries.retain(|_, entry| entry.expires > now);
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone());
+
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        let entry = Entry {
+            payload,
+            expires,
+            used: self.tick,
+        };
+        self.entries.insert(key, entry);
+    }
+
+    /// Save an entry to the file
+    fn append(&mut self, key: &str) -> std::io::Result<()> {
+        let (Some(path), Some(entry)) = (&self.file, self.entries.get(key)) else {
+            return Ok(());
+        };
+
+        if self.lines + 1 >= self.capacity * 2 {
+            return self.compact();
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(&line(key, &entry.payload, entry.expires))?;
+        self.lines += 1;
+
+        Ok(())
+    }
+
+    /// Rewrite the file with the entries in memory, through a temporary file
+    fn compact(&mut self) -> std::io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by_key(|(_, entry)| entry.used);
+        let contents: Vec<u8> = entries
+            .iter()
+            .flat_map(|(key, entry)| line(key, &entry.payload, entry.expires))
+            .collect();
+
+        let temp = path.with_extension("log.tmp");
+        std::fs::write(&temp, contents)?;
+        std::fs::rename(temp, path)?;
+        self.lines = entries.len();
+
+        Ok(())
+    }
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+fn line(key: &str, payload: &[u8], expires: i64) -> Vec<u8> {
+    let mut line = format!("{} {} ", key, expires).into_bytes();
+    line.extend_from_slice(payload);
+    line.push(b'\n');
+    line
+}
+
+/// Callback data for a payload, stored if it does not fit in a button
+pub fn shorten(payload: Vec<u8>) -> Vec<u8> {
+    if payload.len() <= CALLBACK_DATA_LIMIT {
+        return payload;
+    }
+
+    let mut lock = CALLBACK_STORE.lock().unwrap_or_else(|e| e.into_inner());
+    match lock.as_mut() {
+        Some(store) => format!("{}{}", KEY_PREFIX, store.insert(payload)).into_bytes(),
+        None => {
+            log::warn!(
+                "callback data is {} bytes, over the limit of {}",
+                payload.len(),
+                CALLBACK_DATA_LIMIT
+            );
+            payload
+        }
+    }
+}
+
+/// Payload for callback data, looked up if it is a key.
+pub fn expand(data: &[u8]) -> Result<Vec<u8>, Expired> {
+    let Some(key) = data.strip_prefix(KEY_PREFIX.to_string().as_bytes()) else {
+        return Ok(data.to_vec());
+    };
+    let key = String::from_utf8_lossy(key);
+
+    let mut lock = CALLBACK_STORE.lock().unwrap_or_else(|e| e.into_inner());
+    lock.as_mut()
+        .and_then(|store| store.get(&key))
+        .ok_or(Expired(key.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_eviction() {
+        let mut store = Store::new(2, Duration::days(1));
+
+        let a = store.insert(b"a".to_vec());
+        let b = store.insert(b"b".to_vec());
+        assert_eq!(a.len(), 11);
+        assert_ne!(a, b);
+
+        // a is now used more recently than b
+        assert_eq!(store.get(&a), Some(b"a".to_vec()));
+        let c = store.insert(b"c".to_vec());
+
+        assert_eq!(store.entries.len(), 2);
+        assert_eq!(store.get(&b), None);
+        assert_eq!(store.get(&a), Some(b"a".to_vec()));
+        assert_eq!(store.get(&c), Some(b"c".to_vec()));
+
+        let mut store = Store::new(2, Duration::days(-1));
+        let a = store.insert(b"a".to_vec());
+        assert_eq!(store.get(&a), None);
+        assert_eq!(store.entries.len(), 0);
+    }
+
+    #[test]
+    fn test_store_persistence() {
+        let dir = std::env::temp_dir().join(format!("canoebot_store_{}", fastrand::u64(..)));
+        let path = dir.join(STORE_FILE);
+
+        let mut store = Store::load(3, Duration::days(1), &path);
+        let keys: Vec<String> = (0..10)
+            .map(|i| store.insert(format!("payload {}", i).into_bytes()))
+            .collect();
+        // compacted instead of growing past twice the capacity
+        assert!(std::fs::read_to_string(&path).unwrap().lines().count() < 6);
+
+        let mut reloaded = Store::load(3, Duration::days(1), &path);
+        assert_eq!(reloaded.entries.len(), 3);
+        assert_eq!(reloaded.get(&keys[9]), Some(b"payload 9".to_vec()));
+        assert_eq!(reloaded.get(&keys[7]), Some(b"payload 7".to_vec()));
+        assert_eq!(reloaded.get(&keys[0]), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
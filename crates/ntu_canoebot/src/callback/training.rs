@@ -10,7 +10,10 @@ use crate::frame::{
     calendar_month_gen, calendar_year_gen, common_buttons::BLANK, date_am_pm_navigation,
 };
 
-use super::{message_from_callback_query, replace_with_whitespace, Callback, Date, HandleCallback};
+use super::{
+    message_from_callback_query, replace_with_whitespace, reply_sheet_error, Callback, Date,
+    HandleCallback,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Training {
@@ -130,12 +133,15 @@ pub async fn training_get(
     let cache_lock = PROG_CACHE.read().await;
     if cache_lock.contains_date(date) && refresh {
         drop(cache_lock);
-        ntu_canoebot_attd::refresh_prog_sheet_cache(true)
-            .await
-            .unwrap();
+        if let Err(e) = ntu_canoebot_attd::refresh_prog_sheet_cache(true).await {
+            return reply_sheet_error(bot, msg, is_callback, e).await;
+        }
     }
 
-    let sheet = ntu_canoebot_attd::training_prog(date).await;
+    let sheet = match ntu_canoebot_attd::training_prog(date).await {
+        Ok(sheet) => sheet,
+        Err(e) => return reply_sheet_error(bot, msg, is_callback, e).await,
+    };
     let prog = sheet
         .get_formatted_prog(date, time_slot)
         .unwrap_or("".to_string());
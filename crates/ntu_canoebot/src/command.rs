@@ -8,13 +8,14 @@
 //! to send a message, that message should be sent inside the trait method.
 
 pub mod commands;
+mod misc;
 mod silence;
 
 use std::error::Error;
-use std::time::Duration;
 
 use async_trait::async_trait;
 use chrono::NaiveDate;
+use ntu_canoebot_util::metrics::Counter;
 use ntu_canoebot_util::{debug_println, HiddenString};
 use teloxide::prelude::*;
 use teloxide::types::Me;
@@ -22,8 +23,11 @@ use teloxide::utils::command::BotCommands;
 
 use crate::callback::{self, whatactually_get, Callback};
 use crate::dictionaries;
+use crate::error_report::TaskOrigin;
 use crate::frame::common_buttons::BLANK;
 use crate::frame::{calendar_month_gen, calendar_year_gen};
+use crate::monitor::variant_name;
+use crate::permissions;
 use crate::threadmonitor::{DynResult, THREAD_WATCH};
 
 /// Main commands
@@ -36,6 +40,12 @@ pub enum Commands {
     #[command(description = "start your interaction with this bot")]
     Start(commands::Start),
 
+    #[command(description = "see who you are linked to")]
+    WhoAmI(commands::WhoAmI),
+
+    #[command(hide)]
+    Unlink(commands::Unlink),
+
     #[command(description = "bot version")]
     Version,
 
@@ -50,8 +60,8 @@ pub enum Commands {
     #[command(hide)]
     Feedback,
 
-    #[command(description = "reload boat configs")]
-    Reload,
+    #[command(description = "reload boat configs, or bot settings with /reload config")]
+    Reload(commands::Reload),
 
     // #[command(hide)]
     // Src,
@@ -77,7 +87,13 @@ pub enum Commands {
     WeeklyBreakdown,
 
     #[command(description = "send SCF logsheet")]
-    Logsheet,
+    Logsheet(commands::Logsheet),
+
+    #[command(description = "mark your attendance")]
+    Attend,
+
+    #[command(description = "upcoming scheduled events")]
+    Events,
 
     // secondary commands
     /// Logs the users chat info
@@ -173,6 +189,8 @@ impl HandleCommand for Commands {
         match &self {
             Commands::Help(cmd) => cmd.handle_command(bot, msg, me).await,
             Commands::Start(cmd) => cmd.handle_command(bot, msg, me).await,
+            Commands::WhoAmI(cmd) => cmd.handle_command(bot, msg, me).await,
+            Commands::Unlink(cmd) => cmd.handle_command(bot, msg, me).await,
             Commands::Version => {
                 let ver = env!("CARGO_PKG_VERSION");
                 let name = env!("CARGO_PKG_NAME");
@@ -194,11 +212,7 @@ impl HandleCommand for Commands {
 
             //     Ok(())
             // }
-            Commands::Reload => {
-                ntu_canoebot_attd::init().await;
-                bot.send_message(msg.chat.id, "configs updated").await?;
-                Ok(())
-            }
+            Commands::Reload(cmd) => cmd.handle_command(bot, msg, me).await,
             Commands::Namelist => {
                 callback::namelist_get(
                     (chrono::Local::now().date_naive() + chrono::Duration::days(1)).into(),
@@ -228,7 +242,7 @@ impl HandleCommand for Commands {
                     false,
                     true,
                     false,
-                    u64::MAX, // & 0b1111,
+                    Default::default(),
                     bot,
                     &msg,
                     false,
@@ -242,7 +256,7 @@ impl HandleCommand for Commands {
                     true,
                     true,
                     false,
-                    u64::MAX, // & 0b1111,
+                    Default::default(),
                     bot,
                     &msg,
                     false,
@@ -280,8 +294,28 @@ impl HandleCommand for Commands {
                 )
                 .await
             }
-            Commands::Logsheet => {
-                callback::logsheet_start(chrono::Local::now().date_naive(), bot, &msg, false).await
+            Commands::Logsheet(cmd) => cmd.handle_command(bot, msg, me).await,
+
+            Commands::Attend => {
+                let user = msg.from.clone().ok_or("message has no sender")?;
+
+                callback::attend_get(
+                    (chrono::Local::now().date_naive() + chrono::Duration::days(1)).into(),
+                    false,
+                    false,
+                    &user,
+                    bot,
+                    &msg,
+                    false,
+                )
+                .await
+            }
+
+            Commands::Events => {
+                bot.send_message(msg.chat.id, crate::scheduler::upcoming().await)
+                    .await?;
+
+                Ok(())
             }
 
             Commands::Ping => callback::ping_start(bot, &msg).await,
@@ -420,6 +454,8 @@ impl HandleCommand for Commands {
     }
 }
 
+const COMMANDS: Counter = Counter::new("canoebot_commands_total", "commands handled, by command");
+
 /// Main message handler
 ///
 /// Add or remove commands and their implementations in their respective structs
@@ -429,16 +465,28 @@ pub async fn message_handler(
     me: Me,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     /// Inner async fn
-    async fn inner_handler(bot: Bot, msg: Message, me: Me) -> DynResult {
-        if let Some(text) = msg.text() {
-            match Commands::parse(text, me.username()) {
-                Ok(cmd) => {
+    async fn inner_handler(bot: Bot, msg: Message, me: Me, cmd: Option<Commands>) -> DynResult {
+        if msg.text().is_some() {
+            match cmd {
+                Some(cmd) => {
                     log::info!("{:?}", cmd);
+
+                    if let Some(reason) = permissions::check(&cmd, msg.from.as_ref()).await {
+                        log::info!(
+                            "refused {:?} for {:?}",
+                            cmd,
+                            msg.from.as_ref().map(|u| u.id)
+                        );
+                        bot.send_message(msg.chat.id, reason).await?;
+                        return Ok(());
+                    }
+
+                    COMMANDS.increment(&[("command", &variant_name(&cmd))]);
                     cmd.handle_command(bot, msg, me).await?;
                 }
 
-                Err(_err) => {
-                    empty_command_handler(bot, msg, me).await;
+                None => {
+                    empty_command_handler(bot, msg, me).await?;
                 }
             }
         }
@@ -446,28 +494,35 @@ pub async fn message_handler(
         Ok(())
     }
 
-    let handle: tokio::task::JoinHandle<DynResult> = tokio::spawn(inner_handler(bot, msg, me));
+    let cmd = msg
+        .text()
+        .and_then(|text| Commands::parse(text, me.username()).ok());
+    let origin = TaskOrigin {
+        bot: bot.clone(),
+        name: match &cmd {
+            Some(cmd) => format!("/{}", variant_name(cmd)),
+            None => "message".to_string(),
+        },
+        chat: Some(msg.chat.id),
+    };
 
-    tokio::spawn(THREAD_WATCH.push(handle, Duration::from_secs(5)));
+    let handle: tokio::task::JoinHandle<DynResult> = tokio::spawn(inner_handler(bot, msg, me, cmd));
+
+    THREAD_WATCH.push_with(handle, origin);
 
     Ok(())
 }
 
 /// Handler for plain text messages
-async fn empty_command_handler(_bot: Bot, _msg: Message, _me: Me) {
-    log::trace!(
-        "doing nothing for command: {}",
-        _msg.text().unwrap_or(BLANK)
-    );
+async fn empty_command_handler(bot: Bot, msg: Message, _me: Me) -> DynResult {
+    log::trace!("no command in: {}", msg.text().unwrap_or(BLANK));
     log::trace!(
-        "Chat id: {}, User id: {}",
-        _msg.chat.id,
-        _msg.from.clone().unwrap().id
+        "Chat id: {}, User id: {:?}",
+        msg.chat.id,
+        msg.from.as_ref().map(|u| u.id)
     );
 
-    debug_println!("message contents: {:?}", _msg.text());
+    debug_println!("message contents: {:?}", msg.text());
 
-    // delete the unknown message sent by user
-    // tokio::time::sleep(Duration::from_millis(500)).await;
-    // _bot.delete_message(_msg.chat.id, _msg.id).await.unwrap();
+    misc::handle(&bot, &msg).await
 }
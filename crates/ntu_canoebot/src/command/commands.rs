@@ -9,7 +9,10 @@ use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, Me};
 use teloxide::utils::command::BotCommands;
 
 use super::{Commands, HandleCommand};
-use crate::callback::Callback;
+use crate::callback::{self, Callback};
+use crate::permissions::{self, Role};
+use crate::registry::{self, REGISTRY};
+use crate::reload;
 
 /// Unit struct to carry trait implementations.
 /// This separates and simplifies writing code: each command has it's own
@@ -43,14 +46,21 @@ impl HandleCommand for Help {
 }
 
 /// The start command handles bot-user initialisation.
+///
+/// Links a telegram user to their name in the configs sheet.
 #[derive(Clone, Debug)]
-pub struct Start {}
+pub struct Start {
+    /// Name to search for
+    search: String,
+}
 
 impl FromStr for Start {
     type Err = String;
 
-    fn from_str(_s: &str) -> Result<Self, Self::Err> {
-        Ok(Start {})
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Start {
+            search: s.to_string(),
+        })
     }
 }
 
@@ -58,10 +68,232 @@ impl FromStr for Start {
 impl HandleCommand for Start {
     async fn handle_command(
         &self,
-        _bot: Bot,
-        _msg: Message,
+        bot: Bot,
+        msg: Message,
+        _me: Me,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let user = msg.from.clone().ok_or("message has no sender")?;
+
+        callback::onboarding_start(&self.search, &user, bot, &msg).await
+    }
+}
+
+/// Shows who a telegram user is linked to
+#[derive(Clone, Debug)]
+pub struct WhoAmI {}
+
+impl FromStr for WhoAmI {
+    type Err = String;
+
+    fn from_str(_s: &str) -> Result<Self, Self::Err> {
+        Ok(WhoAmI {})
+    }
+}
+
+#[async_trait]
+impl HandleCommand for WhoAmI {
+    async fn handle_command(
+        &self,
+        bot: Bot,
+        msg: Message,
+        _me: Me,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let user = msg.from.as_ref().ok_or("message has no sender")?;
+        let today = chrono::Local::now().date_naive();
+
+        let text = match registry::linked_name(user, today).await {
+            Some(name) => match permissions::user_role(user).await >= Role::Exco {
+                true => format!("You are {} (exco)", name),
+                false => format!("You are {}", name),
+            },
+            None => {
+                "You are not linked yet. Use /start <your name> to link your account.".to_string()
+            }
+        };
+
+        bot.send_message(msg.chat.id, text).await?;
+
+        Ok(())
+    }
+}
+
+/// Removes the link of a telegram user.
+///
+/// The user is given by name or `@handle`, or by replying to one of their messages.
+#[derive(Clone, Debug)]
+pub struct Unlink {
+    target: String,
+}
+
+impl FromStr for Unlink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Unlink {
+            target: s.trim().to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl HandleCommand for Unlink {
+    async fn handle_command(
+        &self,
+        bot: Bot,
+        msg: Message,
         _me: Me,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let user = msg.from.as_ref().ok_or("message has no sender")?;
+
+        let mut registry = REGISTRY.write().await;
+
+        let target = match self.target.len() {
+            0 => msg
+                .reply_to_message()
+                .and_then(|reply| reply.from.as_ref())
+                .map(|from| from.id),
+            _ => registry.find(&self.target),
+        };
+
+        let text = match target {
+            Some(id) => match registry.unlink(id)? {
+                Some(unlinked) => {
+                    log::info!("user {} unlinked from {} by {}", id, unlinked.name, user.id);
+                    format!("Unlinked {}", unlinked.name)
+                }
+                None => "That user is not linked".to_string(),
+            },
+            None => match self.target.len() {
+                0 => "Reply to a message or give a name or @handle to unlink".to_string(),
+                _ => format!("No linked user matches \"{}\"", self.target),
+            },
+        };
+
+        bot.send_message(msg.chat.id, text).await?;
+
+        Ok(())
+    }
+}
+
+/// Number of submissions shown by `/logsheet history`
+const LOGSHEET_HISTORY_LEN: usize = 10;
+
+/// Starts the logsheet menu, or with `history`,
+/// lists the most recent submissions.
+#[derive(Clone, Debug)]
+pub struct Logsheet {
+    history: bool,
+}
+
+impl FromStr for Logsheet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Logsheet {
+            history: s.trim().eq_ignore_ascii_case("history"),
+        })
+    }
+}
+
+#[async_trait]
+impl HandleCommand for Logsheet {
+    async fn handle_command(
+        &self,
+        bot: Bot,
+        msg: Message,
+        _me: Me,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.history {
+            return callback::logsheet_start(chrono::Local::now().date_naive(), bot, &msg, false)
+                .await;
+        }
+
+        let history = ntu_canoebot_attd::logsheet::history(LOGSHEET_HISTORY_LEN)?;
+
+        let text = match history.len() {
+            0 => "No logsheets have been sent yet".to_string(),
+            _ => history
+                .iter()
+                .map(|s| {
+                    format!(
+                        "{} {}, {}-{}, {} cert / {} non-cert ({:+}), {}, sent by {} at {}{}",
+                        s.date,
+                        if s.time_slot { "PM" } else { "AM" },
+                        s.start_time.format("%H%M"),
+                        s.end_time.format("%H%M"),
+                        s.certified,
+                        s.not_certified,
+                        s.participants_offset,
+                        s.particulars_name,
+                        s.submitted_by,
+                        s.submitted_at.format("%Y-%m-%d %H:%M"),
+                        match (s.mock, s.is_success()) {
+                            (true, _) => ", mock".to_string(),
+                            (false, true) => String::new(),
+                            (false, false) => format!(", status {}", s.status),
+                        }
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n\n"),
+        };
+
+        bot.send_message(msg.chat.id, text).await?;
+
+        Ok(())
+    }
+}
+
+/// Reloads the configs sheet, or with `config`, the bot settings
+#[derive(Clone, Debug)]
+pub struct Reload {
+    config: bool,
+}
+
+impl FromStr for Reload {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Reload {
+            config: s.trim().eq_ignore_ascii_case("config"),
+        })
+    }
+}
+
+#[async_trait]
+impl HandleCommand for Reload {
+    async fn handle_command(
+        &self,
+        bot: Bot,
+        msg: Message,
+        _me: Me,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.config {
+            match reload::reload_settings().await {
+                Ok(changed) => {
+                    bot.send_message(msg.chat.id, reload::summary(&changed))
+                        .await?;
+                }
+                Err(e) => {
+                    bot.send_message(msg.chat.id, format!("settings not reloaded: {}", e))
+                        .await?;
+                    Err(e)?;
+                }
+            }
+            return Ok(());
+        }
+
+        match ntu_canoebot_attd::init().await {
+            Ok(()) => {
+                bot.send_message(msg.chat.id, "configs updated").await?;
+            }
+            Err(e) => {
+                bot.send_message(msg.chat.id, format!("failed to update configs: {}", e))
+                    .await?;
+                Err(e)?;
+            }
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,237 @@
+//! Replies to plain messages.
+//!
+//! Each handler matches a message and generates a reply, and is toggled by
+//! its flag in `canoebot.misc_handlers`. Only the first matching handler
+//! replies, and it stays quiet in that chat until `misc.handlers.cooldown`
+//! has passed.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use teloxide::prelude::*;
+use teloxide::types::ReplyParameters;
+
+use ntu_canoebot_config as config;
+
+use crate::threadmonitor::DynResult;
+
+/// How a handler matches a message
+enum Matcher {
+    Regex(Regex),
+    /// The whole message, ignoring case and trailing punctuation
+    Keyword(&'static str),
+    /// Messages with at least `misc.handlers.long_message` characters
+    Long,
+}
+
+impl Matcher {
+    fn regex(pattern: &str) -> Self {
+        Self::Regex(Regex::new(pattern).expect("invalid handler pattern"))
+    }
+
+    fn matches(&self, text: &str, long_message: usize) -> bool {
+        match self {
+            Matcher::Regex(regex) => regex.is_match(text),
+            Matcher::Keyword(keyword) => text
+                .trim()
+                .trim_end_matches(|c: char| c.is_ascii_punctuation())
+                .eq_ignore_ascii_case(keyword),
+            Matcher::Long => text.chars().count() >= long_message,
+        }
+    }
+}
+
+struct Handler {
+    /// Flag in `canoebot.misc_handlers`
+    flag: &'static str,
+    matcher: Matcher,
+    /// Reply for a matching message
+    reply: fn(&str) -> String,
+}
+
+impl Handler {
+    fn new(flag: &'static str, matcher: Matcher, reply: fn(&str) -> String) -> Self {
+        Self {
+            flag,
+            matcher,
+            reply,
+        }
+    }
+}
+
+lazy_static! {
+    static ref HANDLERS: Vec<Handler> = vec![
+        Handler::new("MISC_OOGABOOGA", Matcher::Keyword("ooga"), |_| "booga".into()),
+        Handler::new("MISC_MARCOPOLO", Matcher::Keyword("marco"), |_| "polo".into()),
+        Handler::new("MISC_PINGPONG", Matcher::Keyword("ping"), |_| "pong".into()),
+        Handler::new("MISC_HELLNO", Matcher::regex(r"(?i)^hello+\W*$"), |_| {
+            "hell no".into()
+        }),
+        Handler::new("MISC_HELPNO", Matcher::regex(r"(?i)^help+\W*$"), |_| {
+            "no".into()
+        }),
+        Handler::new("MISC_69NICE", Matcher::regex(r"(^|\D)69(\D|$)"), |_| {
+            "nice".into()
+        }),
+        Handler::new("MISC_NICENICE", Matcher::Keyword("nice"), |_| "nice".into()),
+        Handler::new("MISC_OSAS", Matcher::regex(r"(?i)\bosas\b"), |_| {
+            "own self arrange own self".into()
+        }),
+        Handler::new(
+            "MISC_WHO_BOT",
+            Matcher::regex(r"(?i)\bwho('?s| is) (a |the )?bot\b"),
+            |_| "me 🤖".into()
+        ),
+        Handler::new("MISC_LONGMSG", Matcher::Long, |text| {
+            format!("tl;dr ({} characters)", text.chars().count())
+        }),
+        Handler::new(
+            "MISC_FEELDN",
+            Matcher::regex(r"(?i)\bi('m| am)? feel(ing)? (down|sad)\b"),
+            |_| "sending hugs 🫂".into()
+        ),
+        Handler::new("MISC_WOMEN", Matcher::regex(r"(?i)\bwomen\b"), |_| {
+            "women ☕".into()
+        }),
+        Handler::new("MISC_MEN", Matcher::regex(r"(?i)\bmen\b"), |_| {
+            "men 🍺".into()
+        }),
+        Handler::new("MISC_F_RESPECTS", Matcher::Keyword("f"), |_| "F".into()),
+        // one more w than the message
+        Handler::new("MISC_WWWWWH", Matcher::regex(r"(?i)^w{3,}h*$"), |text| {
+            format!("w{}", text.trim())
+        }),
+        Handler::new("MISC_AYO", Matcher::regex(r"(?i)^ayo+\b"), |_| "ayo? 🤨".into()),
+        // nothing is run, the command is never found
+        Handler::new("MISC_BASH", Matcher::regex(r"^\$ \S+"), |text| {
+            let command = text[2..].split_whitespace().next().unwrap_or_default();
+            format!("bash: {}: command not found", command)
+        }),
+    ];
+
+    /// Last reply from each handler, by chat
+    static ref COOLDOWNS: std::sync::Mutex<Cooldowns> = Default::default();
+}
+
+/// Time of the last reply from each handler in each chat
+#[derive(Debug, Default)]
+struct Cooldowns(HashMap<(ChatId, &'static str), Instant>);
+
+impl Cooldowns {
+    /// Record a reply at `now`, false if the last one was too recent
+    fn try_reply(
+        &mut self,
+        chat: ChatId,
+        flag: &'static str,
+        now: Instant,
+        cooldown: Duration,
+    ) -> bool {
+        match self.0.get(&(chat, flag)) {
+            Some(last) if now.duration_since(*last) < cooldown => false,
+            _ => {
+                self.0.insert((chat, flag), now);
+                true
+            }
+        }
+    }
+}
+
+/// First enabled handler matching a message
+fn find_handler(
+    text: &str,
+    flags: &HashMap<String, bool>,
+    long_message: usize,
+) -> Option<&'static Handler> {
+    HANDLERS
+        .iter()
+        .filter(|h| flags.get(h.flag).copied().unwrap_or(false))
+        .find(|h| h.matcher.matches(text, long_message))
+}
+
+/// Reply to a plain message, if any handler matches it
+pub async fn handle(bot: &Bot, msg: &Message) -> DynResult {
+    let Some(text) = msg.text() else {
+        return Ok(());
+    };
+
+    let settings = config::settings();
+    let options = &settings.misc.handlers;
+    let Some(handler) = find_handler(text, &settings.canoebot.misc_handlers, options.long_message)
+    else {
+        return Ok(());
+    };
+
+    let cooldown = Duration::from_secs(options.cooldown);
+    if !COOLDOWNS
+        .lock()
+        .unwrap()
+        .try_reply(msg.chat.id, handler.flag, Instant::now(), cooldown)
+    {
+        log::trace!("{} is cooling down in {}", handler.flag, msg.chat.id);
+        return Ok(());
+    }
+
+    log::info!("{} replying in {}", handler.flag, msg.chat.id);
+    bot.send_message(msg.chat.id, (handler.reply)(text))
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(text: &str, flags: &HashMap<String, bool>) -> Option<String> {
+        find_handler(text, flags, 20).map(|h| (h.reply)(text))
+    }
+
+    #[test]
+    fn test_find_handler() {
+        let mut flags: HashMap<String, bool> = HANDLERS
+            .iter()
+            .map(|h| (h.flag.to_string(), true))
+            .collect();
+
+        assert_eq!(reply("Marco!", &flags), Some("polo".to_string()));
+        assert_eq!(reply("marco polo", &flags), None);
+        assert_eq!(reply("see you at 0690", &flags), None);
+        assert_eq!(reply("i want to die", &flags), None);
+        assert_eq!(reply("room 69 pls", &flags), Some("nice".to_string()));
+        assert_eq!(reply("wwwwh", &flags), Some("wwwwwh".to_string()));
+        assert_eq!(
+            reply("$ rm -rf /", &flags),
+            Some("bash: rm: command not found".to_string())
+        );
+        assert_eq!(
+            reply("a very long message indeed", &flags),
+            Some("tl;dr (26 characters)".to_string())
+        );
+        assert_eq!(reply("nothing here", &flags), None);
+
+        // disabled or missing flags
+        flags.insert("MISC_MARCOPOLO".to_string(), false);
+        flags.remove("MISC_PINGPONG");
+        assert_eq!(reply("marco", &flags), None);
+        assert_eq!(reply("ping", &flags), None);
+    }
+
+    #[test]
+    fn test_cooldowns() {
+        let mut cooldowns = Cooldowns::default();
+        let cooldown = Duration::from_secs(60);
+        let now = Instant::now();
+        let (group, other) = (ChatId(-100), ChatId(1));
+
+        assert!(cooldowns.try_reply(group, "MISC_AYO", now, cooldown));
+        assert!(!cooldowns.try_reply(group, "MISC_AYO", now + Duration::from_secs(59), cooldown));
+        // other chats and handlers are separate
+        assert!(cooldowns.try_reply(other, "MISC_AYO", now, cooldown));
+        assert!(cooldowns.try_reply(group, "MISC_MEN", now, cooldown));
+
+        assert!(cooldowns.try_reply(group, "MISC_AYO", now + cooldown, cooldown));
+    }
+}
@@ -29,7 +29,7 @@ use super::HandleCommand;
 lazy_static! {
     // / A PNG image representation of the laser crab.
     static ref SILENCE_CRAB: AsyncOnce<RgbaImage> = AsyncOnce::new(async {
-        let resp = reqwest::get(config::MISC_SILENCE_CRAB_URL)
+        let resp = reqwest::get(&config::settings().misc.silence.crab_url)
             .await
             .expect("failed to fetch crab template image");
 
@@ -98,8 +98,9 @@ impl HandleCommand for Silence {
                 t.hash(&mut def_hash);
                 let val = def_hash.finish();
 
-                let idx = val as usize % config::MISC_SILENCE_OFFENSIVE_NAMES.len();
-                config::MISC_SILENCE_OFFENSIVE_NAMES[idx]
+                let names = &config::settings().misc.silence.offensive_names;
+                let idx = val as usize % names.len();
+                names[idx].clone()
             };
 
             bot.send_message(
@@ -110,7 +111,7 @@ impl HandleCommand for Silence {
 
             // change the target to the person that sent the message
             user = msg.from.as_ref().unwrap();
-            silence_text = chosen_noun.to_owned();
+            silence_text = chosen_noun;
         }
 
         let img = virt_fs_cache::get_user_image_fs(bot.clone(), user.id).await?;
@@ -137,7 +138,7 @@ pub async fn cleanup_fs_cache() -> Result<(), ()> {
         }
 
         tokio::time::sleep(Duration::from_secs(
-            config::MISC_SILENCE_FS_CACHE_LIFETIME as u64 * 60,
+            config::settings().misc.silence.fs_cache_lifetime * 60,
         ))
         .await;
     }
@@ -148,10 +149,12 @@ pub async fn cleanup_fs_cache() -> Result<(), ()> {
 /// SILENCE something, or someone. Outputs a png image
 async fn silence(target: &str, profile_pic: Option<RgbaImage>) -> RgbaImage {
     let bottom = SILENCE_CRAB.get().await;
+    let settings = config::settings();
+    let text_pos = &settings.misc.silence.text;
 
     let starting_pos = {
-        let x = (bottom.width() as f64 * config::MISC_SILENCE_TEXT_X_FRAC) as u32;
-        let y = (bottom.height() as f64 * config::MISC_SILENCE_TEXT_Y_FRAC) as u32;
+        let x = (bottom.width() as f64 * text_pos.x_frac) as u32;
+        let y = (bottom.height() as f64 * text_pos.y_frac) as u32;
 
         (y, x)
     };
@@ -161,8 +164,8 @@ async fn silence(target: &str, profile_pic: Option<RgbaImage>) -> RgbaImage {
     let font_size = {
         const DEFAULT_FONT_SIZE: usize = 60;
 
-        let pixels_available = (SILENCE_CRAB.get().await.width() as f64
-            * (1_f64 - config::MISC_SILENCE_TEXT_X_FRAC)) as usize;
+        let pixels_available =
+            (SILENCE_CRAB.get().await.width() as f64 * (1_f64 - text_pos.x_frac)) as usize;
 
         let occupied_pixels = DEFAULT_FONT_SIZE * 2 / 3 * target.len();
 
@@ -211,7 +214,9 @@ fn create_png_text(text: &str, font_size: usize) -> Vec<u8> {
 fn place_profile_picture(profile_pic: &RgbaImage, crab: &RgbaImage) -> Result<RgbaImage, ()> {
     debug_println!("placing profile pic");
 
-    let new_dims = (config::MISC_SILENCE_PIC_WIDTH_FRAC * crab.width() as f64) as u32;
+    let pic = config::settings().misc.silence.pic.clone();
+
+    let new_dims = (pic.width_frac * crab.width() as f64) as u32;
     let profile_pic_new = image::imageops::resize(
         profile_pic,
         new_dims,
@@ -220,10 +225,8 @@ fn place_profile_picture(profile_pic: &RgbaImage, crab: &RgbaImage) -> Result<Rg
     );
 
     let top_left = {
-        let top_col_frac =
-            config::MISC_SILENCE_PIC_COL_FRAC - config::MISC_SILENCE_PIC_WIDTH_FRAC / 2.0;
-        let left_row_frac =
-            config::MISC_SILENCE_PIC_ROW_FRAC - config::MISC_SILENCE_PIC_WIDTH_FRAC / 2.0;
+        let top_col_frac = pic.col_frac - pic.width_frac / 2.0;
+        let left_row_frac = pic.row_frac - pic.width_frac / 2.0;
 
         (
             (left_row_frac * crab.height() as f64) as u32,
@@ -459,7 +462,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_template_from_url() {
-        let resp = reqwest::get(config::MISC_SILENCE_CRAB_URL).await;
+        let resp = reqwest::get(&config::settings().misc.silence.crab_url).await;
 
         let resp = if let Ok(r) = resp {
             r
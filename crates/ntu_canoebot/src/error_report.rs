@@ -0,0 +1,241 @@
+//! Forwarding errors from watched tasks to an admin chat, set in `canoebot.error_reports`.
+//!
+//! The same error from the same command is only forwarded once per cooldown,
+//! and at most `max_per_hour` are forwarded in an hour. Every error is counted
+//! for the digest, sent by the `error_digest` event.
+//!
+//! Errors the user has already been told about are marked [Answered], and
+//! are only counted.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use teloxide::prelude::*;
+
+use ntu_canoebot_config as config;
+
+use crate::threadmonitor::DynError;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+    static ref REPORTS: std::sync::Mutex<Reports> = Default::default();
+}
+
+/// Where a watched task came from
+#[derive(Clone, Debug)]
+pub struct TaskOrigin {
+    pub bot: Bot,
+    /// Command or callback, e.g. `/paddling`
+    pub name: String,
+    /// Chat the task was started from
+    pub chat: Option<ChatId>,
+}
+
+/// An error the user has already been told about
+#[derive(Debug)]
+pub struct Answered(pub DynError);
+
+impl Display for Answered {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for Answered {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+/// Mark an error as [Answered]
+pub fn answered<E: Into<DynError>>(error: E) -> DynError {
+    Box::new(Answered(error.into()))
+}
+
+#[derive(Debug, Default)]
+struct Reports {
+    /// Last time each error was forwarded, by origin
+    last_sent: HashMap<(String, String), Instant>,
+    /// Times errors were forwarded in the last hour
+    sent: VecDeque<Instant>,
+    /// Errors since the last digest, by origin
+    counts: BTreeMap<String, usize>,
+    /// Errors since the last digest that were not forwarded
+    suppressed: usize,
+}
+
+impl Reports {
+    /// Count an error at `now`, true if it should be forwarded
+    fn record(
+        &mut self,
+        origin: &str,
+        error: &str,
+        now: Instant,
+        cooldown: Duration,
+        max_per_hour: usize,
+    ) -> bool {
+        self.count(origin);
+
+        self.last_sent
+            .retain(|_, last| now.duration_since(*last) < cooldown);
+        while self
+            .sent
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= HOUR)
+        {
+            self.sent.pop_front();
+        }
+
+        let key = (origin.to_string(), error.to_string());
+        if self.last_sent.contains_key(&key) || self.sent.len() >= max_per_hour {
+            self.suppressed += 1;
+            return false;
+        }
+
+        self.last_sent.insert(key, now);
+        self.sent.push_back(now);
+        true
+    }
+
+    /// Count an error without forwarding it
+    fn count(&mut self, origin: &str) {
+        *self.counts.entry(origin.to_string()).or_default() += 1;
+    }
+
+    /// Error counts since the last digest, which are then reset
+    fn digest(&mut self) -> Option<String> {
+        if self.counts.is_empty() {
+            return None;
+        }
+
+        let total: usize = self.counts.values().sum();
+        let mut lines = vec![format!("{} error(s) since the last digest:", total)];
+        lines.extend(
+            self.counts
+                .iter()
+                .map(|(origin, count)| format!("{}: {}", origin, count)),
+        );
+        if self.suppressed > 0 {
+            lines.push(format!("({} not forwarded)", self.suppressed));
+        }
+
+        self.counts.clear();
+        self.suppressed = 0;
+        Some(lines.join("\n"))
+    }
+}
+
+/// Tell the user that their task failed, and forward the error to the admin chat.
+///
+/// [Answered] errors are only counted.
+pub async fn report(origin: TaskOrigin, error: DynError) {
+    let answered = error.downcast_ref::<Answered>().is_some();
+    let settings = config::settings();
+    let options = &settings.canoebot.error_reports;
+
+    if let (false, Some(chat)) = (answered, origin.chat) {
+        let text = match options.enabled {
+            true => "Something went wrong, the admins have been notified.",
+            false => "Something went wrong.",
+        };
+        if let Err(e) = origin.bot.send_message(chat, text).await {
+            log::error!("unable to tell {} about an error: {}", chat, e);
+        }
+    }
+
+    if !options.enabled {
+        return;
+    }
+
+    let error = error.to_string();
+    let forward = {
+        let mut reports = REPORTS.lock().unwrap();
+        match answered {
+            true => {
+                reports.count(&origin.name);
+                false
+            }
+            false => reports.record(
+                &origin.name,
+                &error,
+                Instant::now(),
+                Duration::from_secs(options.cooldown),
+                options.max_per_hour,
+            ),
+        }
+    };
+    if !forward {
+        return;
+    }
+    let Some(admin_chat) = settings.canoebot.chat_id(&options.chat) else {
+        log::warn!("error report chat \"{}\" is not set", options.chat);
+        return;
+    };
+
+    let from = match origin.chat {
+        Some(chat) => format!(" in chat {}", chat),
+        None => String::new(),
+    };
+    let text = format!("Error from {}{}:\n{}", origin.name, from, error);
+    if let Err(e) = origin.bot.send_message(ChatId(admin_chat), text).await {
+        log::error!("unable to forward error report: {}", e);
+    }
+}
+
+/// Error counts since the last digest, if there were any
+pub fn digest() -> Option<String> {
+    REPORTS.lock().unwrap().digest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut reports = Reports::default();
+        let cooldown = Duration::from_secs(600);
+        let now = Instant::now();
+        let later = |secs| now + Duration::from_secs(secs);
+
+        assert!(reports.record("/paddling", "sheet not found", now, cooldown, 3));
+        // repeated within the cooldown
+        assert!(!reports.record("/paddling", "sheet not found", later(599), cooldown, 3));
+        // other errors and origins are separate
+        assert!(reports.record("/paddling", "timed out", later(1), cooldown, 3));
+        assert!(reports.record("attd button", "sheet not found", later(2), cooldown, 3));
+        // over the hourly limit
+        assert!(!reports.record("/land", "timed out", later(3), cooldown, 3));
+
+        assert!(reports.record("/paddling", "sheet not found", later(3600), cooldown, 3));
+    }
+
+    #[test]
+    fn test_digest() {
+        let mut reports = Reports::default();
+        let cooldown = Duration::from_secs(600);
+        let now = Instant::now();
+
+        assert_eq!(reports.digest(), None);
+
+        reports.record("/paddling", "sheet not found", now, cooldown, 10);
+        reports.record("/paddling", "sheet not found", now, cooldown, 10);
+        reports.record("attd button", "timed out", now, cooldown, 10);
+        // answered errors are not counted as suppressed
+        reports.count("/land");
+
+        assert_eq!(
+            reports.digest().unwrap(),
+            "4 error(s) since the last digest:\n\
+            /land: 1\n\
+            /paddling: 2\n\
+            attd button: 1\n\
+            (1 not forwarded)"
+        );
+        assert_eq!(reports.digest(), None);
+    }
+}
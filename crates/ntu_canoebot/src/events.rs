@@ -3,24 +3,21 @@
 use std::error::Error;
 
 use chrono::Duration;
-use lazy_static::lazy_static;
 use teloxide::prelude::*;
 use teloxide::types::{Chat, ChatFullInfo, Message, MessageId};
 
+use config::EventAction;
 use ntu_canoebot_config as config;
 
 use crate::callback::Callback;
 use crate::frame::construct_keyboard_tuple;
 
-lazy_static! {
-    /// The exco chat id, parsed as an option
-    pub static ref EXCO_CHAT_ID: Option<i64> = {
-        if config::CANOEBOT_EXCO_CHAT == 0 {
-            None
-        } else {
-            Some(config::CANOEBOT_EXCO_CHAT)
-        }
-    };
+/// The exco chat id, parsed as an option
+pub fn exco_chat_id() -> Option<i64> {
+    match config::settings().canoebot.exco_chat {
+        0 => None,
+        id => Some(id),
+    }
 }
 
 /// The only thing that's valid here is the `chat.id`.
@@ -63,75 +60,102 @@ fn message_from_chat_id(chat_id: i64) -> Message {
     }
 }
 
-/// Send the logsheet to exco chat
-pub async fn logsheet_prompt(bot: Bot) -> Result<(), Box<dyn Error + Send + Sync>> {
-    if let Some(chat_id) = *EXCO_CHAT_ID {
-        log::info!("logsheet prompt");
-
-        let read_lock = ntu_canoebot_attd::logsheet::SUBMIT_LOCK.read().await;
-        if read_lock.0 >= chrono::Local::now().date_naive() {
-            log::info!("logsheet sent before event");
-            return Ok(());
+/// Run an event's action, sending to a chat
+pub async fn run_action(
+    bot: Bot,
+    chat_id: ChatId,
+    action: EventAction,
+    text: String,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match action {
+        EventAction::PaddlingPrompt => attendance_prompt(bot, chat_id).await,
+        EventAction::Breakdown => breakdown_prompt(bot, chat_id).await,
+        EventAction::LogsheetPrompt => logsheet_prompt(bot, chat_id).await,
+        EventAction::ErrorDigest => {
+            if let Some(digest) = crate::error_report::digest() {
+                bot.send_message(chat_id, digest).await?;
+            }
+            Ok(())
         }
+        EventAction::Message => {
+            bot.send_message(chat_id, text).await?;
+            Ok(())
+        }
+    }
+}
 
-        let now = chrono::Local::now().date_naive();
-        let keyboard = construct_keyboard_tuple([[(
-            "logsheet",
-            Callback::LogSheet(crate::callback::LogSheet::Start { date: now.into() }),
-        )]]);
-
-        bot.send_message(ChatId(chat_id), "logsheet")
-            .reply_markup(keyboard)
-            .await?;
+/// Send the logsheet to a chat
+pub async fn logsheet_prompt(
+    bot: Bot,
+    chat_id: ChatId,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    log::info!("logsheet prompt");
+
+    let read_lock = ntu_canoebot_attd::logsheet::SUBMIT_LOCK.read().await;
+    if read_lock.0 >= chrono::Local::now().date_naive() {
+        log::info!("logsheet sent before event");
+        return Ok(());
     }
 
+    let now = chrono::Local::now().date_naive();
+    let keyboard = construct_keyboard_tuple([[(
+        "logsheet",
+        Callback::LogSheet(crate::callback::LogSheet::Start { date: now.into() }),
+    )]]);
+
+    bot.send_message(chat_id, "logsheet")
+        .reply_markup(keyboard)
+        .await?;
+
     Ok(())
 }
 
-pub async fn attendance_prompt(bot: Bot) -> Result<(), Box<dyn Error + Send + Sync>> {
-    if let Some(chat_id) = *EXCO_CHAT_ID {
-        log::info!("attendance prompt");
-
-        let now = chrono::Local::now().date_naive();
-        let keyboard = construct_keyboard_tuple([[(
-            "paddling",
-            Callback::Paddling(crate::callback::Paddling::Get {
-                date: (now + Duration::days(1)).into(),
-                time_slot: false,
-                freshies: false,
-                deconflict: true,
-                refresh: false,
-                excluded_fields: u64::MAX,
-                show_blanks: true,
-            }),
-        )]]);
-
-        bot.send_message(ChatId(chat_id), "paddling")
-            .reply_markup(keyboard)
-            .await?;
-    }
+pub async fn attendance_prompt(
+    bot: Bot,
+    chat_id: ChatId,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    log::info!("attendance prompt");
+
+    let now = chrono::Local::now().date_naive();
+    let keyboard = construct_keyboard_tuple([[(
+        "paddling",
+        Callback::Paddling(crate::callback::Paddling::Get {
+            date: (now + Duration::days(1)).into(),
+            time_slot: false,
+            freshies: false,
+            deconflict: true,
+            refresh: false,
+            excluded: Default::default(),
+            show_blanks: true,
+        }),
+    )]]);
+
+    bot.send_message(chat_id, "paddling")
+        .reply_markup(keyboard)
+        .await?;
 
     Ok(())
 }
 
-pub async fn breakdown_prompt(bot: Bot) -> Result<(), Box<dyn Error + Send + Sync>> {
-    if let Some(chat_id) = *EXCO_CHAT_ID {
-        log::info!("breakdown prompt");
-
-        let now = chrono::Local::now().date_naive() + Duration::days(7);
-        let keyboard = construct_keyboard_tuple([[(
-            "breakdown",
-            Callback::Breakdown(crate::callback::Breakdown::Get {
-                date: now.into(),
-                time_slot: false,
-                refresh: false,
-            }),
-        )]]);
-
-        bot.send_message(ChatId(chat_id), "breakdown")
-            .reply_markup(keyboard)
-            .await?;
-    }
+pub async fn breakdown_prompt(
+    bot: Bot,
+    chat_id: ChatId,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    log::info!("breakdown prompt");
+
+    let now = chrono::Local::now().date_naive() + Duration::days(7);
+    let keyboard = construct_keyboard_tuple([[(
+        "breakdown",
+        Callback::Breakdown(crate::callback::Breakdown::Get {
+            date: now.into(),
+            time_slot: false,
+            refresh: false,
+        }),
+    )]]);
+
+    bot.send_message(chat_id, "breakdown")
+        .reply_markup(keyboard)
+        .await?;
 
     Ok(())
 }
@@ -1,11 +1,20 @@
 mod callback;
 mod command;
 mod dictionaries;
+mod error_report;
 mod events;
 mod frame;
 mod log_writer;
+mod monitor;
+mod permissions;
+mod registry;
+mod reload;
+mod scheduler;
 mod threadmonitor;
+#[cfg(feature = "webhook")]
+mod webhook;
 
+use std::error::Error;
 use std::fs::OpenOptions;
 
 use anyhow::anyhow;
@@ -14,6 +23,7 @@ use futures::TryFutureExt;
 use lazy_static::lazy_static;
 use ntu_canoebot_util::debug_println;
 use pretty_env_logger::env_logger::fmt;
+use teloxide::dispatching::UpdateHandler;
 use teloxide::prelude::*;
 use tokio_schedule::Job;
 
@@ -25,18 +35,9 @@ use ntu_canoebot_config as config;
 
 lazy_static! {
     static ref BOT: Bot = {
-        std::env::set_var("RUST_LOG", config::LOGGER_LOG_LEVEL.to_string());
-        std::env::set_var("TELOXIDE_TOKEN", config::CANOEBOT_APIKEY.to_string());
-
-        // this variable is set only when an override file is present (debug/deploy config).
-        // we can use this to check if defaults have been overriden
-        match config::USE {
-            true => (),
-            false => {
-                log::error!("no config file specified. Bot cannot start.");
-                std::process::exit(1);
-            }
-        }
+        let settings = config::settings();
+        std::env::set_var("RUST_LOG", &settings.logger.log_level);
+        std::env::set_var("TELOXIDE_TOKEN", &settings.canoebot.apikey);
 
         Bot::from_env()
     };
@@ -44,13 +45,24 @@ lazy_static! {
 
 #[tokio::main]
 async fn main() {
+    if std::env::args().any(|arg| arg == "--check-config") {
+        check_config();
+    }
+
+    // settings are validated here, so the bot does not start with a bad config
+    if let Err(e) = config::init() {
+        eprintln!("failed to load settings: {}", e);
+        std::process::exit(1);
+    }
+    let logger = config::settings().logger.clone();
+
     pretty_env_logger::formatted_timed_builder()
-        .target(if config::LOGGER_LOG_TO_FILE {
+        .target(if logger.log_to_file {
             Target::Pipe(Box::new({
                 let log_file_dest = OpenOptions::new()
                     .create(true)
                     .append(true)
-                    .open(config::LOGGER_FILE)
+                    .open(&logger.file)
                     .expect("file creation should not fail");
 
                 LogWriter::to_file(log_file_dest)
@@ -58,21 +70,48 @@ async fn main() {
         } else {
             Target::Stderr
         })
-        .parse_filters(config::LOGGER_LOG_LEVEL)
+        .parse_filters(&logger.log_level)
         .init();
 
     tokio::task::spawn(start_events());
 
-    let handler = dptree::entry()
-        .branch(Update::filter_message().endpoint(message_handler))
-        .branch(Update::filter_callback_query().endpoint(callback_handler));
+    let monitor = config::settings().canoebot.monitor.clone();
+    if monitor.enabled {
+        tokio::task::spawn(monitor::serve(monitor.address));
+    }
 
-    log::info!("startup");
-    Dispatcher::builder(BOT.clone(), handler)
+    let mut dispatcher = Dispatcher::builder(BOT.clone(), schema())
         .enable_ctrlc_handler()
-        .build()
-        .dispatch()
-        .await;
+        .build();
+    monitor::set_dispatching(true);
+
+    #[cfg(feature = "webhook")]
+    {
+        let listener = match webhook::options() {
+            Ok(options) => webhook::listener(BOT.clone(), options).await,
+            Err(e) => Err(e),
+        };
+        let listener = listener.unwrap_or_else(|e| {
+            log::error!("failed to start webhook: {}", e);
+            std::process::exit(1);
+        });
+
+        log::info!("startup, webhook");
+        dispatcher
+            .dispatch_with_listener(
+                listener,
+                LoggingErrorHandler::with_custom_text("webhook listener failed"),
+            )
+            .await;
+    }
+
+    #[cfg(not(feature = "webhook"))]
+    {
+        log::info!("startup");
+        dispatcher.dispatch().await;
+    }
+
+    monitor::set_dispatching(false);
 
     // teloxide::repl_with_listener(
     //     bot,
@@ -86,11 +125,38 @@ async fn main() {
     // .await;
 }
 
+/// Handlers for every update, however they are received
+fn schema() -> UpdateHandler<Box<dyn Error + Send + Sync + 'static>> {
+    dptree::entry()
+        .branch(Update::filter_message().endpoint(message_handler))
+        .branch(Update::filter_callback_query().endpoint(callback_handler))
+}
+
+/// Validate settings, print any problems and exit
+fn check_config() -> ! {
+    match config::check() {
+        Ok(settings) => {
+            let source = settings
+                .source
+                .map(|path| path.display().to_string())
+                .unwrap_or_default();
+            println!("settings are valid ({})", source);
+            std::process::exit(0)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1)
+        }
+    }
+}
+
 /// Periodic tasks / init tasks go here
 async fn start_events() {
     const REFRESH_INTERVAL: u32 = 10;
 
-    ntu_canoebot_attd::init().await;
+    if let Err(e) = ntu_canoebot_attd::init().await {
+        log::error!("failed to load configs sheet: {}", e);
+    }
 
     // tokio::task::spawn(SRC_CACHE.fill_all());
     tokio::task::spawn(ntu_canoebot_attd::refresh_attd_sheet_cache(true));
@@ -108,7 +174,7 @@ async fn start_events() {
             threadmonitor::THREAD_WATCH
                 .spawn(
                     ntu_canoebot_attd::refresh_attd_sheet_cache(false)
-                        .map_err(|_| anyhow!("attd cache refresh failed").into()),
+                        .map_err(|e| anyhow!("attd cache refresh failed: {}", e).into()),
                 )
                 .await;
         });
@@ -121,99 +187,23 @@ async fn start_events() {
             threadmonitor::THREAD_WATCH
                 .spawn(
                     ntu_canoebot_attd::refresh_prog_sheet_cache(false)
-                        .map_err(|_| anyhow!("prog cache refresh failed").into()),
+                        .map_err(|e| anyhow!("prog cache refresh failed: {}", e).into()),
                 )
                 .await;
         });
 
     tokio::task::spawn(prog_cache_refresh);
 
-    debug_println!("chat_id: {:?}", *crate::events::EXCO_CHAT_ID);
-    if config::EVENTS_DAILY_LOGSHEET_PROMPT_ENABLE {
-        let prompt_time = config::EVENTS_DAILY_LOGSHEET_PROMPT_TIME.time.unwrap();
-        let logsheet_task = tokio_schedule::every(1)
-            .day()
-            .at(
-                prompt_time.hour as u32,
-                prompt_time.minute as u32,
-                prompt_time.second as u32,
-            )
-            .perform(|| async {
-                threadmonitor::THREAD_WATCH
-                    .spawn(events::logsheet_prompt(BOT.clone()))
-                    .await;
-            });
-
-        tokio::task::spawn(logsheet_task);
-    }
-
-    if config::EVENTS_DAILY_ATTENDANCE_REMINDER_ENABLE {
-        let prompt_time = config::EVENTS_DAILY_ATTENDANCE_REMINDER_TIME.time.unwrap();
-        let attendance_event = tokio_schedule::every(1)
-            .day()
-            .at(
-                prompt_time.hour as u32,
-                prompt_time.minute as u32,
-                prompt_time.second as u32,
-            )
-            .perform(|| async {
-                threadmonitor::THREAD_WATCH
-                    .spawn(events::attendance_prompt(BOT.clone()))
-                    .await;
-            });
+    tokio::task::spawn(reload::watch_settings(BOT.clone()));
 
-        tokio::task::spawn(attendance_event);
-    }
-
-    if config::EVENTS_WEEKLY_BREAKDOWN_ENABLE {
-        let prompt_time = config::EVENTS_WEEKLY_BREAKDOWN_TIME.time.unwrap();
-        let breakdown_event = tokio_schedule::every(1)
-            .week()
-            .on(chrono::Weekday::Wed)
-            .at(
-                prompt_time.hour as u32,
-                prompt_time.minute as u32,
-                prompt_time.second as u32,
-            )
-            .perform(|| async {
-                threadmonitor::THREAD_WATCH
-                    .spawn(events::breakdown_prompt(BOT.clone()))
-                    .await;
-            });
-
-        tokio::task::spawn(breakdown_event);
-    }
+    schedule_events();
 }
 
-/// This is an unused webhook version of the bot
-#[cfg(feature = "notset")]
-#[tokio::main]
-async fn main() {
-    // requires the webhook feature on teloxide
-    use teloxide::update_listeners::webhooks::{self, Options};
-
-    std::env::set_var("RUST_LOG", config::LOGGER_LOG_LEVEL.to_string());
-    std::env::set_var("TELOXIDE_TOKEN", config::CANOEBOT_APIKEY.to_string());
-
-    pretty_env_logger::init();
-    let bot = Bot::from_env();
-
-    let addr = "127.0.0.1:8443".parse().unwrap(); //([127, 0, 0, 1], 8443).into();
-    let url = "insert url here".parse().unwrap();
-    let listener = webhooks::axum(bot.clone(), webhooks::Options::new(addr, url))
-        .await
-        .expect("Couldn't setup webhook");
-
-    tokio::task::spawn(start_events());
-
-    let handler = dptree::entry()
-        .branch(Update::filter_message().endpoint(message_handler))
-        .branch(Update::filter_callback_query().endpoint(callback_handler));
-
-    log::info!("webhook startup");
-    Dispatcher::builder(bot, handler)
-        .enable_ctrlc_handler()
-        .build()
-        .dispatch()
-        .await;
+/// Schedule the events in settings.
+///
+/// Events that were scheduled before are cancelled, so this can be
+/// called again after settings change.
+fn schedule_events() {
+    debug_println!("chat_id: {:?}", crate::events::exco_chat_id());
+    scheduler::schedule(BOT.clone());
 }
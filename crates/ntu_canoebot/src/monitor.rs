@@ -0,0 +1,165 @@
+//! HTTP endpoints for health checks and metrics, enabled in `canoebot.monitor`.
+//!
+//! - `/healthz`: the dispatcher is running
+//! - `/readyz`: the configs sheet has loaded and the sheet caches are filled
+//! - `/metrics`: metrics in the prometheus text format
+
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use chrono::NaiveDateTime;
+use ntu_canoebot_util::metrics::{self, Gauge};
+use tokio::net::TcpListener;
+
+/// Set while the dispatcher is handling updates
+static DISPATCHING: AtomicBool = AtomicBool::new(false);
+
+const CACHE_AGE: Gauge = Gauge::new(
+    "canoebot_cache_age_seconds",
+    "time since a sheet cache was filled",
+);
+
+/// Record whether the dispatcher is running, for `/healthz`
+pub fn set_dispatching(running: bool) {
+    DISPATCHING.store(running, Ordering::Relaxed);
+}
+
+/// Lowercase name of an enum variant, for metric labels
+pub fn variant_name<T: Debug>(value: &T) -> String {
+    format!("{:?}", value)
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Serve the endpoints until the bot stops
+pub async fn serve(address: SocketAddr) {
+    let listener = match TcpListener::bind(address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("unable to listen on {} for monitoring: {}", address, e);
+            return;
+        }
+    };
+
+    log::info!("monitoring on {}", address);
+    if let Err(e) = axum::serve(listener, router()).await {
+        log::error!("monitoring server failed: {}", e);
+    }
+}
+
+fn router() -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(render_metrics))
+}
+
+async fn healthz() -> (StatusCode, &'static str) {
+    match DISPATCHING.load(Ordering::Relaxed) {
+        true => (StatusCode::OK, "ok"),
+        false => (StatusCode::SERVICE_UNAVAILABLE, "dispatcher is not running"),
+    }
+}
+
+/// Reasons the bot is not ready to answer commands
+fn not_ready(configs_loaded: bool, caches: &[(&str, Option<NaiveDateTime>)]) -> Vec<String> {
+    let mut reasons: Vec<String> = caches
+        .iter()
+        .filter(|(_, filled)| filled.is_none())
+        .map(|(cache, _)| format!("{} cache is empty", cache))
+        .collect();
+
+    if !configs_loaded {
+        reasons.insert(0, "configs sheet has not loaded".to_string());
+    }
+
+    reasons
+}
+
+async fn readyz() -> (StatusCode, String) {
+    let caches = ntu_canoebot_attd::cache_fetch_times().await;
+    let reasons = not_ready(ntu_canoebot_attd::configs_loaded(), &caches);
+
+    match reasons.is_empty() {
+        true => (StatusCode::OK, "ready".to_string()),
+        false => (StatusCode::SERVICE_UNAVAILABLE, reasons.join("\n")),
+    }
+}
+
+async fn render_metrics() -> impl IntoResponse {
+    let now = chrono::Local::now().naive_local();
+    for (cache, filled) in ntu_canoebot_attd::cache_fetch_times().await {
+        if let Some(filled) = filled {
+            let age = (now - filled).num_milliseconds() as f64 / 1000.0;
+            CACHE_AGE.set(&[("cache", cache)], age);
+        }
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Commands;
+
+    #[test]
+    fn test_variant_name() {
+        assert_eq!(variant_name(&Commands::WeeklyBreakdown), "weeklybreakdown");
+        assert_eq!(
+            variant_name(&Commands::What {
+                query: "kayak".into()
+            }),
+            "what"
+        );
+    }
+
+    #[test]
+    fn test_not_ready() {
+        let now = chrono::Local::now().naive_local();
+
+        assert!(not_ready(true, &[("attd", Some(now)), ("prog", Some(now))]).is_empty());
+        assert_eq!(
+            not_ready(false, &[("attd", None), ("prog", Some(now))]),
+            ["configs sheet has not loaded", "attd cache is empty"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_endpoints() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::task::spawn(async move { axum::serve(listener, router()).await });
+
+        let get = |path: &str| reqwest::get(format!("http://{}{}", address, path));
+
+        set_dispatching(true);
+        assert_eq!(get("/healthz").await.unwrap().status(), 200);
+        set_dispatching(false);
+        assert_eq!(get("/healthz").await.unwrap().status(), 503);
+
+        // nothing has loaded in tests
+        let resp = get("/readyz").await.unwrap();
+        assert_eq!(resp.status(), 503);
+        assert!(resp.text().await.unwrap().contains("configs sheet"));
+
+        CACHE_AGE.set(&[("cache", "test")], 1.5);
+        let resp = get("/metrics").await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert!(resp
+            .text()
+            .await
+            .unwrap()
+            .contains("canoebot_cache_age_seconds{cache=\"test\"} 1.5\n"));
+    }
+}
@@ -0,0 +1,235 @@
+//! Role-based access to commands and callbacks.
+//!
+//! Every [Commands] and [Callback] variant is assigned a required [Role].
+//! Matches are exhaustive, so new variants have to be classified here.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use teloxide::types::User;
+
+use crate::callback::{Callback, Onboarding, Paddling};
+use crate::command::Commands;
+use crate::registry;
+
+use ntu_canoebot_config as config;
+
+/// Roles, from least to most privileged
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Anyone, including users that are not linked
+    Guest,
+    /// Users linked to a name in the configs sheet
+    Member,
+    /// Users listed in `canoebot.exco_members`
+    Exco,
+    /// Users listed in `canoebot.admins`
+    Admin,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Role::Guest => "everyone",
+            Role::Member => "members",
+            Role::Exco => "the exco",
+            Role::Admin => "admins",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
+/// Something that can only be used by a minimum [Role]
+pub trait RequiredRole {
+    fn required_role(&self) -> Role;
+}
+
+impl RequiredRole for Commands {
+    fn required_role(&self) -> Role {
+        match self {
+            Commands::Help(_)
+            | Commands::Start(_)
+            | Commands::WhoAmI(_)
+            | Commands::Version
+            | Commands::Calendar
+            | Commands::Button(_)
+            | Commands::Feedback
+            | Commands::Namelist
+            | Commands::Training
+            | Commands::Paddling
+            | Commands::FreshiePaddling
+            | Commands::Land
+            | Commands::FreshieLand
+            | Commands::WeeklyBreakdown
+            | Commands::Ping
+            | Commands::What { .. }
+            | Commands::WhatActually { .. }
+            | Commands::EmojiVomit { .. }
+            | Commands::Uwuify { .. }
+            | Commands::Silence(_) => Role::Guest,
+
+            Commands::Attend => Role::Member,
+
+            Commands::Reload(_)
+            | Commands::Logsheet(_)
+            | Commands::Unlink(_)
+            | Commands::Events => Role::Exco,
+
+            Commands::Panic => Role::Admin,
+        }
+    }
+}
+
+impl RequiredRole for Callback {
+    fn required_role(&self) -> Role {
+        match self {
+            // changes crews for everyone viewing the message
+            Callback::Paddling(Paddling::CrewSwap { .. }) => Role::Exco,
+            // links someone without a matching telegram handle
+            Callback::Onboarding(Onboarding::Approve { .. } | Onboarding::Reject { .. }) => {
+                Role::Exco
+            }
+
+            Callback::Empty
+            | Callback::NameList(_)
+            | Callback::Training(_)
+            | Callback::Paddling(_)
+            | Callback::Land(_)
+            | Callback::Breakdown(_)
+            | Callback::Ping(_)
+            | Callback::WhatActually(_)
+            | Callback::Onboarding(_)
+            | Callback::Custom => Role::Guest,
+
+            Callback::Attend(_) => Role::Member,
+
+            // logsheets are submitted to SCF
+            Callback::LogSheet(_) => Role::Exco,
+        }
+    }
+}
+
+/// Checks if a user is in a table of telegram user ids
+fn is_listed(ids: &HashMap<String, u64>, user: &User) -> bool {
+    ids.values().any(|id| *id == user.id.0)
+}
+
+/// Role of a user from the configured ids, and whether they are linked to a name.
+///
+/// Linking to a name never grants more than [Role::Member].
+fn role_of(user: &User, canoebot: &config::Canoebot, linked: bool) -> Role {
+    if is_listed(&canoebot.admins, user) {
+        Role::Admin
+    } else if is_listed(&canoebot.exco_members, user) {
+        Role::Exco
+    } else if linked {
+        Role::Member
+    } else {
+        Role::Guest
+    }
+}
+
+/// Returns the highest role of a user
+pub async fn user_role(user: &User) -> Role {
+    let today = chrono::Local::now().date_naive();
+    let linked = registry::linked_name(user, today).await.is_some();
+
+    role_of(user, &config::settings().canoebot, linked)
+}
+
+/// Returns the reason for refusing a user, if they do not have the required role.
+pub async fn check<T: RequiredRole>(item: &T, user: Option<&User>) -> Option<String> {
+    let required = item.required_role();
+    if required == Role::Guest {
+        return None;
+    }
+
+    let role = match user {
+        Some(user) => user_role(user).await,
+        None => Role::Guest,
+    };
+
+    if role >= required {
+        return None;
+    }
+
+    let mut reason = format!("Sorry, this is only available to {}.", required);
+    if role == Role::Guest {
+        reason.push_str(" Use /start <your name> to link your account.");
+    }
+
+    Some(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callback::LogSheet;
+
+    #[test]
+    fn test_role_ordering() {
+        assert!(Role::Guest < Role::Member);
+        assert!(Role::Member < Role::Exco);
+        assert!(Role::Exco < Role::Admin);
+    }
+
+    #[test]
+    fn test_required_roles() {
+        assert_eq!(Commands::Version.required_role(), Role::Guest);
+        assert_eq!(Commands::Attend.required_role(), Role::Member);
+        assert_eq!(
+            Commands::Reload("config".parse().unwrap()).required_role(),
+            Role::Exco
+        );
+        let logsheet = Commands::Logsheet("history".parse().unwrap());
+        assert_eq!(logsheet.required_role(), Role::Exco);
+        assert_eq!(Commands::Panic.required_role(), Role::Admin);
+
+        let send = Callback::LogSheet(LogSheet::Send {
+            date: chrono::Local::now().date_naive().into(),
+            time_slot: false,
+            start_time: None,
+            end_time: None,
+            participants_offset: 0,
+        });
+        assert_eq!(send.required_role(), Role::Exco);
+        assert_eq!(Callback::Empty.required_role(), Role::Guest);
+
+        let link = |onboarding| Callback::Onboarding(onboarding).required_role();
+        let name = "Some Person".to_string();
+        assert_eq!(link(Onboarding::Link { name: name.clone() }), Role::Guest);
+        assert_eq!(link(Onboarding::Approve { user: 1, name }), Role::Exco);
+    }
+
+    #[test]
+    fn test_role_of() {
+        let user = |id: u64| User {
+            id: teloxide::types::UserId(id),
+            is_bot: false,
+            first_name: "Test".to_string(),
+            last_name: None,
+            username: None,
+            language_code: None,
+            is_premium: false,
+            added_to_attachment_menu: false,
+        };
+        let mut canoebot = config::settings().canoebot.clone();
+        canoebot.admins = HashMap::from([("admin".to_string(), 1)]);
+        canoebot.exco_members = HashMap::from([("exco".to_string(), 2)]);
+
+        assert_eq!(role_of(&user(1), &canoebot, false), Role::Admin);
+        assert_eq!(role_of(&user(2), &canoebot, false), Role::Exco);
+        // linking to an exco's name does not make someone exco
+        assert_eq!(role_of(&user(3), &canoebot, true), Role::Member);
+        assert_eq!(role_of(&user(3), &canoebot, false), Role::Guest);
+    }
+
+    #[tokio::test]
+    async fn test_check_without_user() {
+        assert!(check(&Commands::Version, None).await.is_none());
+        assert!(check(&Commands::Reload("".parse().unwrap()), None)
+            .await
+            .is_some());
+    }
+}
@@ -0,0 +1,324 @@
+//! Links telegram users to names in the configs sheet.
+//!
+//! Links are stored as a JSON file inside `canoebot.data_dir`.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{User, UserId};
+use tokio::sync::RwLock;
+
+use ntu_canoebot_config as config;
+
+/// File name of the registry, inside the data dir
+const REGISTRY_FILE: &str = "users.json";
+
+lazy_static! {
+    /// Telegram users linked to names
+    pub static ref REGISTRY: RwLock<Registry> =
+        RwLock::new(Registry::load(Path::new(&config::settings().canoebot.data_dir).join(REGISTRY_FILE)));
+}
+
+/// A telegram user linked to a name
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LinkedUser {
+    /// Full name, as it appears in the configs sheet
+    pub name: String,
+    /// Telegram handle at the time of linking
+    pub username: Option<String>,
+    pub linked_at: NaiveDateTime,
+}
+
+/// Why a user cannot link to a name
+#[derive(Clone, Debug, PartialEq)]
+pub enum Refused {
+    /// The user is already linked to this name
+    AlreadyLinked(String),
+    /// Another user is linked to the name
+    NameTaken,
+}
+
+/// Whether the configs sheet backs up a user's claim to a name
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verification {
+    /// The sheet has the user's handle for the name
+    Verified,
+    /// The sheet has another handle for the name
+    Mismatch,
+    /// The sheet has no handle for the name
+    Unverified,
+}
+
+/// Compare a user's telegram handle with the handle the configs sheet has for a name
+pub fn verify_handle(sheet_handle: Option<&str>, username: Option<&str>) -> Verification {
+    let normalize = |handle: &str| handle.trim().trim_start_matches('@').to_lowercase();
+
+    match (sheet_handle, username) {
+        (None, _) => Verification::Unverified,
+        (Some(sheet), Some(user)) if normalize(sheet) == normalize(user) => Verification::Verified,
+        (Some(_), _) => Verification::Mismatch,
+    }
+}
+
+/// Persistent store of linked users, keyed by telegram user id
+#[derive(Debug, Default)]
+pub struct Registry {
+    path: PathBuf,
+    users: HashMap<u64, LinkedUser>,
+}
+
+impl Registry {
+    /// Load the registry from a file.
+    ///
+    /// Starts empty if the file does not exist yet.
+    pub fn load<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
+
+        let users = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                log::error!("unable to parse user registry {}: {}", path.display(), e);
+                Default::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Default::default(),
+            Err(e) => {
+                log::error!("unable to read user registry {}: {}", path.display(), e);
+                Default::default()
+            }
+        };
+
+        Self { path, users }
+    }
+
+    pub fn get(&self, id: UserId) -> Option<&LinkedUser> {
+        self.users.get(&id.0)
+    }
+
+    /// Returns the user linked to a name, if any
+    pub fn user_with_name(&self, name: &str) -> Option<UserId> {
+        self.users
+            .iter()
+            .find(|(_, user)| user.name == name)
+            .map(|(id, _)| UserId(*id))
+    }
+
+    /// Reason a user may not link to a name, if any.
+    ///
+    /// Links are never replaced, a user has to be unlinked first.
+    pub fn refuse_link(&self, id: UserId, name: &str) -> Option<Refused> {
+        if let Some(linked) = self.get(id) {
+            return Some(Refused::AlreadyLinked(linked.name.clone()));
+        }
+
+        self.user_with_name(name).map(|_| Refused::NameTaken)
+    }
+
+    /// Look up a user by their name or `@handle`, ignoring case.
+    pub fn find(&self, query: &str) -> Option<UserId> {
+        let query = query.trim();
+
+        match query.strip_prefix('@') {
+            Some(handle) => self.users.iter().find(|(_, user)| {
+                user.username
+                    .as_ref()
+                    .is_some_and(|u| u.eq_ignore_ascii_case(handle))
+            }),
+            None => self
+                .users
+                .iter()
+                .find(|(_, user)| user.name.to_lowercase() == query.to_lowercase()),
+        }
+        .map(|(id, _)| UserId(*id))
+    }
+
+    /// Link a user to a name and save the registry.
+    /// Replaces any existing link for this user, see [Self::refuse_link].
+    pub fn link(
+        &mut self,
+        id: UserId,
+        name: &str,
+        username: Option<String>,
+    ) -> std::io::Result<()> {
+        let user = LinkedUser {
+            name: name.to_string(),
+            username,
+            linked_at: chrono::Local::now().naive_local(),
+        };
+
+        let prev = self.users.insert(id.0, user);
+
+        // keep memory in sync with the file
+        if let Err(e) = self.save() {
+            match prev {
+                Some(prev) => self.users.insert(id.0, prev),
+                None => self.users.remove(&id.0),
+            };
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Remove the link for a user and save the registry.
+    pub fn unlink(&mut self, id: UserId) -> std::io::Result<Option<LinkedUser>> {
+        let removed = self.users.remove(&id.0);
+
+        if let Some(user) = &removed {
+            if let Err(e) = self.save() {
+                self.users.insert(id.0, user.clone());
+                return Err(e);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Write the registry to disk, through a temporary file
+    fn save(&self) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let contents = serde_json::to_string_pretty(&self.users)?;
+        let temp = self.path.with_extension("json.tmp");
+
+        std::fs::write(&temp, contents)?;
+        std::fs::rename(temp, &self.path)
+    }
+}
+
+/// Name in the attendance sheet that belongs to a telegram user.
+///
+/// Users linked through /start take precedence over the telegram column of the configs sheet.
+pub async fn linked_name(user: &User, date: NaiveDate) -> Option<String> {
+    if let Some(linked) = REGISTRY.read().await.get(user.id) {
+        return Some(linked.name.clone());
+    }
+
+    let handle = user.username.as_ref()?;
+    ntu_canoebot_attd::name_from_telegram(handle, date).await
+}
+
+/// Returns the indices of names that best match a query, best match first.
+pub fn search_names(query: &str, names: &[String], limit: usize) -> Vec<usize> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+
+    let mut scored: Vec<(i64, usize)> = names
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, name)| matcher.fuzzy_match(name, query).map(|score| (score, idx)))
+        .collect();
+
+    // highest score first, then sheet order
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    scored.into_iter().take(limit).map(|(_, idx)| idx).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_persistence() {
+        let dir = std::env::temp_dir().join("canoebot_test_registry_persistence");
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join(REGISTRY_FILE);
+
+        let mut registry = Registry::load(&path);
+        assert!(registry.get(UserId(1)).is_none());
+
+        registry
+            .link(UserId(1), "Some Person", Some("some_handle".to_string()))
+            .unwrap();
+        registry.link(UserId(2), "Another Person", None).unwrap();
+
+        let registry_reloaded = Registry::load(&path);
+        assert_eq!(
+            registry_reloaded.get(UserId(1)).unwrap().name,
+            "Some Person"
+        );
+        assert_eq!(
+            registry_reloaded.user_with_name("Another Person"),
+            Some(UserId(2))
+        );
+        assert_eq!(registry_reloaded.find("@Some_Handle"), Some(UserId(1)));
+        assert_eq!(registry_reloaded.find("another person"), Some(UserId(2)));
+        assert_eq!(registry_reloaded.find("@nobody"), None);
+
+        let removed = registry.unlink(UserId(1)).unwrap();
+        assert_eq!(removed.unwrap().name, "Some Person");
+        assert!(registry.unlink(UserId(1)).unwrap().is_none());
+
+        let registry_reloaded = Registry::load(&path);
+        assert!(registry_reloaded.get(UserId(1)).is_none());
+        assert!(registry_reloaded.get(UserId(2)).is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_refuse_link() {
+        let dir = std::env::temp_dir().join("canoebot_test_refuse_link");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut registry = Registry::load(dir.join(REGISTRY_FILE));
+        assert_eq!(registry.refuse_link(UserId(1), "Some Person"), None);
+        registry.link(UserId(1), "Some Person", None).unwrap();
+
+        // another account cannot take over the name
+        assert_eq!(
+            registry.refuse_link(UserId(2), "Some Person"),
+            Some(Refused::NameTaken)
+        );
+        // relinking needs an unlink first
+        assert_eq!(
+            registry.refuse_link(UserId(1), "Another Person"),
+            Some(Refused::AlreadyLinked("Some Person".to_string()))
+        );
+
+        registry.unlink(UserId(1)).unwrap();
+        assert_eq!(registry.refuse_link(UserId(2), "Some Person"), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_handle() {
+        assert_eq!(
+            verify_handle(Some("some_handle"), Some("Some_Handle")),
+            Verification::Verified
+        );
+        assert_eq!(
+            verify_handle(Some("some_handle"), Some("other_handle")),
+            Verification::Mismatch
+        );
+        // no handle to compare with
+        assert_eq!(
+            verify_handle(Some("some_handle"), None),
+            Verification::Mismatch
+        );
+        assert_eq!(
+            verify_handle(None, Some("some_handle")),
+            Verification::Unverified
+        );
+    }
+
+    #[test]
+    fn test_search_names() {
+        let names: Vec<String> = ["Tan Ah Kow", "Lim Ah Beng", "Tan Mei Ling", "Ali"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        assert_eq!(search_names("ah beng", &names, 5), vec![1]);
+        assert_eq!(search_names("tan", &names, 5).len(), 2);
+        assert_eq!(search_names("tan", &names, 1).len(), 1);
+        assert!(search_names("xyz", &names, 5).is_empty());
+    }
+}
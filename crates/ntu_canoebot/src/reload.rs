@@ -0,0 +1,186 @@
+//! Reloading of bot settings while running.
+//!
+//! Config files are polled for changes, and reloaded settings are only
+//! swapped in if they are valid.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use teloxide::prelude::*;
+
+use ntu_canoebot_config as config;
+
+use crate::events::exco_chat_id;
+
+/// How often config files are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Keys that are only read on startup
+const RESTART_REQUIRED: [&str; 7] = [
+    "canoebot.apikey",
+    "canoebot.data_dir",
+    "canoebot.monitor",
+    "canoebot.webhook",
+    "logger",
+    "misc.silence.crab_url",
+    "use",
+];
+
+/// Reload settings and apply them to anything scheduled.
+///
+/// Returns the keys that changed. On error, the current settings are kept.
+pub async fn reload_settings() -> Result<Vec<String>, config::ConfigError> {
+    let changed = config::reload()?;
+
+    if changed.iter().any(|key| key.starts_with("events")) {
+        crate::schedule_events();
+    }
+
+    if changed.iter().any(|key| key.starts_with("sheetscraper")) {
+        tokio::task::spawn(async {
+            if let Err(e) = ntu_canoebot_attd::init().await {
+                log::error!("failed to load configs sheet: {}", e);
+            }
+
+            let _ = ntu_canoebot_attd::refresh_attd_sheet_cache(true).await;
+            let _ = ntu_canoebot_attd::refresh_prog_sheet_cache(true).await;
+        });
+    }
+
+    log::info!("settings reloaded, changed: {:?}", changed);
+    Ok(changed)
+}
+
+/// Describe which keys changed in a reload
+pub fn summary(changed: &[String]) -> String {
+    if changed.is_empty() {
+        return "settings reloaded, nothing changed".to_string();
+    }
+
+    let keys = changed
+        .iter()
+        .map(|key| {
+            let restart = RESTART_REQUIRED
+                .iter()
+                .any(|k| key == k || key.starts_with(&format!("{}.", k)));
+
+            match restart {
+                true => format!("- {} (restart to apply)", key),
+                false => format!("- {}", key),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    format!("settings reloaded, changed:\n{}", keys)
+}
+
+/// Modified times of the config files in a directory.
+///
+/// The directory is listed every time, so files created or removed later are noticed.
+fn modified_times(dir: &Path) -> BTreeMap<OsString, SystemTime> {
+    let names: Vec<OsString> = config::config_files(dir)
+        .iter()
+        .filter_map(|path| path.file_name().map(|name| name.to_owned()))
+        .collect();
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return BTreeMap::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| names.contains(&entry.file_name()))
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+            Some((entry.file_name(), modified))
+        })
+        .collect()
+}
+
+/// Reload settings whenever a config file changes.
+///
+/// The result is sent to the exco chat, if there is one.
+pub async fn watch_settings(bot: Bot) {
+    let dir = config::config_dir();
+    let mut last = modified_times(&dir);
+
+    loop {
+        tokio::time::sleep(WATCH_INTERVAL).await;
+
+        let current = modified_times(&dir);
+        if current == last {
+            continue;
+        }
+        last = current;
+
+        let text = match reload_settings().await {
+            Ok(changed) if changed.is_empty() => continue,
+            Ok(changed) => summary(&changed),
+            Err(e) => {
+                log::error!("settings not reloaded: {}", e);
+                format!("settings file changed, but was not reloaded: {}", e)
+            }
+        };
+
+        if let Some(chat_id) = exco_chat_id() {
+            if let Err(e) = bot.send_message(ChatId(chat_id), text).await {
+                log::error!("failed to send reload summary: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(summary(&[]), "settings reloaded, nothing changed");
+
+        let changed = [
+            "events".to_string(),
+            "logger.log_level".to_string(),
+            "canoebot.apikey".to_string(),
+            "canoebot.apikeys".to_string(),
+            "canoebot.webhook.port".to_string(),
+            "canoebot.monitor.interval".to_string(),
+        ];
+        assert_eq!(
+            summary(&changed),
+            "settings reloaded, changed:\n\
+            - events\n\
+            - logger.log_level (restart to apply)\n\
+            - canoebot.apikey (restart to apply)\n\
+            - canoebot.apikeys\n\
+            - canoebot.webhook.port (restart to apply)\n\
+            - canoebot.monitor.interval (restart to apply)"
+        );
+    }
+
+    #[test]
+    fn test_modified_times() {
+        let dir = std::env::temp_dir().join("canoebot_test_modified_times");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(config::TEMPLATE_FILE), "").unwrap();
+
+        let before = modified_times(&dir);
+        assert_eq!(before.len(), 1);
+
+        // other files are ignored
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        assert_eq!(modified_times(&dir), before);
+
+        // overrides created after the watch started are picked up
+        std::fs::write(dir.join(config::DEPLOY_FILE), "use = true").unwrap();
+        let after = modified_times(&dir);
+        assert_eq!(after.len(), 2);
+        assert!(after.contains_key(std::ffi::OsStr::new(config::DEPLOY_FILE)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
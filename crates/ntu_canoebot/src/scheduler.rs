@@ -0,0 +1,278 @@
+//! Runs the `[[events]]` in settings on their schedules.
+//!
+//! The last run of each event is saved in `canoebot.data_dir`, so events
+//! that were due while the bot was down can be caught up on after a restart.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, NaiveDateTime};
+use lazy_static::lazy_static;
+use teloxide::prelude::*;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use config::Event;
+use ntu_canoebot_config as config;
+
+use crate::events;
+use crate::threadmonitor::THREAD_WATCH;
+
+/// File name of the last runs, inside the data dir
+const RUNS_FILE: &str = "events.json";
+
+lazy_static! {
+    /// Scheduled events, cancelled when rescheduling
+    static ref EVENT_TASKS: std::sync::Mutex<Vec<JoinHandle<()>>> = Default::default();
+
+    /// When each event last ran
+    static ref LAST_RUNS: Mutex<LastRuns> =
+        Mutex::new(LastRuns::load(Path::new(&config::settings().canoebot.data_dir).join(RUNS_FILE)));
+}
+
+/// Last run of each event, by name
+#[derive(Debug, Default)]
+struct LastRuns {
+    path: PathBuf,
+    runs: HashMap<String, NaiveDateTime>,
+}
+
+impl LastRuns {
+    /// Starts empty if the file does not exist yet
+    fn load(path: PathBuf) -> Self {
+        let runs = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                log::error!("unable to parse event runs {}: {}", path.display(), e);
+                Default::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Default::default(),
+            Err(e) => {
+                log::error!("unable to read event runs {}: {}", path.display(), e);
+                Default::default()
+            }
+        };
+
+        Self { path, runs }
+    }
+
+    /// Record a run and save, through a temporary file
+    fn record(&mut self, name: &str, at: NaiveDateTime) -> std::io::Result<()> {
+        self.runs.insert(name.to_string(), at);
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let contents = serde_json::to_string_pretty(&self.runs)?;
+        let temp = self.path.with_extension("json.tmp");
+
+        std::fs::write(&temp, contents)?;
+        std::fs::rename(temp, &self.path)
+    }
+}
+
+fn now() -> NaiveDateTime {
+    chrono::Local::now().naive_local()
+}
+
+/// Start every enabled event, cancelling those started before.
+///
+/// Call again after settings change.
+pub fn schedule(bot: Bot) {
+    let mut tasks = EVENT_TASKS.lock().unwrap();
+    for task in tasks.drain(..) {
+        task.abort();
+    }
+
+    for event in config::settings().events.iter().filter(|e| e.enable) {
+        log::info!("scheduling \"{}\" ({})", event.name, event.schedule);
+        tasks.push(tokio::task::spawn(run_schedule(bot.clone(), event.clone())));
+    }
+}
+
+/// Whether an event was due since its last run, recently enough to catch up on
+fn missed(event: &Event, last_run: Option<NaiveDateTime>, now: NaiveDateTime) -> bool {
+    if event.catch_up_hours <= 0 {
+        return false;
+    }
+
+    // events that never ran have nothing to catch up on
+    match (last_run, event.schedule.last_before(now)) {
+        (Some(last_run), Some(due)) => {
+            last_run < due && now - due <= Duration::hours(event.catch_up_hours)
+        }
+        _ => false,
+    }
+}
+
+async fn run_schedule(bot: Bot, event: Event) {
+    let last_run = LAST_RUNS.lock().await.runs.get(&event.name).copied();
+    if missed(&event, last_run, now()) {
+        log::info!("catching up on \"{}\"", event.name);
+        run(&bot, &event).await;
+    }
+
+    let mut after = now();
+    loop {
+        let Some(next) = event.schedule.next_after(after) else {
+            log::warn!("\"{}\" ({}) never runs", event.name, event.schedule);
+            return;
+        };
+
+        let wait = (next - now()).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+        run(&bot, &event).await;
+
+        // timers can wake up early, never run the same time twice
+        after = next.max(now());
+    }
+}
+
+async fn run(bot: &Bot, event: &Event) {
+    log::info!("running \"{}\"", event.name);
+
+    match config::settings().canoebot.chat_id(&event.chat) {
+        Some(chat_id) => {
+            THREAD_WATCH
+                .spawn(events::run_action(
+                    bot.clone(),
+                    ChatId(chat_id),
+                    event.action,
+                    event.text.clone(),
+                ))
+                .await
+        }
+        None => log::error!("\"{}\": no chat id for \"{}\"", event.name, event.chat),
+    }
+
+    if let Err(e) = LAST_RUNS.lock().await.record(&event.name, now()) {
+        log::error!("unable to save event runs: {}", e);
+    }
+}
+
+/// Upcoming runs of events, soonest first, for /events
+pub async fn upcoming() -> String {
+    let settings = config::settings();
+    let last_runs = LAST_RUNS.lock().await;
+
+    describe(&settings.events, &last_runs.runs, now())
+}
+
+fn describe(
+    events: &[Event],
+    last_runs: &HashMap<String, NaiveDateTime>,
+    now: NaiveDateTime,
+) -> String {
+    let format = "%a %d %b %H:%M";
+
+    let mut enabled: Vec<(Option<NaiveDateTime>, &Event)> = events
+        .iter()
+        .filter(|e| e.enable)
+        .map(|e| (e.schedule.next_after(now), e))
+        .collect();
+    // events that never run go last
+    enabled.sort_by_key(|(next, _)| (next.is_none(), *next));
+
+    let mut lines: Vec<String> = enabled
+        .iter()
+        .map(|(next, event)| {
+            let next = match next {
+                Some(next) => next.format(format).to_string(),
+                None => "never".to_string(),
+            };
+            let last = match last_runs.get(&event.name) {
+                Some(last) => last.format(format).to_string(),
+                None => "never".to_string(),
+            };
+
+            format!(
+                "{}: {}\n  {}, {} to {}, last ran {}",
+                next, event.name, event.schedule, event.action, event.chat, last
+            )
+        })
+        .collect();
+
+    if lines.is_empty() {
+        lines.push("no events scheduled".to_string());
+    }
+
+    let disabled: Vec<&str> = events
+        .iter()
+        .filter(|e| !e.enable)
+        .map(|e| e.name.as_str())
+        .collect();
+    if !disabled.is_empty() {
+        lines.push(format!("disabled: {}", disabled.join(", ")));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::EventAction;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn event(name: &str, schedule: &str, catch_up_hours: i64) -> Event {
+        Event {
+            name: name.to_string(),
+            enable: true,
+            schedule: schedule.parse().unwrap(),
+            chat: "exco".to_string(),
+            action: EventAction::Message,
+            text: "hello".to_string(),
+            catch_up_hours,
+        }
+    }
+
+    #[test]
+    fn test_missed() {
+        let daily = event("daily", "daily 07:00", 2);
+        let now = at("2024-01-31 08:30");
+
+        assert!(missed(&daily, Some(at("2024-01-30 07:00")), now));
+        // already ran
+        assert!(!missed(&daily, Some(at("2024-01-31 07:00")), now));
+        // never ran before
+        assert!(!missed(&daily, None, now));
+        // too late
+        assert!(!missed(
+            &daily,
+            Some(at("2024-01-30 07:00")),
+            at("2024-01-31 09:30")
+        ));
+
+        let no_catch_up = event("daily", "daily 07:00", 0);
+        assert!(!missed(&no_catch_up, Some(at("2024-01-30 07:00")), now));
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut disabled = event("off", "daily 08:00", 0);
+        disabled.enable = false;
+        let events = [
+            event("weekly", "weekly sat 22:30", 0),
+            event("daily", "daily 19:00", 0),
+            disabled,
+        ];
+        let last_runs = HashMap::from([("daily".to_string(), at("2024-01-30 19:00"))]);
+
+        assert_eq!(
+            describe(&events, &last_runs, at("2024-01-31 12:00")),
+            "Wed 31 Jan 19:00: daily\n  \
+            daily 19:00, message to exco, last ran Tue 30 Jan 19:00\n\
+            Sat 03 Feb 22:30: weekly\n  \
+            weekly sat 22:30, message to exco, last ran never\n\
+            disabled: off"
+        );
+        assert_eq!(
+            describe(&[], &last_runs, at("2024-01-31 12:00")),
+            "no events scheduled"
+        );
+    }
+}
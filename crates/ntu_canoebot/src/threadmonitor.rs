@@ -4,14 +4,22 @@ use std::{error::Error, sync::Arc, time::Duration};
 
 use futures::Future;
 use lazy_static::lazy_static;
+use ntu_canoebot_util::metrics::Counter;
 use tokio::{
     sync::{mpsc, Mutex},
     task::JoinHandle,
 };
 
+use crate::error_report::{self, Answered, TaskOrigin};
+
 /// The default amount of time to wait for a future to execute to completion
 const DEFAULT_DURATION: Duration = Duration::from_secs(15);
 
+const TASK_ERRORS: Counter = Counter::new(
+    "canoebot_task_errors_total",
+    "watched tasks that returned an error or panicked",
+);
+
 lazy_static! {
     /// Holds on to `JoinHandle` and logs any errors
     pub static ref THREAD_WATCH: ThreadWatch = ThreadWatch::new();
@@ -70,6 +78,22 @@ impl ThreadWatch {
     /// Pushes a joinhandle to the thread queue.
     /// Runs a prune of the thread queue after a specified duration.
     pub async fn push(&self, thread: JoinHandle<DynResult>, prune_delay: Duration) {
+        self.push_inner(thread, prune_delay).await;
+    }
+
+    /// Like [Self::push], also reporting errors from the task.
+    ///
+    /// The task is awaited on its own, so errors are reported as soon as it
+    /// finishes. See [error_report].
+    pub fn push_with(&self, thread: JoinHandle<DynResult>, origin: TaskOrigin) {
+        tokio::spawn(async move {
+            if let Some(error) = Self::join(thread).await {
+                error_report::report(origin, error).await;
+            }
+        });
+    }
+
+    async fn push_inner(&self, thread: JoinHandle<DynResult>, prune_delay: Duration) {
         let mut lock = self.inner.lock().await;
         lock.handles.push(thread);
         drop(lock);
@@ -104,16 +128,36 @@ impl ThreadWatch {
 
             for idx in indices {
                 let h = lock.handles.swap_remove(idx);
-                match h.await {
-                    Ok(res) => match res {
-                        Ok(_) => (),
-                        Err(e) => log::error!("Thread error: {} \nCaused by:? {:?}", e, e.source()),
-                    },
-                    Err(e) => log::error!("Join error: {}", e),
-                }
+                Self::join(h).await;
             }
 
             drop(lock);
         }
     }
+
+    /// Wait for a task, logging and counting its error if it has one
+    async fn join(handle: JoinHandle<DynResult>) -> Option<DynError> {
+        match handle.await {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) if e.downcast_ref::<Answered>().is_some() => {
+                TASK_ERRORS.increment(&[("kind", "answered")]);
+                log::warn!(
+                    "Thread error, answered: {} \nCaused by:? {:?}",
+                    e,
+                    e.source()
+                );
+                Some(e)
+            }
+            Ok(Err(e)) => {
+                TASK_ERRORS.increment(&[("kind", "error")]);
+                log::error!("Thread error: {} \nCaused by:? {:?}", e, e.source());
+                Some(e)
+            }
+            Err(e) => {
+                TASK_ERRORS.increment(&[("kind", "join")]);
+                log::error!("Join error: {}", e);
+                Some(e.into())
+            }
+        }
+    }
 }
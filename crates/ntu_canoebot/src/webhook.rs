@@ -0,0 +1,197 @@
+//! Receiving updates through a webhook, with the `webhook` feature.
+//!
+//! Telegram posts updates to `canoebot.webhook.url`, which should be proxied
+//! to the server listening on `canoebot.webhook.address`.
+
+use std::convert::Infallible;
+use std::error::Error;
+
+use teloxide::prelude::*;
+use teloxide::update_listeners::webhooks::{self, Options};
+use teloxide::update_listeners::UpdateListener;
+use tokio::net::TcpListener;
+
+use ntu_canoebot_config as config;
+
+/// Webhook options from settings
+pub fn options() -> Result<Options, Box<dyn Error + Send + Sync>> {
+    let settings = config::settings().canoebot.webhook.clone();
+
+    if settings.url.is_empty() {
+        return Err("canoebot.webhook.url is not set".into());
+    }
+    let url = settings.url.parse()?;
+
+    let options = Options::new(settings.address, url);
+    Ok(match settings.secret_token.is_empty() {
+        true => options,
+        false => options.secret_token(settings.secret_token),
+    })
+}
+
+/// Register the webhook with telegram and start the server.
+///
+/// The webhook is removed when the listener stops.
+pub async fn listener(
+    bot: Bot,
+    options: Options,
+) -> Result<impl UpdateListener<Err = Infallible>, Box<dyn Error + Send + Sync>> {
+    let address = options.address;
+    let tcp_listener = TcpListener::bind(address).await?;
+
+    let (listener, stop, router) = webhooks::axum_to_router(bot, options).await?;
+
+    tokio::task::spawn(async move {
+        let served = axum::serve(tcp_listener, router)
+            .with_graceful_shutdown(stop)
+            .await;
+
+        if let Err(e) = served {
+            log::error!("webhook server failed: {}", e);
+        }
+    });
+
+    log::info!("webhook listening on {}", address);
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::extract::{Path, State};
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+    use teloxide::dispatching::Dispatcher;
+    use teloxide::error_handlers::LoggingErrorHandler;
+    use tokio::sync::mpsc;
+
+    const SECRET: &str = "test_secret";
+
+    /// An update for `/version` in a private chat, as sent by telegram
+    const VERSION_UPDATE: &str = r#"{
+        "update_id": 10000,
+        "message": {
+            "message_id": 1365,
+            "from": {
+                "id": 1111111,
+                "is_bot": false,
+                "first_name": "Test",
+                "username": "tester",
+                "language_code": "en"
+            },
+            "chat": {
+                "id": 1111111,
+                "first_name": "Test",
+                "username": "tester",
+                "type": "private"
+            },
+            "date": 1706688000,
+            "text": "/version",
+            "entities": [{ "offset": 0, "length": 8, "type": "bot_command" }]
+        }
+    }"#;
+
+    /// Answers bot API calls like telegram, and passes on their method and body.
+    ///
+    /// Bodies that are not json (e.g. multipart) are passed on as a string.
+    async fn fake_api(
+        State(calls): State<mpsc::UnboundedSender<(String, Value)>>,
+        Path((_token, method)): Path<(String, String)>,
+        body: String,
+    ) -> Json<Value> {
+        let body = serde_json::from_str(&body).unwrap_or(Value::String(body));
+        let result = match method.as_str() {
+            "GetMe" => json!({
+                "id": 2222222,
+                "is_bot": true,
+                "first_name": "canoebot",
+                "username": "canoebot",
+                "can_join_groups": true,
+                "can_read_all_group_messages": false,
+                "supports_inline_queries": false
+            }),
+            "SendMessage" => json!({
+                "message_id": 1366,
+                "date": 1706688001,
+                "chat": { "id": body["chat_id"], "first_name": "Test", "type": "private" },
+                "text": body["text"]
+            }),
+            _ => json!(true),
+        };
+
+        calls.send((method, body)).unwrap();
+        Json(json!({ "ok": true, "result": result }))
+    }
+
+    /// An address that is free to listen on
+    async fn free_address() -> std::net::SocketAddr {
+        TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_webhook_update() {
+        let (calls_tx, mut calls) = mpsc::unbounded_channel();
+        let api = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}/", api.local_addr().unwrap());
+        let app = Router::new()
+            .route("/:token/:method", post(fake_api))
+            .with_state(calls_tx);
+        tokio::task::spawn(async move { axum::serve(api, app).await });
+
+        let bot = Bot::new("12345:abc").set_api_url(api_url.parse().unwrap());
+        let address = free_address().await;
+        let url = "https://example.com/canoebot".parse().unwrap();
+        let options = Options::new(address, url).secret_token(SECRET.to_string());
+
+        let listener = listener(bot.clone(), options).await.unwrap();
+        let (method, body) = calls.recv().await.unwrap();
+        assert_eq!(method, "SetWebhook");
+        assert!(body.to_string().contains(SECRET));
+
+        let mut dispatcher = Dispatcher::builder(bot, crate::schema()).build();
+        tokio::task::spawn(async move {
+            dispatcher
+                .dispatch_with_listener(listener, LoggingErrorHandler::new())
+                .await
+        });
+        let (method, _) = calls.recv().await.unwrap();
+        assert_eq!(method, "GetMe");
+
+        let client = reqwest::Client::new();
+        let hook = format!("http://{}/canoebot", address);
+
+        // updates without the secret are refused
+        let resp = client
+            .post(&hook)
+            .header("content-type", "application/json")
+            .body(VERSION_UPDATE)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        let resp = client
+            .post(&hook)
+            .header("content-type", "application/json")
+            .header("X-Telegram-Bot-Api-Secret-Token", SECRET)
+            .body(VERSION_UPDATE)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+
+        let (method, body) = calls.recv().await.unwrap();
+        assert_eq!(method, "SendMessage");
+        assert_eq!(body["chat_id"], 1111111);
+        assert!(body["text"]
+            .as_str()
+            .unwrap()
+            .contains(env!("CARGO_PKG_VERSION")));
+    }
+}
@@ -1,721 +1,798 @@
 //! Boat deconflict module
+//!
+//! Deconflict is a maximum weight bipartite matching between names and boats,
+//! solved with the Hungarian algorithm.
+//! Everyone with an allocation can use their primary boat, alternate boat,
+//! or any pool boat (a boat no one in the session has been allocated).
+//! Boats take as many people as they have seats, and out of service boats
+//! are never used.
+//!
+//! Before matching, people with preferred partners in the session are put
+//! together in multi-seat boats as crews, unless that would leave more people
+//! without a boat.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Display;
+
+use ntu_canoebot_util::debug_println;
+
+use crate::{get_config_type, NameList, BOATS, BOAT_ALLOCATIONS, PARTNERS};
+
+/// How much someone wants a boat
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Preference {
+    Primary,
+    Alternate,
+    Pool,
+}
 
-use std::collections::{BTreeMap, HashMap, HashSet};
-
-use ntu_canoebot_util::{debug_print, debug_println};
+impl Preference {
+    /// Matching weight, for a session of `people` names.
+    ///
+    /// Weights are chosen so that seating one more person beats any number of
+    /// primary boats, and one more primary boat beats any number of alternates.
+    fn weight(self, people: usize) -> i64 {
+        let m = people as i64 + 1;
+        match self {
+            Preference::Primary => m * m + m,
+            Preference::Alternate => m * m + 1,
+            Preference::Pool => m * m,
+        }
+    }
+}
 
-use crate::{get_config_type, Config, NameList, BOAT_ALLOCATIONS};
+/// Why someone did not get a boat
+#[derive(Clone, Debug, PartialEq)]
+pub enum UnseatedReason {
+    /// No primary or alternate boat in the configs sheet
+    NoAllocation,
+    /// Their boats are full or out of service, and all pool boats are in use
+    Taken {
+        /// Boats and the crew in them
+        full: Vec<(String, Vec<String>)>,
+        out_of_service: Vec<String>,
+    },
+}
 
-/// This struct contains the boat allocation result.
-/// If lock is set to true, the boat assigned must no longer be changed.
-/// If fail is set to true, all other options have been used.
-#[derive(Clone, Debug, Default)]
-struct AllocResult {
-    boat: String,
-    /// Boat allocated must not be changed
-    lock: bool,
-    /// Allocation has failed
-    fail: bool,
-    /// Name does not have any boats to allocate
-    absent: bool,
+/// Someone who could not be given a boat
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unseated {
+    pub name: String,
+    pub reason: UnseatedReason,
 }
 
-impl AllocResult {
-    /// Pass in a boat name
-    fn from_boat(boat: String) -> Self {
-        Self {
-            boat,
-            lock: false,
-            fail: false,
-            absent: false,
-        }
-    }
-    /// Create a alloc where no boat has been
-    /// specified for a person
-    fn from_absent() -> Self {
-        Self {
-            boat: String::new(),
-            lock: false,
-            fail: false,
-            absent: true,
+impl Display for Unseated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            UnseatedReason::NoAllocation => write!(f, "{}: no boats allocated", self.name),
+            UnseatedReason::Taken {
+                full,
+                out_of_service,
+            } => {
+                let reasons = full
+                    .iter()
+                    .map(|(boat, crew)| format!("{} taken by {}", boat, crew.join(" and ")))
+                    .chain(
+                        out_of_service
+                            .iter()
+                            .map(|boat| format!("{} out of service", boat)),
+                    )
+                    .chain(["no pool boats left".to_string()])
+                    .collect::<Vec<String>>()
+                    .join(", ");
+
+                write!(f, "{}: {}", self.name, reasons)
+            }
         }
     }
+}
 
-    /// Create an alloc where no boat can be assigned
-    /// to a person without creating conflicts
-    fn from_fail(boat: String) -> Self {
-        Self {
-            boat,
-            lock: true,
-            fail: true,
-            absent: false,
-        }
+/// Seats in a boat. Boats not in `seats` have 1, out of service boats have 0.
+fn seats_in(seats: &HashMap<String, usize>, boat: &str) -> usize {
+    seats.get(boat).copied().unwrap_or(1)
+}
+
+/// Boats someone can use and how much they want each
+fn options<'a>(
+    allocation: &'a (Option<String>, Option<String>),
+    pool: &'a [String],
+    seats: &HashMap<String, usize>,
+) -> Vec<(&'a str, Preference)> {
+    let (pri, alt) = allocation;
+    if pri.is_none() && alt.is_none() {
+        return vec![];
     }
 
-    /// Create an alloc where the boat assigned is locked
-    fn from_lock(boat: String) -> Self {
-        Self {
-            boat,
-            lock: true,
-            fail: false,
-            absent: false,
-        }
+    let mut opts: Vec<(&str, Preference)> = Vec::new();
+    if let Some(p) = pri {
+        opts.push((p, Preference::Primary));
     }
-}
+    if let Some(a) = alt.as_ref().filter(|a| Some(*a) != pri.as_ref()) {
+        opts.push((a, Preference::Alternate));
+    }
+    opts.extend(pool.iter().map(|b| (b.as_str(), Preference::Pool)));
+    opts.retain(|(boat, _)| seats_in(seats, boat) > 0);
 
-impl NameList {
-    /// Assign everyone their primary boats.
-    /// If deconflict is set to true, perform deconflict.
-    ///
-    /// Returns false when operation fails.
-    pub async fn assign_boats(&mut self, deconflict: bool) -> bool {
-        let config = get_config_type(self.date);
+    opts
+}
 
-        let allo_lock = BOAT_ALLOCATIONS[config as usize].read().await;
+/// Assign boats to people, given their (primary, alternate) boats.
+///
+/// Maximises the number of people seated, then primary boats, then alternate boats.
+/// `pool` must not contain anyone's primary or alternate boat.
+fn match_boats(
+    allocations: &[(Option<String>, Option<String>)],
+    pool: &[String],
+    seats: &HashMap<String, usize>,
+) -> Vec<Option<String>> {
+    let people = allocations.len();
+
+    // one column per seat
+    let boats: Vec<&str> = allocations
+        .iter()
+        .flat_map(|(p, a)| [p.as_deref(), a.as_deref()])
+        .flatten()
+        .chain(pool.iter().map(String::as_str))
+        .collect::<BTreeSet<&str>>()
+        .into_iter()
+        .flat_map(|b| std::iter::repeat_n(b, seats_in(seats, b)))
+        .collect();
+
+    let weights: Vec<Vec<i64>> = allocations
+        .iter()
+        .map(|allocation| {
+            let opts = options(allocation, pool, seats);
+            boats
+                .iter()
+                .map(|boat| match opts.iter().find(|(b, _)| b == boat) {
+                    Some((_, pref)) => pref.weight(people),
+                    None => 0,
+                })
+                .collect()
+        })
+        .collect();
+
+    max_weight_assignment(&weights)
+        .into_iter()
+        .zip(weights.iter())
+        .map(|(col, row)| match col {
+            // zero weight is not a real option
+            Some(c) if row[c] > 0 => Some(boats[c].to_owned()),
+            _ => None,
+        })
+        .collect()
+}
 
-        let assigned: Vec<Option<String>> = self
-            .names
-            .iter()
-            .map(|n| {
-                if allo_lock.contains_key(n) {
-                    let allo = allo_lock.get(n).unwrap();
-                    if let Some(pri) = allo.0.as_deref() {
-                        return Some(pri.to_owned());
-                    }
-                    if let Some(alt) = allo.1.as_deref() {
-                        return Some(alt.to_owned());
-                    }
-
-                    None
-                } else {
-                    None
+/// Hungarian algorithm, O(n²m).
+///
+/// Returns the column assigned to each row, maximising the total weight.
+/// Rows may be left unassigned if there are more rows than columns.
+fn max_weight_assignment(weights: &[Vec<i64>]) -> Vec<Option<usize>> {
+    let n = weights.len();
+    let cols = weights.first().map(|r| r.len()).unwrap_or(0);
+    // dummy columns, so that every row can be assigned
+    let m = cols + n;
+
+    let cost = |i: usize, j: usize| -> i64 {
+        match j < cols {
+            true => -weights[i][j],
+            false => 0,
+        }
+    };
+
+    // potentials and assignment are 1-indexed, with 0 as a sentinel
+    let mut u = vec![0i64; n + 1];
+    let mut v = vec![0i64; m + 1];
+    let mut p = vec![0usize; m + 1];
+    let mut way = vec![0usize; m + 1];
+
+    for i in 1..=n {
+        p[0] = i;
+        let mut j0 = 0;
+        let mut minv = vec![i64::MAX; m + 1];
+        let mut used = vec![false; m + 1];
+
+        loop {
+            used[j0] = true;
+            let i0 = p[j0];
+            let mut delta = i64::MAX;
+            let mut j1 = 0;
+
+            for j in 1..=m {
+                if used[j] {
+                    continue;
                 }
-            })
-            .collect();
-
-        self.boats = Some(assigned);
-
-        let res = match deconflict {
-            false => {
-                return true;
-            }
-            true => {
-                let potential_matches = Self::find_matching(&self.names, config).await;
-
-                let mut lookup: HashMap<&str, Option<String>> = Default::default();
-
-                let mut deconf_result: bool = true;
-                for matches in potential_matches.iter() {
-                    let (deconf_lookup, success) = Self::deconflict(matches, config).await;
-                    if !success {
-                        deconf_result = false;
-                    }
-                    lookup.extend(deconf_lookup);
+                let cur = cost(i0 - 1, j - 1) - u[i0] - v[j];
+                if cur < minv[j] {
+                    minv[j] = cur;
+                    way[j] = j0;
+                }
+                if minv[j] < delta {
+                    delta = minv[j];
+                    j1 = j;
                 }
+            }
 
-                debug_println!("deconf lookup: {:?}", lookup);
-
-                for idx in 0..self.names.len() {
-                    let name = &self.names[idx];
-                    if lookup.contains_key(name.as_str()) {
-                        let boat = lookup.get(name.as_str()).unwrap().clone();
-                        match &mut self.boats {
-                            Some(boatlist) => boatlist[idx] = boat,
-                            None => (),
-                        }
-                    }
+            for j in 0..=m {
+                if used[j] {
+                    u[p[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    minv[j] -= delta;
                 }
+            }
 
-                deconf_result
+            j0 = j1;
+            if p[j0] == 0 {
+                break;
             }
-        };
-        match &mut self.boats {
-            Some(boats) => {
-                Self::mark_matching(boats);
+        }
+
+        // flip the augmenting path
+        loop {
+            let j1 = way[j0];
+            p[j0] = p[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
             }
-            None => (),
         }
+    }
 
-        res
+    let mut assigned = vec![None; n];
+    for j in 1..=cols {
+        if p[j] != 0 {
+            assigned[p[j] - 1] = Some(j - 1);
+        }
     }
 
-    /// Group names that might potentially share the same boat
-    async fn find_matching(names: &[String], config: Config) -> Vec<Vec<String>> {
-        // vec of names and if a name has been used (true => used, false => not used)
-        let mut remaining_names: Vec<(&str, bool)> =
-            names.iter().map(|n| (n.as_str(), false)).collect();
-
-        // names set for current group
-        let mut names_set = HashSet::<&str>::default();
-        let mut allo_set = HashSet::<&str>::default();
-
-        // groups
-        let mut groups: Vec<Vec<String>> = Vec::new();
-        let read_lock = BOAT_ALLOCATIONS[config as usize].read().await;
-
-        // each iteration of the main loop must create a new list
-        while remaining_names.len() != 0 {
-            // debug_println!("remaining names: {}", remaining_names.len());
-            let mut group: Vec<String> = Vec::new();
-            names_set.clear();
-            allo_set.clear();
-
-            for _idx in 0..2 {
-                debug_println!("repetition {} of 2", _idx);
-                for (name, used) in remaining_names.iter_mut() {
-                    let (pri, alt) = {
-                        match read_lock.get(*name) {
-                            Some(boats) => {
-                                let p = boats.0.as_deref();
-                                let a = boats.1.as_deref();
-
-                                (p, a)
-                            }
-                            None => (None, None),
-                        }
-                    };
-
-                    // empty case
-                    if names_set.len() == 0 {
-                        *used = true;
-
-                        group.push(name.to_owned());
-                        names_set.insert(name);
-                        if let Some(_p) = pri {
-                            allo_set.insert(_p);
-                        }
-                        if let Some(_a) = alt {
-                            allo_set.insert(_a);
-                        }
-
-                        continue;
-                    }
-
-                    match names_set.contains(name) {
-                        // name exists, pri and alt boats added to set
-                        true => {
-                            *used = true;
-
-                            if let Some(primary) = pri {
-                                allo_set.insert(primary);
-                            }
-                            if let Some(alternate) = alt {
-                                allo_set.insert(alternate);
-                            }
-                        }
-                        // check if boats exist in allo set
-                        false => {
-                            let to_add = match (pri, alt) {
-                                (None, None) => false,
-                                (None, Some(a)) => {
-                                    if allo_set.contains(a) {
-                                        true
-                                    } else {
-                                        false
-                                    }
-                                }
-                                (Some(p), None) => {
-                                    if allo_set.contains(p) {
-                                        true
-                                    } else {
-                                        false
-                                    }
-                                }
-                                (Some(p), Some(a)) => {
-                                    if allo_set.contains(p) || allo_set.contains(a) {
-                                        allo_set.insert(p);
-                                        allo_set.insert(a);
-                                        true
-                                    } else {
-                                        false
-                                    }
-                                }
-                            };
-
-                            if to_add {
-                                *used = true;
-                                names_set.insert(name);
-                                // if !names_set.contains(name) {
-                                group.push(name.to_owned());
-                                // }
-                            }
-                        }
-                    }
-                }
+    assigned
+}
+
+/// Form crews of people who want to paddle together.
+///
+/// Going down the list, anyone with partners in the session is given a
+/// multi-seat boat, trying their own boats and their partners' before pool boats.
+/// Returns the boat and the indices of everyone in each crew.
+fn form_crews(
+    names: &[String],
+    allocations: &[(Option<String>, Option<String>)],
+    partners: &HashMap<String, Vec<String>>,
+    pool: &[String],
+    seats: &HashMap<String, usize>,
+) -> Vec<(String, Vec<usize>)> {
+    let index: HashMap<&str, usize> = names
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.as_str(), i))
+        .collect();
+
+    // smallest pool boats first
+    let mut pool_boats: Vec<&str> = pool.iter().map(String::as_str).collect();
+    pool_boats.sort_by_key(|b| seats_in(seats, b));
+
+    let mut in_crew = vec![false; names.len()];
+    let mut used: HashSet<&str> = HashSet::new();
+    let mut crews: Vec<(String, Vec<usize>)> = Vec::new();
+
+    for idx in 0..names.len() {
+        if in_crew[idx] {
+            continue;
+        }
+
+        let mut wanted: Vec<usize> = Vec::new();
+        for partner in partners.get(&names[idx]).into_iter().flatten() {
+            match index.get(partner.as_str()) {
+                Some(&i) if i != idx && !in_crew[i] && !wanted.contains(&i) => wanted.push(i),
+                _ => (),
             }
+        }
+        if wanted.is_empty() {
+            continue;
+        }
 
-            // remove used names
-            remaining_names = remaining_names
-                .into_iter()
-                .filter(|(_, used)| !used)
-                .collect();
-            // debug_println!("removed {} names", group.len());
+        let candidates: Vec<&str> = std::iter::once(idx)
+            .chain(wanted.iter().copied())
+            .flat_map(|m| [allocations[m].0.as_deref(), allocations[m].1.as_deref()])
+            .flatten()
+            .chain(pool_boats.iter().copied())
+            .filter(|b| seats_in(seats, b) > 1 && !used.contains(b))
+            .collect();
 
-            debug_print!("names: {:?} ", names_set);
-            debug_println!("conflicts: {:?}", allo_set);
+        // a boat that fits everyone, otherwise the biggest
+        let boat = candidates
+            .iter()
+            .find(|b| seats_in(seats, b) > wanted.len())
+            .or_else(|| candidates.iter().max_by_key(|b| seats_in(seats, b)));
+        let Some(&boat) = boat else {
+            continue;
+        };
 
-            // at least 1 is in each group
-            if group.len() > 1 {
-                groups.push(group);
-            }
+        let mut members = vec![idx];
+        members.extend(wanted.into_iter().take(seats_in(seats, boat) - 1));
+        for m in members.iter() {
+            in_crew[*m] = true;
         }
 
-        // todo!();
-        groups
+        used.insert(boat);
+        crews.push((boat.to_owned(), members));
     }
 
-    /// Internal deconflict method.
+    crews
+}
+
+impl NameList {
+    /// Swap the boats of two people, e.g. to move someone to another crew.
     ///
-    /// Pass in a list of names known to have a conflict and config,
-    /// Returns a (hopefully) deconflicted list of boats and if the operation
-    /// is successful.
+    /// Returns false if either name is not in the list, or boats have not been assigned.
+    pub fn swap_boats(&mut self, a: &str, b: &str) -> bool {
+        let position = |name: &str| self.names.iter().position(|n| n == name);
+        let (Some(i), Some(j)) = (position(a), position(b)) else {
+            return false;
+        };
+
+        match &mut self.boats {
+            Some(boats) => {
+                boats.swap(i, j);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Assign everyone their primary boats.
+    /// If deconflict is set to true, form crews and find the best assignment
+    /// without boats going over their seats.
     ///
-    async fn deconflict(
-        names: &Vec<String>,
-        config: Config,
-    ) -> (HashMap<&str, Option<String>>, bool) {
-        // identify names with the same boat
+    /// Returns the people who could not be given a boat when deconflicting.
+    pub async fn assign_boats(&mut self, deconflict: bool) -> Vec<Unseated> {
+        let config = get_config_type(self.date);
 
-        let read_lock = BOAT_ALLOCATIONS[config as usize].read().await;
+        let allo_lock = BOAT_ALLOCATIONS[config as usize].read().await;
 
-        let allo_set: HashSet<&str> = names
+        let allocations: Vec<(Option<String>, Option<String>)> = self
+            .names
             .iter()
-            .map(|n| {
-                let allo = read_lock.get(n).unwrap();
-                let (pri, alt) = { (allo.0.as_deref(), allo.1.as_deref()) };
-                match (pri, alt) {
-                    (None, None) => vec![],
-                    (None, Some(a)) => vec![a],
-                    (Some(p), None) => vec![p],
-                    (Some(p), Some(a)) => vec![p, a],
-                }
-            })
-            .collect::<Vec<Vec<&str>>>()
-            .concat()
-            .into_iter()
+            .map(|n| allo_lock.get(n).cloned().unwrap_or_default())
             .collect();
 
-        // actual allocated set of boats.
-        // the bool marks if the allocation is fixed: true => fixed, false => not fixed
-        let mut curr_allo: HashSet<&str> = Default::default();
-
-        // initialize empty map
-        // this map contains the lookup table of a name to a boat and some others.
-        // all values must be the Option::Some variant for
-        // deconflict to complete.
-        let mut res: BTreeMap<&str, Option<AllocResult>> =
-            names.iter().map(|n| (n.as_str(), None)).collect();
-
-        // deconflict possible (sort of)
-        if names.len() <= allo_set.len() {
-            // break when all have been allocated
-            while !res.iter().all(|(_, v)| v.is_some()) {
-                let unallo_name = res
-                    .iter()
-                    .find_map(|(n, allo)| match allo {
-                        Some(_) => None,
-                        None => Some(n),
-                    })
-                    .cloned()
-                    .unwrap();
-
-                debug_println!("performing allocation for {}", unallo_name);
-
-                let avail_opts = read_lock.get(unallo_name).unwrap();
-
-                // first allocation
-                if curr_allo.len() == 0 {
-                    let _cur_allo;
-                    match (avail_opts.0.as_deref(), avail_opts.1.as_deref()) {
-                        (None, None) => {
-                            _cur_allo = None;
-                            res.insert(&unallo_name, Some(AllocResult::from_absent()));
-                            // temp
-                        }
-                        (Some(pri), opt) => {
-                            _cur_allo = Some(pri);
-                            curr_allo.insert(pri);
-                            let mut allo = AllocResult::from_boat(pri.to_owned());
-
-                            if let None = opt {
-                                allo.lock = true;
-                            }
-
-                            res.insert(&unallo_name, Some(allo));
-                        }
-                        (None, Some(alt)) => {
-                            _cur_allo = Some(alt);
-                            curr_allo.insert(alt);
-
-                            let mut allo = AllocResult::from_boat(alt.to_owned());
-                            allo.lock = true;
-                            res.insert(&unallo_name, Some(allo));
-                        }
-                    }
-                    debug_println!("allocating {} to: {:?}", unallo_name, _cur_allo);
-                    continue;
-                }
-
-                // deconf logic here
-                match (avail_opts.0.as_deref(), avail_opts.1.as_deref()) {
-                    // no boat allocation given in config
-                    (None, None) => {
-                        debug_println!("no allocaions for {}", unallo_name);
-                        res.insert(&unallo_name, Some(AllocResult::from_absent()));
-                    }
-                    // primary boat only, dibs
-                    (Some(pri), None) => {
-                        debug_println!("primary boat available for {}", unallo_name);
-                        if !curr_allo.contains(pri) {
-                            curr_allo.insert(pri);
-                            res.insert(&unallo_name, Some(AllocResult::from_lock(pri.to_owned())));
-                        } else {
-                            // find the offending boat and replace with this
-                            let conflict = res
-                                .iter()
-                                .find_map(|(k, v)| match v {
-                                    Some(allocation) => {
-                                        if allocation.boat == pri {
-                                            Some((k.to_string(), allocation.clone()))
-                                        } else {
-                                            None
-                                        }
-                                    }
-                                    None => None,
-                                })
-                                .unwrap();
-
-                            let other = conflict.1;
-                            let mut allo = AllocResult::from_boat(pri.to_owned());
-
-                            // check if the conflicting person has dibs
-                            match other.lock {
-                                true => {
-                                    allo.fail = true;
-                                    allo.lock = true;
-                                }
-                                false => {
-                                    // kick the other guy out
-                                    let other_allo = res.get_mut(conflict.0.as_str()).unwrap();
-                                    *other_allo = None;
-                                    allo.lock = true
-                                }
-                            }
-
-                            res.insert(&unallo_name, Some(allo));
-                        }
-                    }
-                    // alternate boat only
-                    // this branch should not be taken
-                    // pri boats should exist before alt
-                    // people assigned only alt will be given the least priority
-                    (None, Some(alt)) => {
-                        debug_println!("alternate boat available for {}", unallo_name);
-                        if !curr_allo.contains(alt) {
-                            curr_allo.insert(alt);
-                            res.insert(&unallo_name, Some(AllocResult::from_boat(alt.to_owned())));
-                        } else {
-                            res.insert(&unallo_name, Some(AllocResult::from_fail(alt.to_owned())));
-                        }
-                    }
-                    // both options available
-                    (Some(pri), Some(alt)) => {
-                        debug_println!("both boats available for {}", unallo_name);
-                        // check conflicts
-                        match (curr_allo.contains(pri), curr_allo.contains(alt)) {
-                            // need to kick people out
-                            // new case needed when 2 people share the same primary boat
-                            // and they have 2 boats available
-                            (true, true) => {
-                                let pri_conflict = res
-                                    .iter()
-                                    .find_map(|(k, v)| match v {
-                                        Some(allocation) => {
-                                            if allocation.boat == pri {
-                                                Some((k.to_string(), allocation.clone()))
-                                            } else {
-                                                None
-                                            }
-                                        }
-                                        None => None,
-                                    })
-                                    .unwrap();
-
-                                let alt_conflict = res
-                                    .iter()
-                                    .find_map(|(k, v)| match v {
-                                        Some(allocation) => {
-                                            if allocation.boat == alt {
-                                                Some((k.to_string(), allocation.clone()))
-                                            } else {
-                                                None
-                                            }
-                                        }
-                                        None => None,
-                                    })
-                                    .unwrap();
-
-                                // which person do we kick out?
-                                let kicked;
-                                debug_println!(
-                                    "{} has conflicts: {:?}, {:?}",
-                                    unallo_name,
-                                    pri_conflict,
-                                    alt_conflict
-                                );
-                                match (pri_conflict.1.lock, alt_conflict.1.lock) {
-                                    (true, true) => {
-                                        debug_println!("no options left for {}", unallo_name);
-                                        // all locked
-                                        res.insert(
-                                            unallo_name,
-                                            Some(AllocResult::from_fail(pri.to_owned())),
-                                        );
-
-                                        kicked = None;
-                                    }
-                                    (true, false) => {
-                                        debug_println!("locking {} to {}", unallo_name, alt);
-                                        res.insert(
-                                            unallo_name,
-                                            Some(AllocResult::from_lock(alt.to_owned())),
-                                        );
-
-                                        kicked = Some(alt_conflict.0);
-                                    }
-                                    (false, true) => {
-                                        debug_println!("locking {} to {}", unallo_name, pri);
-                                        // curr_al
-                                        res.insert(
-                                            unallo_name,
-                                            Some(AllocResult::from_lock(pri.to_owned())),
-                                        );
-
-                                        kicked = Some(pri_conflict.0);
-                                    }
-                                    (false, false) => {
-                                        debug_println!(
-                                            "attempting to assign {} to {} without locking",
-                                            unallo_name,
-                                            pri
-                                        );
-                                        // this branch is causing problems again
-
-                                        res.insert(
-                                            unallo_name,
-                                            Some(AllocResult::from_boat(pri.to_owned())),
-                                        );
-
-                                        // get the options for the conflicting person
-                                        let conflict_opts = read_lock.get(&pri_conflict.0).unwrap();
-
-                                        debug_println!(
-                                            "conflicting person's options: {:?}",
-                                            conflict_opts
-                                        );
-
-                                        // if both people have the same primary boat
-                                        // someone has to lock the boats
-                                        if let Some(check_boat) = &conflict_opts.0 {
-                                            if check_boat == pri {
-                                                debug_println!("same primary boat for both people");
-                                                res.insert(
-                                                    unallo_name,
-                                                    Some(AllocResult::from_lock(pri.to_owned())),
-                                                );
-                                            }
-                                        }
-
-                                        kicked = Some(pri_conflict.0);
-                                    }
-                                }
-
-                                if let Some(to_kick) = kicked {
-                                    debug_println!(
-                                        "kicking out {}, taking in {}",
-                                        to_kick,
-                                        unallo_name
-                                    );
-                                    let other_allo = res.get_mut(to_kick.as_str()).unwrap();
-                                    *other_allo = None;
-                                }
-                            }
-
-                            (true, false) => {
-                                // res
-                                curr_allo.insert(alt);
-                                res.insert(
-                                    unallo_name,
-                                    Some(AllocResult::from_lock(alt.to_owned())),
-                                );
-                            }
-                            (false, true) => {
-                                curr_allo.insert(pri);
-                                res.insert(
-                                    unallo_name,
-                                    Some(AllocResult::from_lock(pri.to_owned())),
-                                );
-                            }
-                            (false, false) => {
-                                curr_allo.insert(pri);
-                                res.insert(
-                                    unallo_name,
-                                    Some(AllocResult::from_boat(pri.to_owned())),
-                                );
-                            }
-                        }
-                    }
-                }
-
-                debug_println!("curr iteration:\n{:#?}", res);
-            }
-
-            let successful = res.iter().all(|(_, v)| match v {
-                Some(allo) => match (allo.absent, allo.fail) {
-                    (false, false) => true,
-                    _ => false,
-                },
-                None => false,
-            });
+        let boats_lock = BOATS[config as usize].read().await;
+        let seats: HashMap<String, usize> = boats_lock
+            .values()
+            .map(|b| (b.name.clone(), if b.in_service { b.seats } else { 0 }))
+            .collect();
 
-            let actual_res = res
+        if !deconflict {
+            let assigned: Vec<Option<String>> = allocations
                 .into_iter()
-                .map(|(k, v)| {
-                    let allo = match v {
-                        Some(allocation) => {
-                            if allocation.absent {
-                                None
-                            } else {
-                                Some(allocation.boat)
-                            }
-                        }
-                        None => None,
-                    };
-
-                    (k, allo)
+                .map(|(pri, alt)| {
+                    [pri, alt]
+                        .into_iter()
+                        .flatten()
+                        .find(|b| seats_in(&seats, b) > 0)
                 })
                 .collect();
 
-            return (actual_res, successful);
-        } else {
-            // deconflict defo not possible
-            debug_println!(
-                "deconflict definitely not possible: num names = {}, possible boats = {}",
-                names.len(),
-                allo_set.len()
-            );
-            let allocated: HashMap<&str, Option<String>> = names
-                .iter()
-                .map(|n| {
-                    let (pri, alt) = read_lock.get(n).unwrap();
-                    let allocated = match (pri, alt) {
-                        (None, None) => None,
-                        (Some(p), _) => Some(p.to_owned()),
-                        (None, Some(a)) => Some(a.to_owned()),
-                    };
-
-                    (n.as_str(), allocated)
-                })
-                .collect();
-
-            return (allocated, false);
+            self.boat_seats = seats_of(&assigned, &seats);
+            self.boats = Some(assigned);
+            return vec![];
         }
+
+        let claimed: HashSet<&str> = allocations
+            .iter()
+            .flat_map(|(p, a)| [p.as_deref(), a.as_deref()])
+            .flatten()
+            .collect();
+        let mut pool: Vec<String> = boats_lock
+            .values()
+            .filter(|b| b.in_service && !claimed.contains(b.name.as_str()))
+            .map(|b| b.name.clone())
+            .collect();
+        pool.sort();
+
+        let partners_lock = PARTNERS[config as usize].read().await;
+        let assigned = deconflict_boats(&self.names, &allocations, &partners_lock, &pool, &seats);
+        debug_println!("deconf result: {:?}", assigned);
+
+        self.boat_seats = seats_of(&assigned, &seats);
+        self.boats = Some(assigned);
+        // unseated takes the locks again
+        drop(partners_lock);
+        drop(boats_lock);
+        drop(allo_lock);
+
+        self.unseated().await
     }
 
-    /// Mark matching elements in the boat list with an exclamation mark (!)
-    fn mark_matching(boat_list: &mut Vec<Option<String>>) {
-        const MARK: &str = "!";
-
-        let mut boat_set: HashSet<String> = Default::default();
-        let mut match_set: HashSet<String> = Default::default();
-
-        for boat in boat_list.iter() {
-            match boat {
-                Some(b) => {
-                    if boat_set.contains(b.as_str()) {
-                        match_set.insert(b.clone());
-                    } else {
-                        boat_set.insert(b.clone());
-                    }
-                }
-                None => (),
+    /// People without a boat, and why.
+    ///
+    /// Empty if boats have not been assigned.
+    pub async fn unseated(&self) -> Vec<Unseated> {
+        let Some(assigned) = &self.boats else {
+            return vec![];
+        };
+
+        let config = get_config_type(self.date);
+        let allo_lock = BOAT_ALLOCATIONS[config as usize].read().await;
+        let boats_lock = BOATS[config as usize].read().await;
+        let seats: HashMap<String, usize> = boats_lock
+            .values()
+            .map(|b| (b.name.clone(), if b.in_service { b.seats } else { 0 }))
+            .collect();
+
+        let mut crews: HashMap<&str, Vec<String>> = HashMap::new();
+        for (boat, name) in assigned.iter().zip(self.names.iter()) {
+            if let Some(b) = boat {
+                crews.entry(b).or_default().push(name.clone());
             }
         }
 
-        for boat in boat_list.iter_mut() {
-            match boat {
-                Some(b) => {
-                    if match_set.contains(b.as_str()) {
-                        *b = format!("{}{}", MARK, b);
-                    }
+        self.names
+            .iter()
+            .zip(assigned.iter())
+            .filter(|(_, boat)| boat.is_none())
+            .map(|(name, _)| {
+                let (pri, alt) = allo_lock.get(name).cloned().unwrap_or_default();
+                let own: BTreeSet<String> = [pri, alt].into_iter().flatten().collect();
+                let full = own
+                    .iter()
+                    .filter_map(|b| {
+                        crews
+                            .get(b.as_str())
+                            .map(|crew| (b.to_string(), crew.clone()))
+                    })
+                    .collect();
+                let out_of_service = own
+                    .iter()
+                    .filter(|b| seats_in(&seats, b) == 0)
+                    .map(|b| b.to_string())
+                    .collect();
+
+                let reason = match own.is_empty() {
+                    true => UnseatedReason::NoAllocation,
+                    false => UnseatedReason::Taken {
+                        full,
+                        out_of_service,
+                    },
+                };
+
+                Unseated {
+                    name: name.clone(),
+                    reason,
                 }
-                None => (),
-            }
+            })
+            .collect()
+    }
+}
+
+/// Form crews, then match everyone else to the remaining seats.
+///
+/// If the crews leave more people without a boat than matching everyone
+/// would, crews are dropped and everyone is matched.
+fn deconflict_boats(
+    names: &[String],
+    allocations: &[(Option<String>, Option<String>)],
+    partners: &HashMap<String, Vec<String>>,
+    pool: &[String],
+    seats: &HashMap<String, usize>,
+) -> Vec<Option<String>> {
+    let crews = form_crews(names, allocations, partners, pool, seats);
+    debug_println!("crews: {:?}", crews);
+
+    let mut remaining = seats.clone();
+    let mut assigned: Vec<Option<String>> = vec![None; names.len()];
+    for (boat, members) in crews {
+        remaining.insert(boat.clone(), seats_in(seats, &boat) - members.len());
+        for m in members {
+            assigned[m] = Some(boat.clone());
         }
     }
+
+    // everyone not in a crew
+    let others: Vec<usize> = (0..assigned.len())
+        .filter(|i| assigned[*i].is_none())
+        .collect();
+    let other_allocations: Vec<(Option<String>, Option<String>)> =
+        others.iter().map(|i| allocations[*i].clone()).collect();
+    for (idx, boat) in others
+        .into_iter()
+        .zip(match_boats(&other_allocations, pool, &remaining))
+    {
+        assigned[idx] = boat;
+    }
+
+    let without_crews = match_boats(allocations, pool, seats);
+    let seated = |a: &[Option<String>]| a.iter().flatten().count();
+    match seated(&assigned) < seated(&without_crews) {
+        true => {
+            debug_println!("crews leave people unseated, matching without crews");
+            without_crews
+        }
+        false => assigned,
+    }
+}
+
+/// Seats in each assigned boat
+fn seats_of(assigned: &[Option<String>], seats: &HashMap<String, usize>) -> HashMap<String, usize> {
+    assigned
+        .iter()
+        .flatten()
+        .map(|b| (b.clone(), seats_in(seats, b)))
+        .collect()
 }
 
 #[cfg(test)]
-#[allow(unexpected_cfgs)]
 mod tests {
+    use super::*;
 
-    use crate::{get_config_type, Config, NameList, BOAT_ALLOCATIONS};
+    fn alloc(pri: Option<&str>, alt: Option<&str>) -> (Option<String>, Option<String>) {
+        (pri.map(String::from), alt.map(String::from))
+    }
 
-    #[cfg(notset)]
-    #[tokio::test]
-    async fn assign_no_deconflict() {
-        crate::init().await;
+    /// (seated, primaries, alternates) of an assignment
+    fn score(
+        allocations: &[(Option<String>, Option<String>)],
+        assigned: &[Option<String>],
+    ) -> (usize, usize, usize) {
+        let mut score = (0, 0, 0);
+        for ((pri, alt), boat) in allocations.iter().zip(assigned) {
+            let Some(boat) = boat else {
+                continue;
+            };
+            score.0 += 1;
+            if Some(boat) == pri.as_ref() {
+                score.1 += 1;
+            } else if Some(boat) == alt.as_ref() {
+                score.2 += 1;
+            }
+        }
 
-        let mut name_list = crate::namelist(NaiveDate::from_ymd_opt(2023, 1, 14).unwrap(), false)
-            .await
-            .unwrap();
-        name_list.assign_boats(false).await;
+        score
+    }
+
+    /// Best score over every possible assignment
+    fn brute_force(
+        allocations: &[(Option<String>, Option<String>)],
+        pool: &[String],
+        seats: &HashMap<String, usize>,
+        assigned: &mut Vec<Option<String>>,
+    ) -> (usize, usize, usize) {
+        let idx = assigned.len();
+        if idx == allocations.len() {
+            return score(allocations, assigned);
+        }
+
+        assigned.push(None);
+        let mut best = brute_force(allocations, pool, seats, assigned);
+        assigned.pop();
+
+        for (boat, _) in options(&allocations[idx], pool, seats) {
+            let crew = assigned
+                .iter()
+                .filter(|b| b.as_deref() == Some(boat))
+                .count();
+            if crew >= seats_in(seats, boat) {
+                continue;
+            }
+
+            assigned.push(Some(boat.to_owned()));
+            best = best.max(brute_force(allocations, pool, seats, assigned));
+            assigned.pop();
+        }
 
-        println!("namelist with directly assigned boats:\n{}", name_list);
+        best
     }
 
-    /// Find matching groups against the whole config
-    #[tokio::test]
-    async fn test_find_matching_all() {
-        crate::init().await;
+    #[test]
+    fn test_match_boats() {
+        // the second person must give up their primary boat
+        let allocations = [
+            alloc(Some("A"), None),
+            alloc(Some("A"), Some("B")),
+            alloc(Some("C"), Some("B")),
+        ];
+        let no_seats = HashMap::new();
+        let assigned = match_boats(&allocations, &[], &no_seats);
+        assert_eq!(
+            assigned,
+            [Some("A".into()), Some("B".into()), Some("C".into())]
+        );
+
+        // no way to seat everyone
+        let allocations = [
+            alloc(Some("A"), Some("B")),
+            alloc(Some("A"), Some("B")),
+            alloc(Some("A"), None),
+            alloc(None, None),
+        ];
+        let assigned = match_boats(&allocations, &[], &no_seats);
+        assert_eq!(score(&allocations, &assigned), (2, 1, 1));
+        assert_eq!(assigned[3], None);
+
+        // pool boats are used last
+        let assigned = match_boats(&allocations, &["P".to_string()], &no_seats);
+        assert_eq!(score(&allocations, &assigned), (3, 1, 1));
+        assert_eq!(assigned[3], None);
+
+        // a K2 seats 2, and out of service boats are skipped
+        let seats: HashMap<String, usize> = [("A".to_string(), 2), ("B".to_string(), 0)].into();
+        let assigned = match_boats(&allocations, &[], &seats);
+        assert_eq!(assigned, [Some("A".into()), Some("A".into()), None, None]);
+    }
 
-        let config = Config::New;
+    /// Compare against brute force on small random rosters
+    #[test]
+    fn test_match_boats_brute_force() {
+        const BOATS: [&str; 5] = ["A", "B", "C", "D", "E"];
+        let mut rng = fastrand::Rng::with_seed(0x00c0ffee);
 
-        let read_lock: tokio::sync::RwLockReadGuard<
-            '_,
-            std::collections::HashMap<String, (Option<String>, Option<String>)>,
-        > = BOAT_ALLOCATIONS[config as usize].read().await;
-        let names: Vec<String> = read_lock.iter().map(|(k, _)| k.to_owned()).collect();
+        let random_boat = |rng: &mut fastrand::Rng| match rng.u8(0..4) {
+            0 => None,
+            _ => Some(BOATS[rng.usize(0..BOATS.len())]),
+        };
 
-        let groups = NameList::find_matching(&names, config).await;
+        for _ in 0..2000 {
+            let people = rng.usize(0..7);
+            let allocations: Vec<(Option<String>, Option<String>)> = (0..people)
+                .map(|_| alloc(random_boat(&mut rng), random_boat(&mut rng)))
+                .collect();
 
-        // println!("boat allocations:\n{:?}\n", read_lock);
-        println!("potential conflicting groups:\n{:?}\n", groups);
+            let claimed: HashSet<&str> = allocations
+                .iter()
+                .flat_map(|(p, a)| [p.as_deref(), a.as_deref()])
+                .flatten()
+                .collect();
+            let pool: Vec<String> = BOATS
+                .iter()
+                .filter(|b| !claimed.contains(*b) && rng.bool())
+                .map(|b| b.to_string())
+                .collect();
+
+            // out of service, K1 or K2
+            let seats: HashMap<String, usize> = BOATS
+                .iter()
+                .map(|b| (b.to_string(), rng.usize(0..3)))
+                .collect();
 
-        println!("performing deconf");
+            let assigned = match_boats(&allocations, &pool, &seats);
 
-        for group in groups.iter() {
-            println!("deconflicting group: {:?}", group);
-            let res = NameList::deconflict(group, config).await;
-            println!("deconf result: {:?}", res);
+            // everyone gets one of their options, and no boat has too many people
+            for (allocation, boat) in allocations.iter().zip(assigned.iter()) {
+                if let Some(boat) = boat {
+                    assert!(options(allocation, &pool, &seats)
+                        .iter()
+                        .any(|(b, _)| b == boat));
+
+                    let crew = assigned.iter().filter(|b| b.as_ref() == Some(boat)).count();
+                    assert!(crew <= seats[boat], "{} overfull in {:?}", boat, assigned);
+                }
+            }
+
+            let best = brute_force(&allocations, &pool, &seats, &mut vec![]);
+            assert_eq!(
+                score(&allocations, &assigned),
+                best,
+                "allocations: {:?}, pool: {:?}, seats: {:?}, assigned: {:?}",
+                allocations,
+                pool,
+                seats,
+                assigned
+            );
         }
     }
 
+    #[test]
+    fn test_form_crews() {
+        let names: Vec<String> = ["a", "b", "c", "d", "e", "f"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let allocations = [
+            alloc(Some("A1"), None),
+            alloc(Some("B1"), None),
+            alloc(Some("K2"), None),
+            alloc(None, None),
+            alloc(Some("E1"), None),
+            alloc(Some("F1"), None),
+        ];
+        let partners: HashMap<String, Vec<String>> = [
+            ("a", vec!["c", "b"]),
+            ("b", vec!["a"]),
+            ("d", vec!["e", "f", "nobody"]),
+            ("f", vec!["d"]),
+        ]
+        .into_iter()
+        .map(|(n, p)| (n.to_string(), p.into_iter().map(String::from).collect()))
+        .collect();
+        let pool = ["K4".to_string(), "P2".to_string()];
+        let seats: HashMap<String, usize> = [("K2", 2), ("K4", 4), ("P2", 2), ("A1", 1), ("B1", 1)]
+            .into_iter()
+            .map(|(b, s)| (b.to_string(), s))
+            .collect();
+
+        // a's partners do not fit in c's K2, so they take the K4 from the pool
+        // d's crew gets the remaining pool boat, leaving f out
+        let crews = form_crews(&names, &allocations, &partners, &pool, &seats);
+        assert_eq!(
+            crews,
+            [
+                ("K4".to_string(), vec![0, 2, 1]),
+                ("P2".to_string(), vec![3, 4]),
+            ]
+        );
+
+        // no multi-seat boats, no crews
+        let crews = form_crews(&names, &allocations, &partners, &[], &HashMap::new());
+        assert!(crews.is_empty());
+    }
+
+    #[test]
+    fn test_deconflict_boats() {
+        let names: Vec<String> = ["a", "b", "c"].into_iter().map(String::from).collect();
+        let partners: HashMap<String, Vec<String>> =
+            [("a".to_string(), vec!["b".to_string()])].into();
+        let pool = ["K2".to_string()];
+        let seats: HashMap<String, usize> = [("K2", 2), ("X", 0)]
+            .into_iter()
+            .map(|(b, s)| (b.to_string(), s))
+            .collect();
+
+        // a and b's crew would take the K2, the only boat c can use
+        let allocations = [
+            alloc(Some("A1"), None),
+            alloc(Some("B1"), None),
+            alloc(Some("X"), None),
+        ];
+        let assigned = deconflict_boats(&names, &allocations, &partners, &pool, &seats);
+        assert_eq!(
+            assigned,
+            [Some("A1".into()), Some("B1".into()), Some("K2".into())]
+        );
+
+        // c has a boat of their own, so the crew stays
+        let allocations = [
+            alloc(Some("A1"), None),
+            alloc(Some("B1"), None),
+            alloc(Some("C1"), None),
+        ];
+        let assigned = deconflict_boats(&names, &allocations, &partners, &pool, &seats);
+        assert_eq!(
+            assigned,
+            [Some("K2".into()), Some("K2".into()), Some("C1".into())]
+        );
+    }
+
+    #[test]
+    fn test_unseated_display() {
+        let unseated = Unseated {
+            name: "someone".to_string(),
+            reason: UnseatedReason::Taken {
+                full: vec![(
+                    "A".to_string(),
+                    vec!["other".to_string(), "another".to_string()],
+                )],
+                out_of_service: vec!["B".to_string()],
+            },
+        };
+        assert_eq!(
+            unseated.to_string(),
+            "someone: A taken by other and another, B out of service, no pool boats left"
+        );
+    }
+
     #[tokio::test]
-    async fn test_find_matching_today() {
-        crate::init().await;
+    async fn test_assign_boats_today() {
+        crate::init().await.unwrap();
 
-        // let date = NaiveDate::from_ymd_opt(2023, 1, 14).unwrap();
-        // let date = NaiveDate::from_ymd_opt(2023, 7, 13).unwrap();
         let date = chrono::Local::now().date_naive();
-        let config = get_config_type(date);
-        let mut name_list = crate::namelist(date, false, false).await.unwrap();
-        let deconf_res = name_list.assign_boats(true).await;
+        let mut name_list = crate::namelist(date, false, false).await.unwrap().unwrap();
+        let unseated = name_list.assign_boats(true).await;
+        for u in unseated {
+            let idx = name_list.names.iter().position(|n| *n == u.name).unwrap();
+            assert_eq!(name_list.boats.as_ref().unwrap()[idx], None);
+        }
         name_list.fill_prog(false).await.unwrap();
-        let groups = NameList::find_matching(&name_list.names, config).await;
 
-        println!("allocation success: {}", deconf_res);
-        println!("potential conflicting groups: {:?}", groups);
         println!("deconf boat allocation: {}", name_list);
 
         name_list.assign_boats(false).await;
@@ -0,0 +1,207 @@
+//! Boats in the club fleet, from the fleet sheet.
+//!
+//! Boats that are only named in the boat allocation columns are treated as
+//! single seaters in service.
+
+use std::{collections::HashMap, error::Error, fmt::Display, str::FromStr};
+
+use polars::prelude::DataFrame;
+
+use crate::dataframe_cell_to_string;
+use ntu_canoebot_config as config;
+
+/// Status values that mean a boat can be used
+const IN_SERVICE: [&str; 4] = ["", "ok", "available", "in service"];
+
+/// Boat class, K for kayaks and C for canoes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoatClass {
+    K1,
+    K2,
+    K4,
+    C1,
+    C2,
+}
+
+impl BoatClass {
+    /// Number of paddlers
+    pub fn seats(&self) -> usize {
+        match self {
+            BoatClass::K1 | BoatClass::C1 => 1,
+            BoatClass::K2 | BoatClass::C2 => 2,
+            BoatClass::K4 => 4,
+        }
+    }
+}
+
+impl FromStr for BoatClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let class = match s.trim().to_ascii_uppercase().as_str() {
+            "K1" => BoatClass::K1,
+            "K2" => BoatClass::K2,
+            "K4" => BoatClass::K4,
+            "C1" => BoatClass::C1,
+            "C2" => BoatClass::C2,
+            _ => return Err(format!("unknown boat class \"{}\"", s)),
+        };
+
+        Ok(class)
+    }
+}
+
+impl Display for BoatClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// A boat and its status
+#[derive(Clone, Debug, PartialEq)]
+pub struct Boat {
+    pub name: String,
+    /// [Option::None] for boats not in the fleet sheet
+    pub class: Option<BoatClass>,
+    pub seats: usize,
+    pub in_service: bool,
+    /// Status, as written in the fleet sheet
+    pub status: String,
+    pub notes: String,
+}
+
+impl Boat {
+    /// A boat that is not in the fleet sheet
+    pub fn unlisted(name: String) -> Self {
+        Self {
+            name,
+            class: None,
+            seats: 1,
+            in_service: true,
+            status: String::new(),
+            notes: String::new(),
+        }
+    }
+}
+
+/// Read boats from the fleet sheet.
+///
+/// Rows that cannot be read fully are still loaded where possible,
+/// with a problem for each. Row numbers are as shown in the sheet.
+pub(crate) fn fleet_from_df(
+    df: &DataFrame,
+) -> Result<(Vec<Boat>, Vec<String>), Box<dyn Error + Send + Sync>> {
+    let settings = config::settings();
+    let columns = &settings.sheetscraper.columns.fleet;
+
+    let cells = |col: &str| -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let cells = df
+            .column(col)
+            .map_err(|_| format!("missing column \"{}\"", col))?
+            .iter()
+            .map(dataframe_cell_to_string)
+            .collect();
+
+        Ok(cells)
+    };
+    let names = cells(&columns.name)?;
+    let classes = cells(&columns.class)?;
+    let seats = cells(&columns.seats)?;
+    let statuses = cells(&columns.status)?;
+    // notes are optional
+    let notes = cells(&columns.notes).unwrap_or_default();
+
+    let mut boats: Vec<Boat> = Vec::new();
+    let mut problems: Vec<String> = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+
+    for (idx, name) in names.iter().enumerate() {
+        let row = idx + 2;
+        if name.is_empty() {
+            continue;
+        }
+
+        if let Some(first) = seen.insert(name, row) {
+            problems.push(format!(
+                "row {}: \"{}\" is also on row {}",
+                row, name, first
+            ));
+            continue;
+        }
+
+        let class = match classes[idx].parse::<BoatClass>() {
+            Ok(class) => Some(class),
+            Err(e) => {
+                problems.push(format!("row {}: \"{}\" has {}", row, name, e));
+                None
+            }
+        };
+
+        let seats = match (seats[idx].parse::<usize>(), class) {
+            (Ok(s), _) if s > 0 => s,
+            (_, class) if seats[idx].is_empty() => class.map(|c| c.seats()).unwrap_or(1),
+            (_, class) => {
+                problems.push(format!(
+                    "row {}: \"{}\" has {} seats, which is not a positive number",
+                    row, name, seats[idx]
+                ));
+                class.map(|c| c.seats()).unwrap_or(1)
+            }
+        };
+
+        let status = statuses[idx].clone();
+        boats.push(Boat {
+            name: name.clone(),
+            class,
+            seats,
+            in_service: IN_SERVICE.contains(&status.to_ascii_lowercase().as_str()),
+            status,
+            notes: notes.get(idx).cloned().unwrap_or_default(),
+        });
+    }
+
+    Ok((boats, problems))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::*;
+
+    #[test]
+    fn test_fleet_from_df() {
+        let columns = config::settings().sheetscraper.columns.fleet.clone();
+        let df = DataFrame::new(vec![
+            Series::new(&columns.name, &["Bolt", "Twin", "Quad", "Bolt", "Old", ""]),
+            Series::new(&columns.class, &["k1", "K2", "K4", "K1", "X9", ""]),
+            Series::new(&columns.seats, &["", "", "3", "", "many", ""]),
+            Series::new(&columns.status, &["", "OK", "repair", "", "", ""]),
+            Series::new(&columns.notes, &["", "", "cracked hull", "", "", ""]),
+        ])
+        .unwrap();
+
+        let (boats, problems) = fleet_from_df(&df).unwrap();
+
+        assert_eq!(boats.len(), 4);
+        assert_eq!(boats[0].class, Some(BoatClass::K1));
+        assert_eq!(boats[1].seats, 2);
+        assert!(boats[1].in_service);
+        assert_eq!(boats[2].seats, 3);
+        assert!(!boats[2].in_service);
+        assert_eq!(boats[2].notes, "cracked hull");
+        assert_eq!(boats[3].class, None);
+        assert_eq!(boats[3].seats, 1);
+
+        assert_eq!(
+            problems,
+            [
+                "row 5: \"Bolt\" is also on row 2",
+                "row 6: \"Old\" has unknown boat class \"X9\"",
+                "row 6: \"Old\" has many seats, which is not a positive number",
+            ]
+        );
+
+        let df = df.drop(&columns.seats).unwrap();
+        assert!(fleet_from_df(&df).is_err());
+    }
+}
@@ -1,7 +1,9 @@
 //! Attendance fetch and formatting crate
 
 mod deconflict;
+mod fleet;
 pub mod logsheet;
+mod metered;
 mod update;
 
 use std::{
@@ -11,7 +13,9 @@ use std::{
 };
 
 use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use g_sheets::{ApiSource, Cell, DirSource, HttpSource, SheetSource};
 use lazy_static::lazy_static;
+use metered::MeteredSource;
 
 use ntu_canoebot_config as config;
 use ntu_canoebot_util::debug_println;
@@ -19,46 +23,54 @@ use polars::{
     prelude::{AnyValue, DataFrame},
     series::Series,
 };
+use serde::{Deserialize, Serialize};
 use tokio::sync::{RwLock, RwLockWriteGuard};
 
+pub use deconflict::{Unseated, UnseatedReason};
+pub use fleet::{Boat, BoatClass};
+pub use g_sheets::Error as SheetError;
 pub use logsheet::SUBMIT_LOCK;
-pub use update::init;
+pub use update::{check as check_configs, configs_loaded, init};
 
 const NO_ALLOCATION: &str = "NO BOAT";
+/// Marks a boat with more people than seats
+const OVERFULL: &str = "!";
 const IS_ATTENDING: &str = "Y";
 
-// most of these globals are initialized in init().
-lazy_static! {
-    /// Attendance sheet lookup
-    static ref ATTENDANCE_SHEETS: [Option<&'static str>; 2] = [
-        match config::SHEETSCRAPER_OLD_ATTENDANCE_SHEET.len() {
-            0 => None,
-            _ => Some(config::SHEETSCRAPER_OLD_ATTENDANCE_SHEET)
-        },
-        match config::SHEETSCRAPER_NEW_ATTENDANCE_SHEET.len() {
-            0 => None,
-            _ => Some(config::SHEETSCRAPER_NEW_ATTENDANCE_SHEET)
-        },
-    ];
+/// Sheet id, if filled in
+fn sheet_id(id: &str) -> Option<String> {
+    match id.len() {
+        0 => None,
+        _ => Some(id.to_string()),
+    }
+}
 
-    /// Program sheet lookup
-    static ref PROGRAM_SHEETS: [Option<&'static str>; 2] = [
-        match config::SHEETSCRAPER_OLD_PROGRAM_SHEET.len() {
-            0 => None,
-            _ => Some(config::SHEETSCRAPER_OLD_PROGRAM_SHEET)
-        },
-        match config::SHEETSCRAPER_NEW_PROGRAM_SHEET.len() {
-            0 => None,
-            _ => Some(config::SHEETSCRAPER_NEW_PROGRAM_SHEET)
-        },
-    ];
+/// Attendance sheet lookup, indexed by [Config]
+fn attendance_sheets() -> [Option<String>; 2] {
+    let settings = config::settings();
+    [
+        sheet_id(&settings.sheetscraper.old.attendance_sheet),
+        sheet_id(&settings.sheetscraper.new.attendance_sheet),
+    ]
+}
 
+/// Program sheet lookup, indexed by [Config]
+fn program_sheets() -> [Option<String>; 2] {
+    let settings = config::settings();
+    [
+        sheet_id(&settings.sheetscraper.old.program_sheet),
+        sheet_id(&settings.sheetscraper.new.program_sheet),
+    ]
+}
+
+// most of these globals are initialized in init().
+lazy_static! {
     /// Lookup table of names and their 1-star certificate status.
     /// Those marked as true have passed 1-star.
     static ref NAMES_CERTS: [RwLock<HashMap<String, bool>>; 2] = Default::default();
 
-    /// Set of all valid boats
-    static ref BOATS: [RwLock<HashSet<String>>; 2] = Default::default();
+    /// All boats, by name
+    static ref BOATS: [RwLock<HashMap<String, Boat>>; 2] = Default::default();
 
     /// Boat allocations hashmap
     /// Name -> Boat
@@ -84,6 +96,60 @@ lazy_static! {
     /// Set of names that are part of the EXCO
     pub static ref EXCO_NAMES: [RwLock<HashSet<String>>; 2] = Default::default();
 
+    /// All names in the configs sheet, in sheet order.
+    static ref NAMES: [RwLock<Vec<String>>; 2] = Default::default();
+
+    /// Telegram handles -> names.
+    /// Handles are stored in lowercase, without the leading `@`.
+    static ref TELEGRAM_HANDLES: [RwLock<HashMap<String, String>>; 2] = Default::default();
+
+    /// Short names -> short names of preferred crew partners, most preferred first
+    static ref PARTNERS: [RwLock<HashMap<String, Vec<String>>>; 2] = Default::default();
+
+    /// Where all sheets are read from.
+    /// Defaults to the backend specified in the config file.
+    static ref SHEET_SOURCE: RwLock<Arc<dyn SheetSource>> =
+        RwLock::new(Arc::new(MeteredSource(default_sheet_source())));
+}
+
+/// Construct the sheet source specified in `sheetscraper.source`
+fn default_sheet_source() -> Arc<dyn SheetSource> {
+    let source = &config::settings().sheetscraper.source;
+
+    match source.backend.as_str() {
+        "dir" => Arc::new(DirSource::new(&source.dir)),
+        "gviz" => Arc::new(HttpSource::default()),
+        "api" => match ApiSource::from_key_file(&source.api.key_file) {
+            Ok(source) => Arc::new(source),
+            Err(e) => {
+                log::error!(
+                    "sheetscraper.source.api.key_file is unusable: {}, using gviz",
+                    e
+                );
+                Arc::new(HttpSource::default())
+            }
+        },
+        other => {
+            log::error!(
+                "sheetscraper.source.backend \"{}\" is unknown, using gviz",
+                other
+            );
+            Arc::new(HttpSource::default())
+        }
+    }
+}
+
+/// Replace the source that attendance, program and config sheets are read from.
+///
+/// Caches are not cleared, so this should be called before [init].
+pub async fn set_sheet_source<S: SheetSource + 'static>(source: S) {
+    let mut lock = SHEET_SOURCE.write().await;
+    *lock = Arc::new(MeteredSource(Arc::new(source)));
+}
+
+/// Returns the current sheet source
+pub(crate) async fn sheet_source() -> Arc<dyn SheetSource> {
+    SHEET_SOURCE.read().await.clone()
 }
 
 /// For switching between configs
@@ -131,13 +197,14 @@ pub enum Session {
     Land,
 }
 
-/// This struct stores the indices of names to retain/exclude.
-/// The LSB corresponds to vec idx 0, with support of up to 64 names.
+/// This struct stores the indices of names to exclude, as a bitset.
+/// The LSB of the first word corresponds to vec idx 0, and the set grows
+/// to fit any number of names.
 ///
-/// By default, if all boats are allocated, this would be the same as setting
-/// the inner u64 to [u64::MAX].
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub struct BitIndices(u64);
+/// Trailing empty words are never stored, so equal sets compare equal and
+/// the default (empty) set serializes to almost nothing.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BitIndices(Vec<u64>);
 
 /// Namelist object
 #[derive(Clone, Debug)]
@@ -160,15 +227,16 @@ pub struct NameList {
     /// List of boats (if any) for a session
     pub boats: Option<Vec<Option<String>>>,
 
+    /// Seats in each assigned boat, for grouping crews
+    pub boat_seats: HashMap<String, usize>,
+
     pub prog: Option<String>,
 
     pub fetch_time: NaiveDateTime,
 }
 
-fn format_toml_time(time: toml::value::Time, offset: Option<Duration>) -> String {
-    let mut repr =
-        chrono::NaiveTime::from_hms_opt(time.hour as u32, time.minute as u32, time.second as u32)
-            .unwrap();
+fn format_time(time: NaiveTime, offset: Option<Duration>) -> String {
+    let mut repr = time;
 
     match offset {
         Some(val) => repr += val,
@@ -178,6 +246,25 @@ fn format_toml_time(time: toml::value::Time, offset: Option<Duration>) -> String
     repr.format("%H%M").to_string()
 }
 
+/// Order names so that people in the same boat are listed together,
+/// with their seat in the boat (starting from 1).
+fn crew_order(boats: &[Option<String>]) -> Vec<(usize, usize)> {
+    let mut order: Vec<(usize, usize)> = Vec::new();
+    for (idx, boat) in boats.iter().enumerate() {
+        if order.iter().any(|(i, _)| *i == idx) {
+            continue;
+        }
+        order.push((idx, 1));
+
+        if let Some(b) = boat {
+            let crew = (idx + 1..boats.len()).filter(|j| boats[*j].as_ref() == Some(b));
+            order.extend(crew.zip(2..));
+        }
+    }
+
+    order
+}
+
 /// Format the namelist for display
 /// If prog is [Option::Some], format according to config file
 impl Display for NameList {
@@ -188,16 +275,23 @@ impl Display for NameList {
             Some(_boats) => {
                 let padding = self.names.iter().map(|name| name.len()).max().unwrap_or(0);
 
-                self.names
-                    .iter()
-                    .zip(_boats.iter())
-                    .map(|(n, b)| {
-                        format!(
-                            "{:padding$}  {}",
-                            n,
-                            b.clone().unwrap_or(NO_ALLOCATION.to_string()),
-                            padding = padding
-                        )
+                crew_order(_boats)
+                    .into_iter()
+                    .map(|(idx, seat)| {
+                        let boat = match &_boats[idx] {
+                            Some(b) => {
+                                let seats = self.boat_seats.get(b).copied().unwrap_or(1);
+                                let crew = _boats.iter().filter(|o| o.as_ref() == Some(b)).count();
+                                match (crew > seats, seats > 1) {
+                                    (true, _) => format!("{}{}", OVERFULL, b),
+                                    (false, true) => format!("{} {}/{}", b, seat, seats),
+                                    (false, false) => b.clone(),
+                                }
+                            }
+                            None => NO_ALLOCATION.to_string(),
+                        };
+
+                        format!("{:padding$}  {}", self.names[idx], boat, padding = padding)
                     })
                     .collect()
             }
@@ -213,35 +307,34 @@ impl Display for NameList {
 
         let fetch = format!("fetched at {}", self.fetch_time.format("%H:%M:%S"));
 
+        let settings = config::settings();
+        let paddling = &settings.sheetscraper.paddling;
+
         // modify the paddling format if land
         let template = match &self.session {
-            Session::Paddling => config::SHEETSCRAPER_PADDLING_FORMAT
-                .replace(
-                    config::SHEETSCRAPER_PADDLING_SUB_HEADER,
-                    config::SHEETSCRAPER_PADDLING_HEADER_FORMAT,
-                )
-                .replace(
-                    config::SHEETSCRAPER_PADDLING_SUB_FOOTER,
-                    config::SHEETSCRAPER_PADDLING_FOOTER_FORMAT,
-                ),
-            Session::Land => config::SHEETSCRAPER_PADDLING_FORMAT
-                .replace(config::SHEETSCRAPER_PADDLING_SUB_HEADER, "\r")
-                .replace(config::SHEETSCRAPER_PADDLING_SUB_FOOTER, "\r"),
+            Session::Paddling => paddling
+                .format
+                .replace(&paddling.sub_header, &paddling.header_format)
+                .replace(&paddling.sub_footer, &paddling.footer_format),
+            Session::Land => paddling
+                .format
+                .replace(&paddling.sub_header, "\r")
+                .replace(&paddling.sub_footer, "\r"),
         };
 
         let res = match &self.prog {
             Some(prog) => {
-                // let template = config::SHEETSCRAPER_PADDLING_FORMAT;
-                let sub_session = config::SHEETSCRAPER_PADDLING_SUB_SESSION;
-                let sub_date = config::SHEETSCRAPER_PADDLING_SUB_DATE;
-                let sub_allo = config::SHEETSCRAPER_PADDLING_SUB_BOATALLO;
-                let sub_exclude = config::SHEETSCRAPER_PADDLING_SUB_EXCLUDE;
-                let sub_prog = config::SHEETSCRAPER_PADDLING_SUB_PROG;
-                let sub_fetch = config::SHEETSCRAPER_PADDLING_SUB_FETCH;
+                // let template = paddling.format;
+                let sub_session = paddling.sub_session.as_str();
+                let sub_date = paddling.sub_date.as_str();
+                let sub_allo = paddling.sub_boatallo.as_str();
+                let sub_exclude = paddling.sub_exclude.as_str();
+                let sub_prog = paddling.sub_prog.as_str();
+                let sub_fetch = paddling.sub_fetch.as_str();
                 // time substitutions
-                let sub_arrive = config::SHEETSCRAPER_PADDLING_SUB_ARRIVE;
-                let sub_finish = config::SHEETSCRAPER_PADDLING_SUB_FINISH;
-                let sub_start = config::SHEETSCRAPER_PADDLING_SUB_START;
+                let sub_arrive = paddling.sub_arrive.as_str();
+                let sub_finish = paddling.sub_finish.as_str();
+                let sub_start = paddling.sub_start.as_str();
 
                 let date = self.date.format("%A %d %b ").to_string()
                     + match self.time {
@@ -252,40 +345,17 @@ impl Display for NameList {
                 let allo = main_list.join("\n");
                 let excl = excluded_list.join("\n");
 
-                let offset = Duration::minutes(
-                    config::SHEETSCRAPER_PADDLING_TIMES_ARRIVE_TO_START_DELTA_MINS,
-                );
+                let offset = Duration::minutes(paddling.times.arrive_to_start_delta_mins);
 
-                let (arrive, start, finish) = match self.time {
-                    false => (
-                        format_toml_time(
-                            config::SHEETSCRAPER_PADDLING_TIMES_AM_ARRIVE.time.unwrap(),
-                            None,
-                        ),
-                        format_toml_time(
-                            config::SHEETSCRAPER_PADDLING_TIMES_AM_ARRIVE.time.unwrap(),
-                            Some(offset),
-                        ),
-                        format_toml_time(
-                            config::SHEETSCRAPER_PADDLING_TIMES_AM_FINISH.time.unwrap(),
-                            None,
-                        ),
-                    ),
-                    true => (
-                        format_toml_time(
-                            config::SHEETSCRAPER_PADDLING_TIMES_PM_ARRIVE.time.unwrap(),
-                            None,
-                        ),
-                        format_toml_time(
-                            config::SHEETSCRAPER_PADDLING_TIMES_PM_ARRIVE.time.unwrap(),
-                            Some(offset),
-                        ),
-                        format_toml_time(
-                            config::SHEETSCRAPER_PADDLING_TIMES_PM_FINISH.time.unwrap(),
-                            None,
-                        ),
-                    ),
+                let times = match self.time {
+                    false => &paddling.times.am,
+                    true => &paddling.times.pm,
                 };
+                let (arrive, start, finish) = (
+                    format_time(times.arrive, None),
+                    format_time(times.arrive, Some(offset)),
+                    format_time(times.finish, None),
+                );
 
                 let res = template
                     .replace(sub_session, format!("{:?}", self.session).as_str())
@@ -473,19 +543,16 @@ fn num_digits(mut number: i64) -> usize {
     res
 }
 
-impl Default for BitIndices {
-    fn default() -> Self {
-        Self(u64::MAX)
-    }
-}
-
 impl BitIndices {
     pub fn from_u64(value: u64) -> Self {
-        Self(value)
+        let mut indices = Self(vec![value]);
+        indices.trim();
+        indices
     }
 
+    /// The first 64 indices, as a bitmask
     pub fn to_u64(&self) -> u64 {
-        self.0
+        self.0.first().copied().unwrap_or_default()
     }
 
     /// Create an instance of `Self` from a single index.
@@ -497,16 +564,16 @@ impl BitIndices {
     /// let indices = BitIndices::from_index(10);
     ///
     /// assert_eq!(indices.to_u64(), 0b100_0000_0000);
+    /// assert_eq!(BitIndices::from_index(100).to_vec(), vec![100]);
     /// ```
-    pub fn from_index(mut index: usize) -> Self {
-        index &= 0b11_1111; // bounds check
-        Self(0b1 << index)
+    pub fn from_index(index: usize) -> Self {
+        let mut indices = Self::default();
+        indices.insert(index);
+        indices
     }
 
     /// Create an instance of `Self` from a slice.
     ///
-    /// This will only look at indices up to the bitsize (63)
-    ///
     /// ```
     /// use ntu_canoebot_attd::BitIndices;
     ///
@@ -516,20 +583,16 @@ impl BitIndices {
     ///
     /// assert_eq!(indices.to_u64(), 0b1001_0101_0110);
     /// ```
-    pub fn from_vec(mut v: Vec<usize>) -> Self {
-        v.sort();
-
-        let bit_iter = v.iter().filter(|idx| **idx < 64);
-
-        let mut inner = 0_u64;
-        for bit_idx in bit_iter {
-            inner |= 0b1 << bit_idx;
+    pub fn from_vec(v: Vec<usize>) -> Self {
+        let mut indices = Self::default();
+        for idx in v {
+            indices.insert(idx);
         }
 
-        Self(inner)
+        indices
     }
 
-    /// Converts the internal bitwise representation to a vector of indices.
+    /// Converts the internal bitwise representation to a sorted vector of indices.
     ///
     /// ```
     /// use ntu_canoebot_attd::BitIndices;
@@ -540,34 +603,52 @@ impl BitIndices {
     /// assert_eq!(converted, vec![4,5,6,7])
     /// ```
     pub fn to_vec(&self) -> Vec<usize> {
-        let mut copied = self.0;
         let mut indices = Vec::new();
-        let mut idx = 0;
 
-        // short circuit if spawned from default
-        if self.0 == u64::MAX {
-            return (0..64).into_iter().collect();
+        for (word_idx, word) in self.0.iter().enumerate() {
+            let mut copied = *word;
+            while copied != 0 {
+                let bit = copied.trailing_zeros() as usize;
+                indices.push(word_idx * 64 + bit);
+                copied &= copied - 1;
+            }
         }
 
-        loop {
-            if copied == 0 {
-                break;
-            }
+        indices
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        match self.0.get(index / 64) {
+            Some(word) => word & (0b1 << (index % 64)) != 0,
+            None => false,
+        }
+    }
 
-            let zeros = copied.trailing_zeros();
-            idx += zeros;
-            copied >>= zeros;
+    pub fn insert(&mut self, index: usize) {
+        let word_idx = index / 64;
+        if word_idx >= self.0.len() {
+            self.0.resize(word_idx + 1, 0);
+        }
 
-            match (copied & 0b1) != 0 {
-                true => indices.push(idx as usize),
-                false => (),
-            }
+        self.0[word_idx] |= 0b1 << (index % 64);
+    }
 
-            copied >>= 1;
-            idx += 1;
+    pub fn remove(&mut self, index: usize) {
+        if let Some(word) = self.0.get_mut(index / 64) {
+            *word &= !(0b1 << (index % 64));
+            self.trim();
         }
+    }
 
-        indices
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Drop trailing empty words
+    fn trim(&mut self) {
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
     }
 }
 
@@ -580,14 +661,15 @@ impl NameList {
             names: Default::default(),
             excluded_names: Default::default(),
             boats: Default::default(),
+            boat_seats: Default::default(),
             prog: Default::default(),
             fetch_time: chrono::Local::now().naive_local(),
         }
     }
 
     /// Get namelist to fetch the prog for the day, for a given time slot
-    pub async fn fill_prog(&mut self, time_slot: bool) -> Result<(), ()> {
-        let prog_sheet = training_prog(self.date).await;
+    pub async fn fill_prog(&mut self, time_slot: bool) -> Result<(), SheetError> {
+        let prog_sheet = training_prog(self.date).await?;
 
         self.prog = Some(
             prog_sheet
@@ -598,35 +680,17 @@ impl NameList {
         Ok(())
     }
 
-    /// Retain names that have an index. Move all the rest to
-    /// the exclude list.
-    pub fn exclude(&mut self, exclude_idx: BitIndices) {
-        // filter out indices within range
-        let indices: Vec<usize> = exclude_idx
-            .to_vec()
-            .into_iter()
-            .filter(|idx| *idx < self.names.len())
-            .collect();
-
-        let filtered: Vec<_> = self
+    /// Move names at the given indices to the exclude list,
+    /// retaining the rest. Indices out of range are ignored.
+    pub fn exclude(&mut self, excluded: &BitIndices) {
+        let (excluded, filtered): (Vec<_>, Vec<_>) = self
             .names
             .iter()
             .enumerate()
-            .filter_map(|(idx, name)| match indices.binary_search(&idx).is_ok() {
-                true => Some(name.to_owned()),
-                false => None,
-            })
-            .collect();
+            .partition(|(idx, _)| excluded.contains(*idx));
 
-        let excluded: Vec<_> = self
-            .names
-            .iter()
-            .enumerate()
-            .filter_map(|(idx, name)| match !indices.binary_search(&idx).is_ok() {
-                true => Some(name.to_owned()),
-                false => None,
-            })
-            .collect();
+        let filtered: Vec<String> = filtered.into_iter().map(|(_, name)| name.clone()).collect();
+        let excluded: Vec<String> = excluded.into_iter().map(|(_, name)| name.clone()).collect();
 
         self.names = filtered;
         self.excluded_names = excluded;
@@ -637,7 +701,13 @@ impl TryFrom<DataFrame> for AttdSheet {
     type Error = ();
     fn try_from(value: DataFrame) -> Result<Self, Self::Error> {
         // verify start date
-        let start_date = &value[1].get(0).ok().ok_or(())?;
+        let start_date = &value
+            .get_columns()
+            .get(1)
+            .ok_or(())?
+            .get(0)
+            .ok()
+            .ok_or(())?;
 
         let start_date = dataframe_cell_to_string(start_date.to_owned());
 
@@ -647,7 +717,7 @@ impl TryFrom<DataFrame> for AttdSheet {
         let cols_to_drop: Vec<&str> = value
             .get_column_names()
             .iter()
-            .zip((0..config::SHEETSCRAPER_LAYOUT_ATTD_FENCING_LEFT).into_iter())
+            .zip((0..config::settings().sheetscraper.layout.attd.fencing.left).into_iter())
             .map(|(col, _)| *col)
             .collect();
 
@@ -656,19 +726,24 @@ impl TryFrom<DataFrame> for AttdSheet {
         let inter_1 = value.drop_many(&cols_to_drop);
 
         let length = inter_1.iter().map(|series| series.len()).max().ok_or(())?;
-        let inter_2 = inter_1.slice(config::SHEETSCRAPER_LAYOUT_ATTD_FENCING_TOP - 1, length);
+        let fencing_top = config::settings().sheetscraper.layout.attd.fencing.top as i64;
+        let inter_2 = inter_1.slice(fencing_top - 1, length);
         let name_column = &inter_2[0];
 
         // remove non-data columns
+        let pre_padding = config::settings()
+            .sheetscraper
+            .layout
+            .attd
+            .block_pre_padding;
         let filtered: Vec<Series> = inter_2
             .iter()
             .enumerate()
             .skip(1) // skip names column
             .filter_map(|(idx, col)| {
-                let window_index =
-                    (idx - 1) % (14 + config::SHEETSCRAPER_LAYOUT_ATTD_BLOCK_PRE_PADDING) as usize;
+                let window_index = (idx - 1) % (14 + pre_padding);
 
-                if window_index < config::SHEETSCRAPER_LAYOUT_ATTD_BLOCK_PRE_PADDING as usize {
+                if window_index < pre_padding {
                     None
                 } else {
                     Some(col.to_owned())
@@ -682,9 +757,10 @@ impl TryFrom<DataFrame> for AttdSheet {
 
         debug_println!("{}", filtered);
 
-        let start = NaiveDate::parse_from_str(&start_date, config::SHEETSCRAPER_DATE_FORMAT)
-            .ok()
-            .ok_or(())?;
+        let start =
+            NaiveDate::parse_from_str(&start_date, &config::settings().sheetscraper.date_format)
+                .ok()
+                .ok_or(())?;
         let days_in_sheet = calculate_sheet_name(start).1;
 
         debug_println!("filtered cols: {}", filtered.get_columns().len());
@@ -717,16 +793,7 @@ impl AttdSheet {
     /// Set `time_slot` to `false` for morning sessions.
     /// Set `time_slot` to `true` for afternoon sessions.
     pub async fn get_names(&self, date: NaiveDate, time_slot: bool) -> Option<NameList> {
-        if date < self.start || date > self.end {
-            return None;
-        }
-
-        let delta = (date - self.start).num_days() as usize;
-        let offset = if time_slot {
-            delta * 2 + 2
-        } else {
-            delta * 2 + 1
-        };
+        let offset = self.data_offset(date, time_slot)?;
 
         let names = &self
             .data
@@ -738,7 +805,7 @@ impl AttdSheet {
             .ok()?;
 
         let read_lock = {
-            let _change_over = config::SHEETSCRAPER_CHANGEOVER_DATE.date.unwrap();
+            let _change_over = config::settings().sheetscraper.changeover_date;
             let config = get_config_type(date);
 
             debug_println!(
@@ -755,15 +822,7 @@ impl AttdSheet {
             "selected col with offset {} (col {}): {}",
             offset,
             // reconstruct the original column idx, inside the shared online sheet.
-            AttdSheet::col_idx_to_excel_alphabetic(
-                offset
-                    + {
-                        ((offset - 1) / 14 + 1)
-                            * config::SHEETSCRAPER_LAYOUT_ATTD_BLOCK_PRE_PADDING as usize
-                    }
-                    + config::SHEETSCRAPER_LAYOUT_ATTD_FENCING_LEFT as usize
-                    + 1
-            ),
+            AttdSheet::col_idx_to_excel_alphabetic(AttdSheet::sheet_col(offset) + 1),
             selected
         );
         let filtered: Vec<String> = selected
@@ -795,6 +854,7 @@ impl AttdSheet {
             names: filtered,
             excluded_names: Default::default(),
             boats: None,
+            boat_seats: Default::default(),
             prog: None,
             fetch_time: self.fetch_time,
         })
@@ -809,6 +869,66 @@ impl AttdSheet {
         }
     }
 
+    /// Column index inside the filtered data for a particular date and time.
+    /// Column 0 contains names.
+    fn data_offset(&self, date: NaiveDate, time_slot: bool) -> Option<usize> {
+        if date < self.start || date > self.end {
+            return None;
+        }
+
+        let delta = (date - self.start).num_days() as usize;
+        let offset = if time_slot {
+            delta * 2 + 2
+        } else {
+            delta * 2 + 1
+        };
+
+        Some(offset)
+    }
+
+    /// Convert a column index inside the filtered data back to the
+    /// 0-indexed column inside the online sheet.
+    fn sheet_col(offset: usize) -> usize {
+        let layout = &config::settings().sheetscraper.layout.attd;
+
+        offset + ((offset - 1) / 14 + 1) * layout.block_pre_padding + layout.fencing.left
+    }
+
+    /// Returns the cell inside the online sheet that holds the attendance
+    /// of `name` for a particular date and time.
+    ///
+    /// `name` can be either the full name in the sheet, or its shortened form.
+    /// Returns [Option::None] if the date is outside the sheet range, or the name is not found.
+    pub async fn cell_position(
+        &self,
+        name: &str,
+        date: NaiveDate,
+        time_slot: bool,
+    ) -> Option<Cell> {
+        let offset = self.data_offset(date, time_slot)?;
+        if offset >= self.data.width() {
+            return None;
+        }
+
+        let names = self
+            .data
+            .column(self.data.get_column_names().first()?)
+            .ok()?;
+
+        let read_lock = SHORTENED_NAMES[get_config_type(date) as usize].read().await;
+
+        let row = names.iter().position(|cell| {
+            let full_name = dataframe_cell_to_string(cell);
+            full_name == name || read_lock.get(&full_name).is_some_and(|short| short == name)
+        })?;
+
+        // data starts after the header row and the fenced rows
+        Some(Cell::new(
+            row + config::settings().sheetscraper.layout.attd.fencing.top,
+            AttdSheet::sheet_col(offset),
+        ))
+    }
+
     /// Convert a column number to the human-readable excel column indices.
     ///
     /// 1-indexed instead of 0-indexed, so 1 maps to A, 26 maps to Z, etc.
@@ -844,7 +964,7 @@ impl TryFrom<DataFrame> for ProgSheet {
 
         let (sheet_start, sheet_end) = {
             let date_col = value
-                .column(config::SHEETSCRAPER_COLUMNS_PROG_DATE)
+                .column(&config::settings().sheetscraper.columns.prog.date)
                 .map_err(|_| ())?;
 
             // debug_println!()
@@ -855,11 +975,10 @@ impl TryFrom<DataFrame> for ProgSheet {
             debug_println!("start: {}", start);
             debug_println!("end: {}", end);
 
+            let format = &config::settings().sheetscraper.date_format_prog;
             (
-                NaiveDate::parse_from_str(&start, config::SHEETSCRAPER_DATE_FORMAT_PROG)
-                    .map_err(|_| ())?,
-                NaiveDate::parse_from_str(&end, config::SHEETSCRAPER_DATE_FORMAT_PROG)
-                    .map_err(|_| ())?,
+                NaiveDate::parse_from_str(&start, format).map_err(|_| ())?,
+                NaiveDate::parse_from_str(&end, format).map_err(|_| ())?,
             )
         };
 
@@ -891,10 +1010,11 @@ impl ProgSheet {
         debug_println!("input date: {}", date);
         debug_println!("prog sheet row delta: {}", delta);
 
+        let settings = config::settings();
         let col = if time_slot {
-            config::SHEETSCRAPER_COLUMNS_PROG_PM
+            &settings.sheetscraper.columns.prog.pm
         } else {
-            config::SHEETSCRAPER_COLUMNS_PROG_AM
+            &settings.sheetscraper.columns.prog.am
         };
 
         let col = self.data.column(col).ok()?;
@@ -934,12 +1054,7 @@ impl ProgSheet {
 
 /// Checks which config to use, by comparing the given date and changeover date.
 pub fn get_config_type(date: NaiveDate) -> Config {
-    let change = config::SHEETSCRAPER_CHANGEOVER_DATE.date.unwrap();
-
-    if date
-        >= NaiveDate::from_ymd_opt(change.year.into(), change.month.into(), change.day.into())
-            .unwrap()
-    {
+    if date >= config::settings().sheetscraper.changeover_date {
         Config::New
     } else {
         Config::Old
@@ -1007,7 +1122,9 @@ pub fn calculate_last_day(date: NaiveDate) -> NaiveDate {
 pub fn calculate_sheet_name(date: NaiveDate) -> (String, i64) {
     let (start, end) = calculate_month_start_end(date);
     let num_days = (end - start).num_days() + 1;
-    let sheet_name = end.format(config::SHEETSCRAPER_SHEET_LABEL).to_string();
+    let sheet_name = end
+        .format(&config::settings().sheetscraper.sheet_label)
+        .to_string();
 
     (sheet_name, num_days)
 }
@@ -1048,15 +1165,27 @@ pub fn calculate_land_sheet_name(date: NaiveDate) -> String {
 }
 
 /// Convert an [AnyValue] type to a string.
+/// Empty cells are empty strings.
 fn dataframe_cell_to_string(cell: AnyValue) -> String {
-    cell.to_string().trim_matches('\"').to_string()
+    match cell {
+        AnyValue::Null => String::new(),
+        cell => cell.to_string().trim_matches('\"').to_string(),
+    }
 }
 
 /// Return the namelist struct. Accesses cache if hit.
 /// Accesses the freshie sheet if `freshies` is set to `true`.
-pub async fn namelist(date: NaiveDate, time_slot: bool, freshies: bool) -> Option<NameList> {
+///
+/// Freshie sheets are optional, so a missing freshie sheet is treated as empty.
+/// Returns [Option::None] if the date is outside the fetched sheet.
+pub async fn namelist(
+    date: NaiveDate,
+    time_slot: bool,
+    freshies: bool,
+) -> Result<Option<NameList>, SheetError> {
     let config = get_config_type(date);
-    let sheet_id = ATTENDANCE_SHEETS[config as usize];
+    let attendance = attendance_sheets();
+    let sheet_id = attendance[config as usize].as_deref();
 
     debug_println!("date: {}\nusing {:?} config", date, config);
 
@@ -1076,18 +1205,26 @@ pub async fn namelist(date: NaiveDate, time_slot: bool, freshies: bool) -> Optio
                 let s = {
                     match sheet_id {
                         Some(id) => {
-                            let df = g_sheets::get_as_dataframe(
-                                id,
-                                Some(format!(
-                                    "{}{}",
-                                    sheet_name,
-                                    config::SHEETSCRAPER_PADDLING_FRESHIE_SHEET_SUFFIX
-                                )),
-                            )
-                            .await;
-
-                            let s: AttdSheet = df.try_into().unwrap_or(AttdSheet::from_date(date));
-                            s
+                            let res = sheet_source()
+                                .await
+                                .get_as_dataframe(
+                                    id,
+                                    Some(&format!(
+                                        "{}{}",
+                                        sheet_name,
+                                        config::settings()
+                                            .sheetscraper
+                                            .paddling
+                                            .freshie_sheet_suffix
+                                    )),
+                                )
+                                .await;
+
+                            match res {
+                                Ok(df) => df.try_into().unwrap_or(AttdSheet::from_date(date)),
+                                Err(e) if is_missing_sheet(&e) => AttdSheet::from_date(date),
+                                Err(e) => return Err(e),
+                            }
                         }
                         None => AttdSheet::from_date(date),
                     }
@@ -1116,7 +1253,10 @@ pub async fn namelist(date: NaiveDate, time_slot: bool, freshies: bool) -> Optio
                 let sheet = {
                     match sheet_id {
                         Some(id) => {
-                            let df = g_sheets::get_as_dataframe(id, Some(sheet_name)).await;
+                            let df = sheet_source()
+                                .await
+                                .get_as_dataframe(id, Some(&sheet_name))
+                                .await?;
                             let s: AttdSheet = df.try_into().unwrap_or(AttdSheet::from_date(date));
                             s
                         }
@@ -1134,14 +1274,20 @@ pub async fn namelist(date: NaiveDate, time_slot: bool, freshies: bool) -> Optio
 
     debug_println!("sheet from: {} to {}", sheet.start, sheet.end);
 
-    sheet.get_names(date, time_slot).await
+    Ok(sheet.get_names(date, time_slot).await)
+}
+
+/// Checks if an error means that a sheet does not exist or has nothing in it.
+fn is_missing_sheet(err: &SheetError) -> bool {
+    matches!(err, SheetError::SheetNotFound(_) | SheetError::Empty)
 }
 
 /// Finds the training program for a given date. Accesses the cache
 /// if hit.
-pub async fn training_prog(date: NaiveDate) -> ProgSheet {
+pub async fn training_prog(date: NaiveDate) -> Result<ProgSheet, SheetError> {
     let config = get_config_type(date);
-    let sheet_id = PROGRAM_SHEETS[config as usize];
+    let program = program_sheets();
+    let sheet_id = program[config as usize].as_deref();
 
     let read_lock = PROG_CACHE.read().await;
     let prog_sheet = if read_lock.contains_date(date) {
@@ -1149,7 +1295,7 @@ pub async fn training_prog(date: NaiveDate) -> ProgSheet {
     } else {
         match sheet_id {
             Some(id) => {
-                let df = g_sheets::get_as_dataframe(id, Option::<&str>::None).await;
+                let df = sheet_source().await.get_as_dataframe(id, None).await?;
                 let sheet: ProgSheet = df.try_into().unwrap_or(ProgSheet::from_date(date));
                 sheet
             }
@@ -1157,12 +1303,12 @@ pub async fn training_prog(date: NaiveDate) -> ProgSheet {
         }
     };
 
-    prog_sheet
+    Ok(prog_sheet)
 }
 
 /// Returns the attendance breakdown for a particular week,
 /// from Mon to Sun
-pub async fn breakdown(date: NaiveDate, time_slot: bool) -> Breakdown {
+pub async fn breakdown(date: NaiveDate, time_slot: bool) -> Result<Breakdown, SheetError> {
     let cache_lock = SHEET_CACHE.read().await;
     let mut wand_lock = SHEET_CACHE_WANDERING.write().await;
     let sheet = {
@@ -1176,9 +1322,12 @@ pub async fn breakdown(date: NaiveDate, time_slot: bool) -> Breakdown {
                 let config = get_config_type(date);
                 let sheet_name = calculate_sheet_name(date).0;
 
-                match ATTENDANCE_SHEETS[config as usize] {
+                match attendance_sheets()[config as usize].as_deref() {
                     Some(sheet) => {
-                        let df = g_sheets::get_as_dataframe(sheet, Some(sheet_name)).await;
+                        let df = sheet_source()
+                            .await
+                            .get_as_dataframe(sheet, Some(&sheet_name))
+                            .await?;
                         let sheet: AttdSheet = df.try_into().unwrap_or(AttdSheet::from_date(date));
                         update_attd_cache(sheet, &mut wand_lock);
                         wand_lock.clone()
@@ -1230,7 +1379,7 @@ pub async fn breakdown(date: NaiveDate, time_slot: bool) -> Breakdown {
         breakdown.num_exco[idx] = num_exco as u16;
     }
 
-    breakdown
+    Ok(breakdown)
 }
 
 /// Returns the land program, taking names from the gym sheet.
@@ -1238,28 +1387,36 @@ pub async fn breakdown(date: NaiveDate, time_slot: bool) -> Breakdown {
 /// No cache for this one, it's barely used.
 ///
 /// All data processing is performed inside here.
-pub async fn land(date: NaiveDate, freshies: bool) -> NameList {
+pub async fn land(date: NaiveDate, freshies: bool) -> Result<NameList, SheetError> {
     let config = get_config_type(date);
     let sheet_name = match freshies {
         true => format!(
             "{}{}",
             calculate_land_sheet_name(date),
-            config::SHEETSCRAPER_PADDLING_FRESHIE_SHEET_SUFFIX
+            config::settings()
+                .sheetscraper
+                .paddling
+                .freshie_sheet_suffix
         ),
         false => calculate_land_sheet_name(date),
     };
 
     debug_println!("land sheet name: {}", sheet_name);
-    let df = match ATTENDANCE_SHEETS[config as usize] {
-        Some(sheet_id) => g_sheets::get_as_dataframe(sheet_id, Some(sheet_name)).await,
-        None => return NameList::from_date_time(date, true),
+    let df = match attendance_sheets()[config as usize].as_deref() {
+        Some(sheet_id) => {
+            sheet_source()
+                .await
+                .get_as_dataframe(sheet_id, Some(&sheet_name))
+                .await?
+        }
+        None => return Ok(NameList::from_date_time(date, true)),
     };
 
     // trim sides of data
     let cols_to_drop: Vec<&str> = df
         .get_column_names()
         .iter()
-        .zip((0..config::SHEETSCRAPER_LAYOUT_LAND_FENCING_LEFT).into_iter())
+        .zip((0..config::settings().sheetscraper.layout.land.fencing.left).into_iter())
         .map(|(col, _)| *col)
         .collect();
 
@@ -1270,7 +1427,7 @@ pub async fn land(date: NaiveDate, freshies: bool) -> NameList {
     // let length = df_fenced.iter().map(|series| series.len()).max().unwrap_or(0);
 
     // debug_println!("{}", inter_1);
-    // let df_fenced = inter_1.slice(config::SHEETSCRAPER_LAYOUT_LAND_FENCING_TOP, length);
+    // let df_fenced = inter_1.slice(layout.land.fencing.top, length);
     let name_column = &df_fenced[0];
 
     // debug_println!("{}", df_fenced);
@@ -1282,9 +1439,17 @@ pub async fn land(date: NaiveDate, freshies: bool) -> NameList {
 
     debug_println!("offset: {}", offset);
 
-    let attd_column = df_fenced
-        .column(df_fenced.get_column_names().get(offset as usize).unwrap())
-        .unwrap();
+    let attd_column = match df_fenced
+        .get_column_names()
+        .get(offset as usize)
+        .and_then(|col| df_fenced.column(col).ok())
+    {
+        Some(col) => col,
+        None => {
+            log::error!("land sheet {} is missing column {}", sheet_name, offset);
+            return Ok(NameList::from_date_time(date, true));
+        }
+    };
 
     debug_println!("{}", attd_column);
 
@@ -1310,20 +1475,81 @@ pub async fn land(date: NaiveDate, freshies: bool) -> NameList {
         })
         .collect();
 
-    NameList {
+    Ok(NameList {
         date,
         session: Session::Land,
         time: true,
         names: filtered,
         excluded_names: Default::default(),
         boats: None,
+        boat_seats: Default::default(),
         prog: None,
         fetch_time: chrono::Local::now().naive_local(),
-    }
+    })
 
     // println!("{}", df);
 }
 
+/// Mark or unmark someone's attendance for a particular date and time.
+///
+/// Writes directly to the monthly attendance sheet, then refreshes
+/// whichever cache holds that date.
+/// The cache is left untouched if the sheet cannot be read back.
+/// The sheet backend must support writing.
+pub async fn mark_attendance(
+    name: &str,
+    date: NaiveDate,
+    time_slot: bool,
+    attending: bool,
+) -> Result<(), SheetError> {
+    let (sheet_name, _) = calculate_sheet_name(date);
+    let sheet_id = attendance_sheets()[get_config_type(date) as usize]
+        .clone()
+        .ok_or_else(|| SheetError::SheetNotFound(sheet_name.clone()))?;
+
+    let source = sheet_source().await;
+    let writer = source.as_writer().ok_or(SheetError::ReadOnly)?;
+
+    let df = source
+        .get_as_dataframe(&sheet_id, Some(&sheet_name))
+        .await?;
+    let sheet: AttdSheet = df
+        .try_into()
+        .map_err(|_| SheetError::Layout(sheet_name.clone()))?;
+
+    let cell = sheet
+        .cell_position(name, date, time_slot)
+        .await
+        .ok_or_else(|| SheetError::NameNotFound(name.to_string()))?;
+
+    debug_println!("marking {} at {}!{}", name, sheet_name, cell);
+
+    let mark = if attending { IS_ATTENDING } else { "" };
+    writer
+        .update_cell(&sheet_id, &sheet_name, cell, mark)
+        .await?;
+
+    // fetch again, so the cache reflects the change
+    let df = source
+        .get_as_dataframe(&sheet_id, Some(&sheet_name))
+        .await?;
+    let Ok(sheet) = AttdSheet::try_from(df) else {
+        log::warn!("unable to parse {} after marking attendance", sheet_name);
+        return Ok(());
+    };
+
+    let mut write_lock = SHEET_CACHE.write().await;
+    if write_lock.contains_date(date) {
+        update_attd_cache(sheet, &mut write_lock);
+    } else {
+        drop(write_lock);
+        let mut write_wandering = SHEET_CACHE_WANDERING.write().await;
+        update_attd_cache(sheet, &mut write_wandering);
+    }
+
+    Ok(())
+}
+
 fn update_attd_cache(sheet: AttdSheet, cache_lock: &mut RwLockWriteGuard<'_, AttdSheet>) {
     cache_lock.start = sheet.start;
     cache_lock.end = sheet.end;
@@ -1331,8 +1557,10 @@ fn update_attd_cache(sheet: AttdSheet, cache_lock: &mut RwLockWriteGuard<'_, Att
     cache_lock.fetch_time = sheet.fetch_time;
 }
 
-/// Refresh the main cached and wandering sheet
-pub async fn refresh_attd_sheet_cache(force: bool) -> Result<(), ()> {
+/// Refresh the main cached and wandering sheet.
+///
+/// Caches are left untouched if their sheet could not be fetched.
+pub async fn refresh_attd_sheet_cache(force: bool) -> Result<(), SheetError> {
     debug_println!(
         "refreshing attd sheet cache at: {}",
         chrono::Local::now().time()
@@ -1343,7 +1571,7 @@ pub async fn refresh_attd_sheet_cache(force: bool) -> Result<(), ()> {
 
     // check if cache lifetime limit has exceeded
     if (chrono::Local::now().naive_local() - read_cache.fetch_time).num_minutes()
-        < config::SHEETSCRAPER_CACHE_ATTD
+        < config::settings().sheetscraper.cache.attd
     {
         if !force {
             return Ok(());
@@ -1351,8 +1579,9 @@ pub async fn refresh_attd_sheet_cache(force: bool) -> Result<(), ()> {
     }
 
     drop(read_cache);
+    let attendance = attendance_sheets();
     let config = get_config_type(today);
-    let sheet_id = ATTENDANCE_SHEETS[config as usize];
+    let sheet_id = attendance[config as usize].clone();
     let (sheet_name, _) = calculate_sheet_name(today);
 
     let read_wandering = SHEET_CACHE_WANDERING.read().await;
@@ -1360,7 +1589,7 @@ pub async fn refresh_attd_sheet_cache(force: bool) -> Result<(), ()> {
 
     drop(read_wandering);
     let config = get_config_type(wandering_date);
-    let sheet_id_wandering = ATTENDANCE_SHEETS[config as usize];
+    let sheet_id_wandering = attendance[config as usize].clone();
     let (sheet_name_wandering, _) = calculate_sheet_name(wandering_date);
 
     let read_freshies = SHEET_CACHE_FRESHIES.read().await;
@@ -1368,22 +1597,33 @@ pub async fn refresh_attd_sheet_cache(force: bool) -> Result<(), ()> {
     drop(read_freshies);
 
     let config = get_config_type(freshies_date);
-    let sheet_id_freshies = ATTENDANCE_SHEETS[config as usize];
+    let sheet_id_freshies = attendance[config as usize].as_deref();
     let (sheet_name_freshies, _) = calculate_sheet_name(freshies_date);
     let sheet_name_freshies = format!(
         "{}{}",
         sheet_name_freshies,
-        config::SHEETSCRAPER_PADDLING_FRESHIE_SHEET_SUFFIX
+        config::settings()
+            .sheetscraper
+            .paddling
+            .freshie_sheet_suffix
     );
 
+    let source = sheet_source().await;
+
     let mut cache_lock = SHEET_CACHE.write().await;
     let mut cache_lock_wand = SHEET_CACHE_WANDERING.write().await;
     let mut cache_lock_freshies = SHEET_CACHE_FRESHIES.write().await;
 
     match sheet_id_freshies {
         Some(id) => {
-            let df = g_sheets::get_as_dataframe(id, Some(sheet_name_freshies)).await;
-            let sheet: AttdSheet = df.try_into().unwrap_or(AttdSheet::from_date(freshies_date));
+            let sheet = match source
+                .get_as_dataframe(id, Some(&sheet_name_freshies))
+                .await
+            {
+                Ok(df) => df.try_into().unwrap_or(AttdSheet::from_date(freshies_date)),
+                Err(e) if is_missing_sheet(&e) => AttdSheet::from_date(freshies_date),
+                Err(e) => return Err(e),
+            };
             update_attd_cache(sheet, &mut cache_lock_freshies);
         }
         None => (),
@@ -1392,36 +1632,49 @@ pub async fn refresh_attd_sheet_cache(force: bool) -> Result<(), ()> {
     match (sheet_id, sheet_id_wandering) {
         (None, None) => (),
         (None, Some(wand)) => {
-            let df = g_sheets::get_as_dataframe(wand, Some(sheet_name_wandering)).await;
+            let df = source
+                .get_as_dataframe(&wand, Some(&sheet_name_wandering))
+                .await?;
             let sheet_wand: AttdSheet = df
                 .try_into()
                 .unwrap_or(AttdSheet::from_date(wandering_date));
             update_attd_cache(sheet_wand, &mut cache_lock_wand);
         }
         (Some(id), None) => {
-            let df = g_sheets::get_as_dataframe(id, Some(sheet_name)).await;
+            let df = source.get_as_dataframe(&id, Some(&sheet_name)).await?;
             let sheet = df.try_into().unwrap_or(AttdSheet::from_date(today));
             update_attd_cache(sheet, &mut cache_lock);
         }
         (Some(id), Some(id_wand)) => {
             let tasks = (
-                tokio::spawn(g_sheets::get_as_dataframe(id, Some(sheet_name))),
-                tokio::spawn(g_sheets::get_as_dataframe(
-                    id_wand,
-                    Some(sheet_name_wandering),
-                )),
+                tokio::spawn({
+                    let source = source.clone();
+                    async move { source.get_as_dataframe(&id, Some(&sheet_name)).await }
+                }),
+                tokio::spawn({
+                    let source = source.clone();
+                    async move {
+                        source
+                            .get_as_dataframe(&id_wand, Some(&sheet_name_wandering))
+                            .await
+                    }
+                }),
             );
 
-            let df = tasks.0.await.unwrap();
-            let sheet: AttdSheet = df.try_into().unwrap_or(AttdSheet::from_date(today));
+            // update whichever sheet succeeded, then report the first failure
+            let res = tasks.0.await.unwrap().map(|df| {
+                let sheet: AttdSheet = df.try_into().unwrap_or(AttdSheet::from_date(today));
+                update_attd_cache(sheet, &mut cache_lock);
+            });
 
-            let df_wandering = tasks.1.await.unwrap();
-            let sheet_wandering: AttdSheet = df_wandering
-                .try_into()
-                .unwrap_or(AttdSheet::from_date(wandering_date));
+            let res_wandering = tasks.1.await.unwrap().map(|df| {
+                let sheet: AttdSheet = df
+                    .try_into()
+                    .unwrap_or(AttdSheet::from_date(wandering_date));
+                update_attd_cache(sheet, &mut cache_lock_wand);
+            });
 
-            update_attd_cache(sheet, &mut cache_lock);
-            update_attd_cache(sheet_wandering, &mut cache_lock_wand);
+            res.and(res_wandering)?;
         }
     }
 
@@ -1429,7 +1682,7 @@ pub async fn refresh_attd_sheet_cache(force: bool) -> Result<(), ()> {
 }
 
 /// Refresh the cached sheet
-pub async fn refresh_prog_sheet_cache(force: bool) -> Result<(), ()> {
+pub async fn refresh_prog_sheet_cache(force: bool) -> Result<(), SheetError> {
     debug_println!(
         "refreshing prog sheet cache at: {}",
         chrono::Local::now().time()
@@ -1439,7 +1692,7 @@ pub async fn refresh_prog_sheet_cache(force: bool) -> Result<(), ()> {
     let read_lock = PROG_CACHE.read().await;
 
     if (chrono::Local::now().naive_local() - read_lock.fetch_time).num_minutes()
-        < config::SHEETSCRAPER_CACHE_PROG
+        < config::settings().sheetscraper.cache.prog
     {
         if !force {
             return Ok(());
@@ -1448,12 +1701,13 @@ pub async fn refresh_prog_sheet_cache(force: bool) -> Result<(), ()> {
 
     drop(read_lock);
     let config = get_config_type(today);
-    let sheet_id = PROGRAM_SHEETS[config as usize];
+    let program = program_sheets();
+    let sheet_id = program[config as usize].as_deref();
 
     let sheet = {
         match sheet_id {
             Some(id) => {
-                let df = g_sheets::get_as_dataframe(id, Option::<&str>::None).await;
+                let df = sheet_source().await.get_as_dataframe(id, None).await?;
                 let sheet: ProgSheet = df.try_into().unwrap_or(ProgSheet::from_date(today));
 
                 sheet
@@ -1474,26 +1728,86 @@ pub async fn refresh_prog_sheet_cache(force: bool) -> Result<(), ()> {
     Ok(())
 }
 
+/// When the attendance and program caches were last filled, [None] if never
+pub async fn cache_fetch_times() -> [(&'static str, Option<NaiveDateTime>); 2] {
+    let filled = |time: NaiveDateTime| (time != NaiveDateTime::default()).then_some(time);
+
+    [
+        ("attd", filled(SHEET_CACHE.read().await.fetch_time)),
+        ("prog", filled(PROG_CACHE.read().await.fetch_time)),
+    ]
+}
+
+/// All names in the configs sheet that applies to a date.
+pub async fn names(date: NaiveDate) -> Vec<String> {
+    NAMES[get_config_type(date) as usize].read().await.clone()
+}
+
+/// Returns the shortened form of a name, or the name itself if it has none.
+///
+/// Names in [NameList] are shortened.
+pub async fn short_name(name: &str, date: NaiveDate) -> String {
+    let lock = SHORTENED_NAMES[get_config_type(date) as usize].read().await;
+
+    lock.get(name).cloned().unwrap_or(name.to_string())
+}
+
+/// Checks if someone is part of the EXCO
+pub async fn is_exco(name: &str, date: NaiveDate) -> bool {
+    let short = short_name(name, date).await;
+    let lock = EXCO_NAMES[get_config_type(date) as usize].read().await;
+
+    lock.contains(&short)
+}
+
+/// Look up the name linked to a telegram handle in the configs sheet.
+pub async fn name_from_telegram(handle: &str, date: NaiveDate) -> Option<String> {
+    let key = normalize_telegram_handle(handle);
+    let lock = TELEGRAM_HANDLES[get_config_type(date) as usize]
+        .read()
+        .await;
+
+    lock.get(&key).cloned()
+}
+
+/// Look up the telegram handle of a name in the configs sheet, without the `@`.
+pub async fn telegram_of_name(name: &str, date: NaiveDate) -> Option<String> {
+    let lock = TELEGRAM_HANDLES[get_config_type(date) as usize]
+        .read()
+        .await;
+
+    lock.iter()
+        .find(|(_, n)| n.as_str() == name)
+        .map(|(handle, _)| handle.clone())
+}
+
+/// Lowercase a handle and strip the leading `@`
+fn normalize_telegram_handle(handle: &str) -> String {
+    handle.trim().trim_start_matches('@').to_lowercase()
+}
+
+/// Checks if attendance for a session can no longer be changed.
+///
+/// A session closes once it starts, or once its logsheet has been submitted.
+pub async fn session_closed(date: NaiveDate, time_slot: bool) -> bool {
+    let (start, _) = start_end_times(time_slot);
+    if date.and_time(start) <= chrono::Local::now().naive_local() {
+        return true;
+    }
+
+    let lock = SUBMIT_LOCK.read().await;
+    let last_submitted = if time_slot { lock.1 } else { lock.0 };
+
+    date <= last_submitted
+}
+
 /// Get the (water) start and end times for a given time slot.
 pub fn start_end_times(time_slot: bool) -> (NaiveTime, NaiveTime) {
-    let start = if time_slot {
-        config::SHEETSCRAPER_PADDLING_TIMES_PM_ARRIVE
-    } else {
-        config::SHEETSCRAPER_PADDLING_TIMES_AM_ARRIVE
-    };
-
-    let end = if time_slot {
-        config::SHEETSCRAPER_PADDLING_TIMES_PM_FINISH
-    } else {
-        config::SHEETSCRAPER_PADDLING_TIMES_AM_FINISH
-    };
+    let times = &config::settings().sheetscraper.paddling.times;
 
-    let to_naive_time = |input: toml::value::Datetime| -> NaiveTime {
-        let time = input.time.expect("time must be present");
-        NaiveTime::from_hms_opt(time.hour.into(), time.minute.into(), time.second.into()).unwrap()
-    };
+    let times = if time_slot { &times.pm } else { &times.am };
 
-    (to_naive_time(start), to_naive_time(end))
+    (times.arrive, times.finish)
 }
 
 #[cfg(test)]
@@ -1528,22 +1842,28 @@ mod tests {
 
     #[tokio::test]
     async fn test_breakdown() {
-        init().await;
+        init().await.unwrap();
 
-        let bd = breakdown(chrono::Local::now().date_naive(), false).await;
+        let bd = breakdown(chrono::Local::now().date_naive(), false)
+            .await
+            .unwrap();
         println!("{}", bd);
 
-        let bd = breakdown(chrono::Local::now().date_naive(), true).await;
+        let bd = breakdown(chrono::Local::now().date_naive(), true)
+            .await
+            .unwrap();
         println!("{}", bd);
     }
 
     #[tokio::test]
     async fn get_sheet() {
+        let settings = config::settings();
         let mut df = g_sheets::get_as_dataframe(
-            config::SHEETSCRAPER_NEW_ATTENDANCE_SHEET,
-            Some(config::SHEETSCRAPER_CONFIGURATION_SHEET),
+            &settings.sheetscraper.new.attendance_sheet,
+            Some(&settings.sheetscraper.configuration_sheet),
         )
-        .await;
+        .await
+        .unwrap();
 
         println!("{:?}", df.column("is_exco"));
 
@@ -1554,7 +1874,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_reloading_configs() {
-        init().await;
+        init().await.unwrap();
 
         let new_allocations = BOAT_ALLOCATIONS[0].read().await;
         println!("{:#?}", new_allocations);
@@ -1571,7 +1891,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_sheet_from_dataframe() {
-        init().await;
+        init().await.unwrap();
 
         let today = chrono::Local::now().date_naive();
 
@@ -1579,9 +1899,12 @@ mod tests {
 
         println!("sheet name: {}", &sheet_name);
 
-        let mut df =
-            g_sheets::get_as_dataframe(config::SHEETSCRAPER_NEW_ATTENDANCE_SHEET, Some(sheet_name))
-                .await;
+        let mut df = g_sheets::get_as_dataframe(
+            &config::settings().sheetscraper.new.attendance_sheet,
+            Some(sheet_name),
+        )
+        .await
+        .unwrap();
 
         let mut sheet: AttdSheet = df.try_into().unwrap();
 
@@ -1607,17 +1930,166 @@ mod tests {
             .unwrap();
     }
 
+    /// Parse an attendance sheet read from local fixtures
+    #[tokio::test]
+    async fn test_sheet_from_dir_source() {
+        let root = std::env::temp_dir().join("attd_test_sheet_from_dir_source");
+        let spreadsheet = root.join("some_id");
+        std::fs::create_dir_all(&spreadsheet).unwrap();
+
+        // 1 fenced column, 3 padding columns, then 14 data columns (7 days, AM/PM)
+        let row = |left: &str, name: &str, cells: &[&str]| {
+            let mut data = vec![""; 14];
+            data[..cells.len()].copy_from_slice(cells);
+            format!("{},{},,,,{}\n", left, name, data.join(","))
+        };
+        let header = format!(
+            "fence,names,p1,p2,p3,{}\n",
+            (0..14)
+                .map(|i| format!("d{}", i))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let contents = [
+            header,
+            row("", "26-Jun-23", &[]),
+            row("", "", &[]),
+            row("", "asd", &["Y", ""]),
+            row("", "qwe", &["", "Y", "", "Y"]),
+        ]
+        .concat();
+        std::fs::write(spreadsheet.join("Jul-2023.csv"), contents).unwrap();
+
+        let source = DirSource::new(&root);
+        let df = source
+            .get_as_dataframe("some_id", Some("Jul-2023"))
+            .await
+            .unwrap();
+        let sheet: AttdSheet = df.try_into().unwrap();
+
+        assert_eq!(sheet.start, create_date(2023, 6, 26));
+
+        let am = sheet
+            .get_names(create_date(2023, 6, 26), false)
+            .await
+            .unwrap();
+        assert_eq!(am.names, vec!["asd".to_string()]);
+
+        let pm = sheet
+            .get_names(create_date(2023, 6, 26), true)
+            .await
+            .unwrap();
+        assert_eq!(pm.names, vec!["qwe".to_string()]);
+
+        let pm = sheet
+            .get_names(create_date(2023, 6, 27), true)
+            .await
+            .unwrap();
+        assert_eq!(pm.names, vec!["qwe".to_string()]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    /// Locate and write attendance cells in local fixtures
+    #[tokio::test]
+    async fn test_mark_attendance_cell() {
+        let root = std::env::temp_dir().join("attd_test_mark_attendance_cell");
+        let spreadsheet = root.join("some_id");
+        std::fs::create_dir_all(&spreadsheet).unwrap();
+
+        // 1 fenced column, 3 padding columns, then 2 blocks of 14 data columns
+        let row = |name: &str| {
+            format!(
+                ",{},,,,{},,,,{}\n",
+                name,
+                vec![""; 14].join(","),
+                vec![""; 14].join(",")
+            )
+        };
+        let contents = [
+            format!(",names{}\n", ",".repeat(34)),
+            row("26-Jun-23"),
+            row(""),
+            row("asd"),
+            row("qwe"),
+        ]
+        .concat();
+        std::fs::write(spreadsheet.join("Jul-2023.csv"), contents).unwrap();
+
+        let source = DirSource::new(&root);
+        let df = source
+            .get_as_dataframe("some_id", Some("Jul-2023"))
+            .await
+            .unwrap();
+        let sheet: AttdSheet = df.try_into().unwrap();
+
+        let date = create_date(2023, 6, 26);
+        assert_eq!(
+            sheet.cell_position("asd", date, false).await,
+            Some(Cell::new(3, 5))
+        );
+        assert_eq!(
+            sheet.cell_position("qwe", date, true).await,
+            Some(Cell::new(4, 6))
+        );
+        // second week block skips another set of padding columns
+        assert_eq!(
+            sheet
+                .cell_position("qwe", create_date(2023, 7, 3), false)
+                .await,
+            Some(Cell::new(4, 22))
+        );
+        assert_eq!(sheet.cell_position("zxc", date, false).await, None);
+        assert_eq!(
+            sheet
+                .cell_position("asd", create_date(2023, 6, 25), false)
+                .await,
+            None
+        );
+
+        let cell = sheet
+            .cell_position("qwe", create_date(2023, 7, 3), true)
+            .await
+            .unwrap();
+        source
+            .as_writer()
+            .unwrap()
+            .update_cell("some_id", "Jul-2023", cell, IS_ATTENDING)
+            .await
+            .unwrap();
+
+        let df = source
+            .get_as_dataframe("some_id", Some("Jul-2023"))
+            .await
+            .unwrap();
+        let sheet: AttdSheet = df.try_into().unwrap();
+        let names = sheet
+            .get_names(create_date(2023, 7, 3), true)
+            .await
+            .unwrap();
+        assert_eq!(names.names, vec!["qwe".to_string()]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_sheet_from_empty_dataframe() {
+        let sheet: Result<AttdSheet, ()> = DataFrame::default().try_into();
+        assert!(sheet.is_err());
+    }
+
     #[tokio::test]
     async fn test_prog_from_dataframe() {
-        init().await;
+        init().await.unwrap();
 
         let today = chrono::Local::now().date_naive();
 
         let mut df = g_sheets::get_as_dataframe(
-            config::SHEETSCRAPER_NEW_PROGRAM_SHEET,
+            &config::settings().sheetscraper.new.program_sheet,
             Option::<&str>::None,
         )
-        .await;
+        .await
+        .unwrap();
 
         debug_println!("prog sheet: {}", df);
 
@@ -1766,8 +2238,10 @@ mod tests {
 
     #[tokio::test]
     async fn test_asd() {
-        init().await;
-        let mut res = land(chrono::Local::now().date_naive() + Duration::days(1), false).await;
+        init().await.unwrap();
+        let mut res = land(chrono::Local::now().date_naive() + Duration::days(1), false)
+            .await
+            .unwrap();
         res.fill_prog(true).await.unwrap();
         println!("{}", res);
     }
@@ -1799,6 +2273,64 @@ mod tests {
         assert_eq!(y2, res2);
     }
 
+    #[test]
+    fn test_crew_order() {
+        let boats: Vec<Option<String>> = [Some("K2"), None, Some("K1"), Some("K2"), Some("K2")]
+            .into_iter()
+            .map(|b| b.map(String::from))
+            .collect();
+
+        assert_eq!(crew_order(&boats), [(0, 1), (3, 2), (4, 3), (1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn test_bit_indices() {
+        let mut indices = BitIndices::from_vec(vec![130, 3, 64, 3]);
+        assert_eq!(indices.to_vec(), vec![3, 64, 130]);
+        assert!(indices.contains(130));
+        assert!(!indices.contains(1000));
+
+        indices.remove(130);
+        indices.remove(1000);
+        assert_eq!(indices, BitIndices::from_vec(vec![3, 64]));
+
+        indices.remove(64);
+        assert_eq!(indices, BitIndices::from_u64(0b1000));
+        indices.remove(3);
+        assert_eq!(indices, BitIndices::default());
+
+        let mut name_list = NameList::from_date_time(NaiveDate::default(), false);
+        name_list.names = (0..100).map(|i| i.to_string()).collect();
+        name_list.exclude(&BitIndices::from_vec(vec![0, 70, 99, 150]));
+        assert_eq!(name_list.names.len(), 97);
+        assert_eq!(name_list.excluded_names, ["0", "70", "99"]);
+    }
+
+    #[test]
+    fn test_normalize_telegram_handle() {
+        assert_eq!(normalize_telegram_handle("@Some_User "), "some_user");
+        assert_eq!(normalize_telegram_handle("some_user"), "some_user");
+    }
+
+    #[tokio::test]
+    async fn test_session_closed() {
+        let today = chrono::Local::now().date_naive();
+
+        assert!(session_closed(today - Duration::days(1), false).await);
+        assert!(session_closed(today - Duration::days(1), true).await);
+        assert!(!session_closed(today + Duration::days(2), false).await);
+
+        let mut lock = SUBMIT_LOCK.write().await;
+        lock.1 = today + Duration::days(2);
+        drop(lock);
+
+        assert!(session_closed(today + Duration::days(2), true).await);
+        assert!(!session_closed(today + Duration::days(2), false).await);
+
+        let mut lock = SUBMIT_LOCK.write().await;
+        lock.1 = today - Duration::days(1);
+    }
+
     #[test]
     fn test_prune_empty_lines() {
         let sample = "Hello world\n\n\n\n\n\n\n\n\n\nHello world";
@@ -1808,9 +2340,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_land() {
-        init().await;
+        init().await.unwrap();
 
-        let res = land(NaiveDate::from_ymd_opt(2024, 7, 31).unwrap(), false).await;
+        let res = land(NaiveDate::from_ymd_opt(2024, 7, 31).unwrap(), false)
+            .await
+            .unwrap();
         println!("{}", res);
     }
 }
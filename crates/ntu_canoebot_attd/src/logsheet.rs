@@ -1,20 +1,22 @@
 //! Logsheet logic goes here
 //!
 
-use std::collections::HashMap;
+pub mod fields;
 
-use chrono::{Duration, NaiveDate, NaiveTime};
-use g_forms::{
-    form::{QuestionType, Response},
-    FillResult, GoogleForm,
-};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
 use lazy_static::lazy_static;
 use ntu_canoebot_util::debug_println;
+use ntu_canoebot_util::metrics::Counter;
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
 use ntu_canoebot_config as config;
 
 use crate::{get_config_type, start_end_times, NAMES_CERTS};
+use fields::LogsheetValues;
 
 lazy_static! {
     /// Logsheet lock. Prevents multiple submissions. Keeps track of
@@ -22,19 +24,138 @@ lazy_static! {
     ///
     /// Element 0 is for AM sessions,
     /// Element 1 is for PM sessions.
+    ///
+    /// Restored from the submission history on startup.
     pub static ref SUBMIT_LOCK: RwLock<(NaiveDate, NaiveDate)> = {
         let yesterday = chrono::Local::now().date_naive() - Duration::days(1);
+        let history = read_submissions(&history_path()).unwrap_or_else(|e| {
+            log::error!("unable to read logsheet history: {}", e);
+            Default::default()
+        });
 
-        RwLock::new((yesterday, yesterday))
+        RwLock::new(lock_from_history(&history, yesterday))
     };
 
     static ref LOOPING_COUNTER: RwLock<LoopingCounter> = {
-        let particulars = &*config::FORMFILLER_PARTICULARS;
+        let particulars = config::settings().formfiller.particulars.len();
 
-        RwLock::new(LoopingCounter::from_size(particulars.len()))
+        RwLock::new(LoopingCounter::from_size(particulars))
     };
 }
 
+/// File name of the submission history, inside the data dir.
+/// Each line is one [Submission] in JSON.
+const HISTORY_FILE: &str = "logsheets.jsonl";
+
+const SUBMISSIONS: Counter = Counter::new(
+    "canoebot_logsheet_submissions_total",
+    "logsheets submitted to SCF, by result: sent, mock or failed",
+);
+
+/// A logsheet that was submitted to SCF
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Submission {
+    pub date: NaiveDate,
+    /// `false` for AM, `true` for PM
+    pub time_slot: bool,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub certified: usize,
+    pub not_certified: usize,
+    pub participants_offset: i32,
+    /// Index into `formfiller.particulars`
+    pub particulars_idx: usize,
+    /// Name in the particulars used
+    pub particulars_name: String,
+    /// Telegram user id of whoever sent the logsheet
+    pub submitted_by_id: u64,
+    pub submitted_by: String,
+    pub submitted_at: NaiveDateTime,
+    /// HTTP status code of the form submission, 0 for mocks
+    pub status: u16,
+    /// Filled but not sent, with `formfiller.mock` set
+    #[serde(default)]
+    pub mock: bool,
+}
+
+impl Submission {
+    /// Checks if the form accepted the submission
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+fn history_path() -> PathBuf {
+    Path::new(&config::settings().canoebot.data_dir).join(HISTORY_FILE)
+}
+
+/// Read all submissions in a history file, oldest first.
+///
+/// A missing file has no submissions. Lines that cannot be parsed are skipped.
+fn read_submissions(path: &Path) -> std::io::Result<Vec<Submission>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let submissions = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(submission) => Some(submission),
+            Err(e) => {
+                log::error!("skipping logsheet history entry: {}", e);
+                None
+            }
+        })
+        .collect();
+
+    Ok(submissions)
+}
+
+/// Append a submission to a history file
+fn append_submission(path: &Path, submission: &Submission) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut line = serde_json::to_string(submission)?;
+    line.push('\n');
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// Reconstruct [SUBMIT_LOCK] from the most recent submission of each session.
+///
+/// Failed and mock submissions did not reach SCF, so they do not lock a session.
+fn lock_from_history(history: &[Submission], default: NaiveDate) -> (NaiveDate, NaiveDate) {
+    history
+        .iter()
+        .filter(|submission| submission.is_success() && !submission.mock)
+        .fold(
+            (default, default),
+            |(am, pm), submission| match submission.time_slot {
+                false => (am.max(submission.date), pm),
+                true => (am, pm.max(submission.date)),
+            },
+        )
+}
+
+/// Most recent logsheet submissions, newest first
+pub fn history(limit: usize) -> Result<Vec<Submission>, String> {
+    let mut submissions = read_submissions(&history_path())
+        .map_err(|e| format!("unable to read logsheet history: {}", e))?;
+    submissions.reverse();
+    submissions.truncate(limit);
+
+    Ok(submissions)
+}
+
 /// Looping counter for particulars
 /// Iterates infinitely
 struct LoopingCounter {
@@ -68,30 +189,63 @@ impl LoopingCounter {
             curr: 0,
         }
     }
+
+    /// The next value for a given size, without advancing the counter
+    pub fn peek(&self, size: usize) -> usize {
+        match self.has_size(size) {
+            true => self.curr,
+            false => 0,
+        }
+    }
+
+    /// Checks if the counter was created with this size
+    pub fn has_size(&self, size: usize) -> bool {
+        self.size == size as i64 - 1
+    }
 }
 
-/// Sends a logsheet for a date and time.
+/// A logsheet that has been filled in, but not submitted
+#[derive(Debug)]
+pub struct Filled {
+    pub form: g_forms::GoogleForm,
+    pub values: LogsheetValues,
+    /// Index into `formfiller.particulars`
+    pub particulars_idx: usize,
+    /// Question and the answer filled in, in the order of `formfiller.fields`
+    pub answers: Vec<(String, String)>,
+}
+
+/// Fetch the logsheet form and fill it in for a date and time.
 #[rustfmt::skip]
-pub async fn send(
+async fn fill(
     date: NaiveDate,
     session: bool,
     start_override: Option<NaiveTime>,
     end_override: Option<NaiveTime>,
     participants_override: i32,
-) -> Result<Response, String> {
-    let logsheet_id = config::FORMFILLER_FORM_ID;
+    particulars_idx: usize,
+) -> Result<Filled, String> {
+    let settings = config::settings();
+    let logsheet_id = &settings.formfiller.form_id;
 
     let mut form = g_forms::GoogleForm::from_id(logsheet_id)
         .await
         .ok_or("Failed to fetch form. Does the form exist?")?;
 
+    // check the form before anything else, so changes to it are caught early
+    let fields = fields::fields_from_config(&settings.formfiller.fields)
+        .map_err(|e| format!("invalid formfiller.fields: {}", e))?;
+    let indices = fields::resolve(&fields, &form)?;
+
     let name_list = crate::namelist(date, session, false)
         .await
+        .map_err(|e| format!("Unable to get namelist: {}", e))?
         .ok_or("Unable to get namelist")?;
 
     // may not exist
     let freshie_name_list = crate::namelist(date, session, true)
         .await
+        .map_err(|e| format!("Unable to get freshie namelist: {}", e))?
         .unwrap_or(crate::NameList::from_date_time(date, session));
 
     let total_paddlers = name_list.names.len() + freshie_name_list.names.len();
@@ -140,18 +294,11 @@ pub async fn send(
     );
     debug_println!("namelist struct: {:?}", name_list);
 
-    let particulars: &[HashMap<&'static str, String>] = &*config::FORMFILLER_PARTICULARS;
-    let part_idx = LOOPING_COUNTER.write().await.next().unwrap();
-    let (exco_name, exco_number) = particulars
-        .iter()
-        .skip(part_idx)
-        .next()
-        .map(|particular| {
-            (
-                particular.get("name").expect("expected name field").to_string(),
-                particular.get("number").expect("expected number field").to_string(),
-            )
-        })
+    let (exco_name, exco_number) = settings
+        .formfiller
+        .particulars
+        .get(particulars_idx)
+        .map(|particular| (particular.name.clone(), particular.number.clone()))
         .ok_or("failed to insert exco particulars")?;
 
 
@@ -173,81 +320,132 @@ pub async fn send(
         }
     };
 
-    /// Fills a question with whatever and returns a more verbose error
-    #[inline]
-    fn get_qn_with_error(f: &mut GoogleForm, qn: usize) -> Result<&mut QuestionType, String> {
-        let question = f
-            .question(qn)
-            .ok_or(format!("unable to index into question '{}'", qn))?;
+    let values = LogsheetValues {
+        exco_name,
+        exco_number,
+        certified,
+        not_certified,
+        date,
+        start_time,
+        end_time,
+    };
 
-        Ok(question)
-    }
-    /// Adds a nice error message when encountering a result
-    #[inline]
-    fn transform_fill_result(res: FillResult, qn: usize) -> Result<(), String> {
-        res.map_err(|e| format!("{:?}, failed to fill question '{}'", e, qn))
-    }
+    let mut answers = Vec::new();
+    for (field, idx) in fields.iter().zip(indices) {
+        let question = form
+            .question(idx)
+            .ok_or(format!("unable to index into question '{}'", idx))?;
 
-    // name
-    transform_fill_result(
-        get_qn_with_error(&mut form, 0)?
-        .fill_str(&exco_name), 0
-    )?;
-    // hp number
-    transform_fill_result(
-        get_qn_with_error(&mut form, 1)?
-        .fill_str(&exco_number), 1
-    )?;
-    // organization
-    transform_fill_result(
-        get_qn_with_error(&mut form, 2)?
-        .fill_str("Nanyang Technological University"), 2
-    )?;
-    // type of activity
-    transform_fill_result(
-        get_qn_with_error(&mut form, 3)?
-        .fill_option(2), 3
-    )?;
-    // number of certified
-    transform_fill_result(
-        get_qn_with_error(&mut form, 4)?
-        .fill_number(certified.into()), 4
-    )?;
-    // number of non certified
-    transform_fill_result(
-        get_qn_with_error(&mut form, 5)?
-        .fill_number(not_certified.into()), 5
-    )?;
-    // paddling location
-    transform_fill_result(
-        get_qn_with_error(&mut form, 6)?
-        .fill_option(0), 6
-    )?;
-    // // date of training
-    // transform_fill_result(
-    //     get_qn_with_error(&mut form, 7)?
-    //     .fill_date(date.and_time(chrono::Local::now().time())), 7
-    // )?;
-    // start time
-    transform_fill_result(
-        get_qn_with_error(&mut form, 7)?
-        .fill_time(start_time), 8
-    )?;
-    // end time
-    transform_fill_result(
-        get_qn_with_error(&mut form, 8)?
-        .fill_time(end_time), 9
-    )?;
-    // disclaimer agree
-    transform_fill_result(
-        get_qn_with_error(&mut form, 9)?
-        .fill_option(0), 10
-    )?;
+        fields::fill(question, &field.value, &values)
+            .map_err(|e| format!("{}, failed to fill question {}", e, field.question))?;
+        answers.push((field.question.to_string(), fields::describe(&field.value, &values)));
+    }
     debug_println!("form response: {:#?}", form);
 
-    form.submit(config::FORMFILLER_MOCK).await
-    // Ok(Default::default())
-    // Err(())
+    Ok(Filled {
+        form,
+        values,
+        particulars_idx,
+        answers,
+    })
+}
+
+/// Fill in a logsheet without submitting it.
+///
+/// Uses the particulars that the next submission would use.
+pub async fn dry_run(
+    date: NaiveDate,
+    session: bool,
+    start_override: Option<NaiveTime>,
+    end_override: Option<NaiveTime>,
+    participants_override: i32,
+) -> Result<Filled, String> {
+    let particulars = config::settings().formfiller.particulars.len();
+    let particulars_idx = LOOPING_COUNTER.read().await.peek(particulars);
+
+    fill(
+        date,
+        session,
+        start_override,
+        end_override,
+        participants_override,
+        particulars_idx,
+    )
+    .await
+}
+
+/// Sends a logsheet for a date and time.
+///
+/// Submissions are saved to the logsheet history,
+/// along with the telegram user that sent it.
+pub async fn send(
+    date: NaiveDate,
+    session: bool,
+    start_override: Option<NaiveTime>,
+    end_override: Option<NaiveTime>,
+    participants_override: i32,
+    submitted_by_id: u64,
+    submitted_by: &str,
+) -> Result<Submission, String> {
+    let particulars = config::settings().formfiller.particulars.len();
+    let part_idx = {
+        let mut counter = LOOPING_COUNTER.write().await;
+        // particulars may have been changed by a settings reload
+        if !counter.has_size(particulars) {
+            *counter = LoopingCounter::from_size(particulars);
+        }
+        counter.next().unwrap()
+    };
+
+    let mut filled = fill(
+        date,
+        session,
+        start_override,
+        end_override,
+        participants_override,
+        part_idx,
+    )
+    .await?;
+
+    let mock = config::settings().formfiller.mock;
+    let status = match filled.form.submit(mock).await {
+        Ok(response) => response.status().as_u16(),
+        // the form is filled but never sent
+        Err(_) if mock => 0,
+        Err(e) => {
+            SUBMISSIONS.increment(&[("result", "failed")]);
+            return Err(e);
+        }
+    };
+    let result = match mock {
+        true => "mock",
+        false => "sent",
+    };
+    SUBMISSIONS.increment(&[("result", result)]);
+
+    let submission = Submission {
+        date,
+        time_slot: session,
+        start_time: filled.values.start_time,
+        end_time: filled.values.end_time,
+        certified: filled.values.certified,
+        not_certified: filled.values.not_certified,
+        participants_offset: participants_override,
+        particulars_idx: part_idx,
+        particulars_name: filled.values.exco_name,
+        submitted_by_id,
+        submitted_by: submitted_by.to_string(),
+        submitted_at: chrono::Local::now().naive_local(),
+        status,
+        mock,
+    };
+
+    // the form has been sent, so a failure here should not be reported as a failed send
+    if let Err(e) = append_submission(&history_path(), &submission) {
+        log::error!("unable to save logsheet submission {:?}: {}", submission, e);
+    }
+
+    Ok(submission)
 }
 
 #[cfg(test)]
@@ -263,7 +461,7 @@ mod tests {
     /// Test if g_forms can deserialize form data
     #[tokio::test]
     async fn test_logsheet_valid() {
-        let logsheet_id = config::FORMFILLER_FORM_ID;
+        let logsheet_id = &config::settings().formfiller.form_id;
 
         let mut form = g_forms::GoogleForm::from_id(logsheet_id).await.unwrap();
 
@@ -352,13 +550,77 @@ mod tests {
     #[cfg(notset)]
     #[tokio::test]
     async fn test_logsheet() {
-        crate::init().await;
+        crate::init().await.unwrap();
 
         let res = super::send(chrono::Local::now().date_naive(), false).await;
 
         println!("{:#?}", res);
     }
 
+    fn submission(date: NaiveDate, time_slot: bool) -> Submission {
+        Submission {
+            date,
+            time_slot,
+            start_time: NaiveTime::from_hms_opt(7, 20, 0).unwrap(),
+            end_time: NaiveTime::from_hms_opt(9, 15, 0).unwrap(),
+            certified: 10,
+            not_certified: 2,
+            participants_offset: -1,
+            particulars_idx: 0,
+            particulars_name: "some exco".to_string(),
+            submitted_by_id: 1234,
+            submitted_by: "some person".to_string(),
+            submitted_at: date.and_hms_opt(10, 0, 0).unwrap(),
+            status: 200,
+            mock: false,
+        }
+    }
+
+    #[test]
+    fn test_submission_history() {
+        let dir = std::env::temp_dir().join("attd_test_submission_history");
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join(HISTORY_FILE);
+
+        assert!(read_submissions(&path).unwrap().is_empty());
+
+        let d = |day| NaiveDate::from_ymd_opt(2023, 7, day).unwrap();
+        let submissions = vec![
+            submission(d(1), false),
+            submission(d(3), true),
+            submission(d(2), false),
+        ];
+        for s in &submissions {
+            append_submission(&path, s).unwrap();
+        }
+
+        let read = read_submissions(&path).unwrap();
+        assert_eq!(read, submissions);
+        assert!(read[0].is_success());
+
+        assert_eq!(lock_from_history(&read, d(1)), (d(2), d(3)));
+        // newer defaults are kept
+        assert_eq!(lock_from_history(&read, d(5)), (d(5), d(5)));
+        assert_eq!(lock_from_history(&[], d(1)), (d(1), d(1)));
+
+        // submissions that did not reach SCF are skipped
+        let failed = Submission {
+            status: 500,
+            ..submission(d(4), false)
+        };
+        let mock = Submission {
+            status: 0,
+            mock: true,
+            ..submission(d(4), true)
+        };
+        assert_eq!(
+            lock_from_history(&[read, vec![failed, mock]].concat(), d(1)),
+            (d(2), d(3))
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
     #[test]
     fn test_looping_counter() {
         let mut counter = LoopingCounter::from_size(5);
@@ -0,0 +1,463 @@
+//! Mapping of logsheet form questions to values, from `formfiller.fields`.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveTime};
+use g_forms::form::{QuestionHeader, QuestionType};
+use ntu_canoebot_config::FormField;
+
+/// How a field refers to a question in the form
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldQuestion {
+    /// Position in the form, from 0
+    Index(usize),
+    /// Question title, ignoring case
+    Title(String),
+    /// Entry id, as used in the submission payload
+    Entry(u64),
+}
+
+impl Display for FieldQuestion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldQuestion::Index(idx) => write!(f, "question {}", idx),
+            FieldQuestion::Title(title) => write!(f, "\"{}\"", title),
+            FieldQuestion::Entry(id) => write!(f, "entry.{}", id),
+        }
+    }
+}
+
+/// Source of the value filled into a question
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    ExcoName,
+    ExcoNumber,
+    Certified,
+    NotCertified,
+    StartTime,
+    EndTime,
+    Date,
+    /// Fixed text
+    Literal(String),
+    /// Choice to select, by its label
+    Option(String),
+    /// Choice to select, by its position from 0
+    OptionIndex(usize),
+}
+
+impl FromStr for FieldValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(text) = s.strip_prefix("literal:") {
+            return Ok(FieldValue::Literal(text.to_string()));
+        }
+        if let Some(idx) = s.strip_prefix("option_index:") {
+            return idx
+                .trim()
+                .parse()
+                .map(FieldValue::OptionIndex)
+                .map_err(|_| format!("invalid option index '{}'", idx));
+        }
+        if let Some(label) = s.strip_prefix("option:") {
+            return Ok(FieldValue::Option(label.trim().to_string()));
+        }
+
+        let value = match s.trim() {
+            "exco.name" => FieldValue::ExcoName,
+            "exco.number" => FieldValue::ExcoNumber,
+            "counts.certified" => FieldValue::Certified,
+            "counts.not_certified" => FieldValue::NotCertified,
+            "times.start" => FieldValue::StartTime,
+            "times.end" => FieldValue::EndTime,
+            "date" => FieldValue::Date,
+            other => return Err(format!("unknown value source '{}'", other)),
+        };
+
+        Ok(value)
+    }
+}
+
+/// One entry of `formfiller.fields`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub question: FieldQuestion,
+    pub value: FieldValue,
+}
+
+impl Field {
+    /// Parse a field from its config table
+    pub fn from_config(table: &FormField) -> Result<Self, String> {
+        let question = match (table.index, &table.title, &table.entry) {
+            (_, _, Some(entry)) => FieldQuestion::Entry(
+                entry
+                    .trim()
+                    .trim_start_matches("entry.")
+                    .parse()
+                    .map_err(|_| format!("invalid entry id '{}'", entry))?,
+            ),
+            (_, Some(title), None) => FieldQuestion::Title(title.trim().to_string()),
+            (Some(idx), None, None) => FieldQuestion::Index(idx),
+            (None, None, None) => return Err("field needs an index, title or entry".to_string()),
+        };
+
+        let value = table
+            .value
+            .parse()
+            .map_err(|e| format!("field {}: {}", question, e))?;
+
+        Ok(Self { question, value })
+    }
+}
+
+/// Parse all of `formfiller.fields`
+pub fn fields_from_config(tables: &[FormField]) -> Result<Vec<Field>, String> {
+    tables.iter().map(Field::from_config).collect()
+}
+
+/// Values available for filling a logsheet
+#[derive(Clone, Debug)]
+pub struct LogsheetValues {
+    pub exco_name: String,
+    pub exco_number: String,
+    pub certified: usize,
+    pub not_certified: usize,
+    pub date: NaiveDate,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+}
+
+/// Short description of a question type, for error messages
+fn question_kind(question: &QuestionType) -> &'static str {
+    match question {
+        QuestionType::ShortAnswer(_) => "short answer",
+        QuestionType::LongAnswer(_) => "long answer",
+        QuestionType::MultipleChoice(_) => "multiple choice",
+        QuestionType::DropDown(_) => "drop down",
+        QuestionType::CheckBox(_) => "check box",
+        QuestionType::LinearScale(_) => "linear scale",
+        QuestionType::Grid => "grid",
+        QuestionType::Date(_) => "date",
+        QuestionType::Time(_) => "time",
+    }
+}
+
+/// Checks if a value can be filled into a question
+fn check_value(value: &FieldValue, question: &QuestionType) -> Result<(), String> {
+    let text = matches!(
+        question,
+        QuestionType::ShortAnswer(_) | QuestionType::LongAnswer(_)
+    );
+
+    let (ok, expected) = match value {
+        FieldValue::ExcoName
+        | FieldValue::ExcoNumber
+        | FieldValue::Certified
+        | FieldValue::NotCertified
+        | FieldValue::Literal(_) => (text, "a text question"),
+        FieldValue::StartTime | FieldValue::EndTime => {
+            (matches!(question, QuestionType::Time(_)), "a time question")
+        }
+        FieldValue::Date => (matches!(question, QuestionType::Date(_)), "a date question"),
+        FieldValue::Option(label) => {
+            let Some(options) = question.options() else {
+                return Err(format!(
+                    "expected a selection question, found {}",
+                    question_kind(question)
+                ));
+            };
+
+            if !options
+                .iter()
+                .any(|opt| opt.trim().eq_ignore_ascii_case(label))
+            {
+                return Err(format!(
+                    "no option \"{}\", options are: {}",
+                    label,
+                    options
+                        .iter()
+                        .map(|opt| format!("\"{}\"", opt))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ));
+            }
+
+            (true, "")
+        }
+        FieldValue::OptionIndex(idx) => match question.options() {
+            Some(options) if *idx < options.len() => (true, ""),
+            Some(options) => {
+                return Err(format!(
+                    "no option {}, there are {} options",
+                    idx,
+                    options.len()
+                ))
+            }
+            None => (false, "a selection question"),
+        },
+    };
+
+    match ok {
+        true => Ok(()),
+        false => Err(format!(
+            "expected {}, found {}",
+            expected,
+            question_kind(question)
+        )),
+    }
+}
+
+/// Match each field to a question in the form, returning their indices.
+///
+/// Every question must be mapped by exactly one field.
+/// If the form does not match, the error lists each difference.
+pub fn resolve(fields: &[Field], questions: &[QuestionHeader]) -> Result<Vec<usize>, String> {
+    let mut diff: Vec<String> = Vec::new();
+    let mut indices: Vec<usize> = Vec::new();
+    let mut mapped = vec![false; questions.len()];
+
+    for field in fields {
+        let idx = match &field.question {
+            FieldQuestion::Index(idx) => (*idx < questions.len()).then_some(*idx),
+            FieldQuestion::Title(title) => questions.iter().position(|qn| {
+                qn.title
+                    .as_ref()
+                    .is_some_and(|t| t.trim().eq_ignore_ascii_case(title))
+            }),
+            FieldQuestion::Entry(id) => questions.iter().position(|qn| qn.id == *id),
+        };
+
+        let Some(idx) = idx else {
+            diff.push(format!("- {}: not found in form", field.question));
+            continue;
+        };
+
+        if mapped[idx] {
+            diff.push(format!(
+                "- {}: question {} is already mapped by another field",
+                field.question, idx
+            ));
+            continue;
+        }
+        mapped[idx] = true;
+
+        if let Err(e) = check_value(&field.value, &questions[idx].question_type) {
+            diff.push(format!("- {}: {}", field.question, e));
+            continue;
+        }
+
+        indices.push(idx);
+    }
+
+    for (idx, qn) in questions
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| !mapped[*idx])
+    {
+        diff.push(format!(
+            "+ question {} \"{}\" ({}, entry.{}) is not mapped",
+            idx,
+            qn.title.as_deref().unwrap_or(""),
+            question_kind(&qn.question_type),
+            qn.id
+        ));
+    }
+
+    match diff.len() {
+        0 => Ok(indices),
+        _ => Err(format!(
+            "logsheet form does not match formfiller.fields:\n{}",
+            diff.join("\n")
+        )),
+    }
+}
+
+/// The text of a value, as it would be filled in
+pub fn describe(value: &FieldValue, values: &LogsheetValues) -> String {
+    match value {
+        FieldValue::ExcoName => values.exco_name.clone(),
+        FieldValue::ExcoNumber => values.exco_number.clone(),
+        FieldValue::Certified => values.certified.to_string(),
+        FieldValue::NotCertified => values.not_certified.to_string(),
+        FieldValue::StartTime => values.start_time.format("%H:%M").to_string(),
+        FieldValue::EndTime => values.end_time.format("%H:%M").to_string(),
+        FieldValue::Date => values.date.format("%Y-%m-%d").to_string(),
+        FieldValue::Literal(text) => text.clone(),
+        FieldValue::Option(label) => label.clone(),
+        FieldValue::OptionIndex(idx) => format!("option {}", idx),
+    }
+}
+
+/// Fill a question with the value of a field
+pub fn fill(
+    question: &mut QuestionType,
+    value: &FieldValue,
+    values: &LogsheetValues,
+) -> Result<(), String> {
+    let res = match value {
+        FieldValue::ExcoName => question.fill_str(&values.exco_name),
+        FieldValue::ExcoNumber => question.fill_str(&values.exco_number),
+        FieldValue::Certified => question.fill_number(values.certified.into()),
+        FieldValue::NotCertified => question.fill_number(values.not_certified.into()),
+        FieldValue::StartTime => question.fill_time(values.start_time),
+        FieldValue::EndTime => question.fill_time(values.end_time),
+        FieldValue::Date => question.fill_date(values.date.and_time(values.start_time)),
+        FieldValue::Literal(text) => question.fill_str(text),
+        FieldValue::Option(label) => question.fill_option_label(label),
+        FieldValue::OptionIndex(idx) => question.fill_option(*idx),
+    };
+
+    res.map_err(|e| format!("{:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(title: &str, id: u64, question_type: QuestionType) -> QuestionHeader {
+        QuestionHeader {
+            title: Some(title.to_string()),
+            id,
+            description: None,
+            question_type,
+        }
+    }
+
+    fn field(title: &str, value: &str) -> Field {
+        Field::from_config(&FormField {
+            title: Some(title.to_string()),
+            value: value.to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn indexed(index: usize, value: &str) -> Field {
+        Field::from_config(&FormField {
+            index: Some(index),
+            value: value.to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_field_from_config() {
+        assert_eq!(
+            field("Name", "exco.name"),
+            Field {
+                question: FieldQuestion::Title("Name".to_string()),
+                value: FieldValue::ExcoName
+            }
+        );
+
+        let by_index = Field::from_config(&FormField {
+            index: Some(3),
+            value: "option_index:2".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(by_index.question, FieldQuestion::Index(3));
+        assert_eq!(by_index.value, FieldValue::OptionIndex(2));
+
+        let by_entry = Field::from_config(&FormField {
+            entry: Some("entry.1234".to_string()),
+            value: "literal: NTU".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(by_entry.question, FieldQuestion::Entry(1234));
+        assert_eq!(by_entry.value, FieldValue::Literal(" NTU".to_string()));
+
+        assert!(Field::from_config(&FormField {
+            value: "date".to_string(),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(Field::from_config(&FormField {
+            title: Some("Name".to_string()),
+            value: "exco.age".to_string(),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let questions = vec![
+            question("Name", 100, QuestionType::ShortAnswer(Default::default())),
+            question("Start Time", 101, QuestionType::Time(Default::default())),
+            question(
+                "Date of Training",
+                102,
+                QuestionType::Date(Default::default()),
+            ),
+        ];
+
+        let fields = vec![
+            field("date of training", "date"),
+            field(" Name ", "exco.name"),
+            Field {
+                question: FieldQuestion::Entry(101),
+                value: FieldValue::StartTime,
+            },
+        ];
+        assert_eq!(resolve(&fields, &questions), Ok(vec![2, 0, 1]));
+
+        // form changed: a question was renamed and a type no longer matches
+        let fields = vec![
+            field("Name", "exco.name"),
+            field("Start Time", "counts.certified"),
+            field("Training Date", "date"),
+        ];
+        let err = resolve(&fields, &questions).unwrap_err();
+
+        assert!(err.contains("- \"Start Time\": expected a text question, found time"));
+        assert!(err.contains("- \"Training Date\": not found in form"));
+        assert!(err.contains("+ question 2 \"Date of Training\" (date, entry.102) is not mapped"));
+        assert!(!err.contains("\"Name\""), "{}", err);
+    }
+
+    #[test]
+    fn test_template_fields() {
+        let settings = ntu_canoebot_config::settings();
+        let fields = fields_from_config(&settings.formfiller.fields).unwrap();
+
+        // the default mapping goes by title, so reordered questions are caught
+        for field in fields.iter() {
+            assert!(
+                matches!(field.question, FieldQuestion::Title(_)),
+                "{:?}",
+                field
+            );
+        }
+        assert_eq!(
+            fields[4].question,
+            FieldQuestion::Title("Number of Certified Participants".to_string())
+        );
+        assert_eq!(fields[4].value, FieldValue::Certified);
+        assert_eq!(fields[3].value, FieldValue::Option("Training".to_string()));
+    }
+
+    #[test]
+    fn test_resolve_index() {
+        let questions = vec![
+            question("Name", 100, QuestionType::ShortAnswer(Default::default())),
+            question("Start Time", 101, QuestionType::Time(Default::default())),
+        ];
+
+        let fields = vec![indexed(1, "times.start"), indexed(0, "exco.name")];
+        assert_eq!(resolve(&fields, &questions), Ok(vec![1, 0]));
+
+        // the form changed: questions moved or were added
+        let fields = vec![indexed(0, "option_index:2"), indexed(2, "times.start")];
+        let err = resolve(&fields, &questions).unwrap_err();
+        assert!(
+            err.contains("- question 0: expected a selection question, found short answer"),
+            "{}",
+            err
+        );
+        assert!(err.contains("- question 2: not found in form"), "{}", err);
+    }
+}
@@ -0,0 +1,63 @@
+//! Records the latency and failures of sheet fetches.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use g_sheets::{SheetSource, SheetWriter};
+use ntu_canoebot_util::metrics::{Counter, Histogram, LATENCY_BUCKETS};
+use polars::prelude::DataFrame;
+
+const FETCH_SECONDS: Histogram = Histogram::new(
+    "canoebot_sheet_fetch_seconds",
+    "time taken to fetch a sheet",
+    LATENCY_BUCKETS,
+);
+const FETCH_FAILURES: Counter = Counter::new(
+    "canoebot_sheet_fetch_failures_total",
+    "sheet fetches that failed",
+);
+
+/// A [SheetSource] that records metrics for every fetch
+pub(crate) struct MeteredSource(pub Arc<dyn SheetSource>);
+
+#[async_trait]
+impl SheetSource for MeteredSource {
+    async fn get_as_dataframe(
+        &self,
+        sheet_id: &str,
+        sheet_name: Option<&str>,
+    ) -> g_sheets::Result<DataFrame> {
+        let start = Instant::now();
+        let result = self.0.get_as_dataframe(sheet_id, sheet_name).await;
+
+        FETCH_SECONDS.observe(&[], start.elapsed().as_secs_f64());
+        if result.is_err() {
+            FETCH_FAILURES.increment(&[]);
+        }
+
+        result
+    }
+
+    fn as_writer(&self) -> Option<&dyn SheetWriter> {
+        self.0.as_writer()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use g_sheets::DirSource;
+
+    #[tokio::test]
+    async fn test_metered_failure() {
+        let dir = std::env::temp_dir().join("attd_test_metered_failure");
+        let source = MeteredSource(Arc::new(DirSource::new(&dir)));
+
+        assert!(source.get_as_dataframe("nope", None).await.is_err());
+
+        let rendered = ntu_canoebot_util::metrics::render();
+        assert!(rendered.contains("canoebot_sheet_fetch_failures_total "));
+        assert!(rendered.contains("canoebot_sheet_fetch_seconds_count "));
+    }
+}
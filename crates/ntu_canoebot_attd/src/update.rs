@@ -3,28 +3,37 @@
 use std::{
     collections::{HashMap, HashSet},
     error::Error,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use polars::prelude::DataFrame;
 
+use crate::fleet::{fleet_from_df, Boat};
 use crate::{
-    dataframe_cell_to_string, Config, ATTENDANCE_SHEETS, BOATS, BOAT_ALLOCATIONS, EXCO_NAMES,
-    NAMES_CERTS, PROGRAM_SHEETS, SHORTENED_NAMES,
+    attendance_sheets, dataframe_cell_to_string, normalize_telegram_handle, program_sheets,
+    sheet_source, Config, BOATS, BOAT_ALLOCATIONS, EXCO_NAMES, NAMES, NAMES_CERTS, PARTNERS,
+    SHORTENED_NAMES, TELEGRAM_HANDLES,
 };
 use ntu_canoebot_config as config;
 
+/// Set once the configs sheets have loaded
+static CONFIGS_LOADED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the configs sheets have loaded since startup
+pub fn configs_loaded() -> bool {
+    CONFIGS_LOADED.load(Ordering::Relaxed)
+}
+
 /// Performs lookup and stuff and updates lazy-static globals
 async fn update_config_from_df(
     df: &DataFrame,
     config: Config,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let settings = config::settings();
+    let columns = &settings.sheetscraper.columns.attd;
+
     // SHORTENED_NAMES
-    let names_lookup = df
-        .columns([
-            config::SHEETSCRAPER_COLUMNS_ATTD_NAME,
-            config::SHEETSCRAPER_COLUMNS_ATTD_SHORT_NAME,
-        ])
-        .unwrap();
+    let names_lookup = df.columns([&columns.name, &columns.short_name])?;
 
     let filtered = names_lookup
         .chunks(2)
@@ -61,12 +70,7 @@ async fn update_config_from_df(
     drop(lock);
 
     // BOATS
-    let boat_list = df
-        .columns([
-            config::SHEETSCRAPER_COLUMNS_ATTD_BOAT_PRIMARY,
-            config::SHEETSCRAPER_COLUMNS_ATTD_BOAT_ALTERNATE,
-        ])
-        .unwrap();
+    let boat_list = df.columns([&columns.boat_primary, &columns.boat_alternate])?;
 
     let mut set: HashSet<String> = Default::default();
 
@@ -94,21 +98,28 @@ async fn update_config_from_df(
 
     // println!("boat set: {:?}", set);
 
+    // replaced by the fleet sheet, if there is one
     let mut lock = BOATS[config as usize].write().await;
     lock.clear();
-    lock.extend(set);
+    lock.extend(set.into_iter().map(|b| (b.clone(), Boat::unlisted(b))));
     drop(lock);
 
     // NAMES_CERTS
-    let names_and_certs = df
-        .columns([
-            config::SHEETSCRAPER_COLUMNS_ATTD_NAME,
-            config::SHEETSCRAPER_COLUMNS_ATTD_CERTIFICATION,
-        ])
-        .unwrap();
+    let names_and_certs = df.columns([&columns.name, &columns.certification])?;
+
+    let names = names_and_certs[0];
+    let certs = names_and_certs[1];
+
+    // NAMES
+    let all_names = names
+        .iter()
+        .map(dataframe_cell_to_string)
+        .filter(|name| !name.is_empty());
 
-    let names = names_and_certs.get(0).unwrap();
-    let certs = names_and_certs.get(1).unwrap();
+    let mut lock = NAMES[config as usize].write().await;
+    lock.clear();
+    lock.extend(all_names);
+    drop(lock);
 
     let filtered = names
         .iter()
@@ -176,7 +187,7 @@ async fn update_config_from_df(
     lock.extend(allocations);
     drop(lock);
 
-    let exco_id = df.column(config::SHEETSCRAPER_COLUMNS_ATTD_EXCO).unwrap();
+    let exco_id = df.column(&columns.exco)?;
     let short_lock = SHORTENED_NAMES[config as usize].read().await;
 
     let exco_names = names
@@ -211,12 +222,96 @@ async fn update_config_from_df(
     lock.extend(exco_names);
     drop(lock);
 
+    // TELEGRAM_HANDLES, optional column
+    let handles: HashMap<String, String> = match df.column(&columns.telegram) {
+        Ok(handle_col) => names
+            .iter()
+            .zip(handle_col.iter())
+            .filter_map(|(name, handle)| {
+                let name = dataframe_cell_to_string(name);
+                let handle = normalize_telegram_handle(&dataframe_cell_to_string(handle));
+
+                if name.is_empty() || handle.is_empty() {
+                    return None;
+                }
+
+                Some((handle, name))
+            })
+            .collect(),
+        Err(_) => {
+            log::warn!(
+                "{:?} configs sheet has no \"{}\" column",
+                config,
+                columns.telegram
+            );
+            Default::default()
+        }
+    };
+
+    let mut lock = TELEGRAM_HANDLES[config as usize].write().await;
+    lock.clear();
+    lock.extend(handles);
+    drop(lock);
+
+    // PARTNERS, optional column
+    let partners: HashMap<String, Vec<String>> = match df.column(&columns.partners) {
+        Ok(partner_col) => names
+            .iter()
+            .zip(partner_col.iter())
+            .filter_map(|(name, partners)| {
+                let name = dataframe_cell_to_string(name);
+                let partners: Vec<String> = split_partners(&dataframe_cell_to_string(partners))
+                    .map(|p| short_lock.get(p).cloned().unwrap_or(p.to_string()))
+                    .collect();
+
+                if name.is_empty() || partners.is_empty() {
+                    return None;
+                }
+
+                Some((short_lock.get(&name).cloned().unwrap_or(name), partners))
+            })
+            .collect(),
+        Err(_) => Default::default(),
+    };
+
+    let mut lock = PARTNERS[config as usize].write().await;
+    lock.clear();
+    lock.extend(partners);
+    drop(lock);
+
     Ok(())
 }
 
-/// Initialize/reload from the configs sheet
-pub async fn init() {
-    for (idx, sheet_id) in ATTENDANCE_SHEETS.iter().enumerate() {
+/// Names in a partners cell
+fn split_partners(cell: &str) -> impl Iterator<Item = &str> {
+    cell.split(',').map(str::trim).filter(|p| !p.is_empty())
+}
+
+/// Add boats from the fleet sheet, replacing those only named in the configs sheet
+async fn update_fleet_from_df(
+    df: &DataFrame,
+    config: Config,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (boats, problems) = fleet_from_df(df)?;
+    for problem in problems {
+        log::warn!("{:?} fleet sheet: {}", config, problem);
+    }
+
+    let mut lock = BOATS[config as usize].write().await;
+    lock.extend(boats.into_iter().map(|b| (b.name.clone(), b)));
+    drop(lock);
+
+    Ok(())
+}
+
+/// Initialize/reload from the configs sheet.
+///
+/// Stops at the first configs sheet that fails to load.
+pub async fn init() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let configuration_sheet = config::settings().sheetscraper.configuration_sheet.clone();
+    let fleet_sheet = config::settings().sheetscraper.fleet_sheet.clone();
+
+    for (idx, sheet_id) in attendance_sheets().iter().enumerate() {
         let conf: Config = idx.into();
 
         log::info!(
@@ -230,15 +325,35 @@ pub async fn init() {
         );
         match sheet_id {
             Some(id) => {
-                let df =
-                    g_sheets::get_as_dataframe(id, Some(config::SHEETSCRAPER_CONFIGURATION_SHEET))
-                        .await;
-                update_config_from_df(&df, idx.into()).await.unwrap()
+                let df = sheet_source()
+                    .await
+                    .get_as_dataframe(id, Some(&configuration_sheet))
+                    .await
+                    .map_err(|e| format!("{:?} configs sheet: {}", conf, e))?;
+                update_config_from_df(&df, conf)
+                    .await
+                    .map_err(|e| format!("{:?} configs sheet: {}", conf, e))?;
+
+                if fleet_sheet.is_empty() {
+                    continue;
+                }
+                let loaded = match sheet_source()
+                    .await
+                    .get_as_dataframe(id, Some(&fleet_sheet))
+                    .await
+                {
+                    Ok(df) => update_fleet_from_df(&df, conf).await,
+                    Err(e) => Err(e.into()),
+                };
+                // the fleet sheet is optional, so it never stops the configs from loading
+                if let Err(e) = loaded {
+                    log::warn!("{:?} fleet sheet not loaded: {}", conf, e);
+                }
             }
             None => {}
         }
     }
-    for (idx, sheet_id) in PROGRAM_SHEETS.iter().enumerate() {
+    for (idx, sheet_id) in program_sheets().iter().enumerate() {
         let conf: Config = idx.into();
         log::info!(
             "prog sheet: {:?}: {:?}",
@@ -250,16 +365,276 @@ pub async fn init() {
             }
         );
     }
+
+    CONFIGS_LOADED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Lists problems in a configs sheet that would otherwise be silently skipped.
+///
+/// Row numbers are as shown in the sheet, with the header on row 1.
+fn check_config_df(df: &DataFrame) -> Vec<String> {
+    let settings = config::settings();
+    let columns = &settings.sheetscraper.columns.attd;
+
+    let required = [
+        &columns.name,
+        &columns.short_name,
+        &columns.boat_primary,
+        &columns.boat_alternate,
+        &columns.certification,
+        &columns.exco,
+    ];
+    let missing: Vec<String> = required
+        .iter()
+        .filter(|col| df.column(col).is_err())
+        .map(|col| format!("missing column \"{}\"", col))
+        .collect();
+    if !missing.is_empty() {
+        return missing;
+    }
+
+    let cells = |col: &str| -> Vec<String> {
+        df.column(col)
+            .map(|c| c.iter().map(dataframe_cell_to_string).collect())
+            .unwrap_or_default()
+    };
+    let names = cells(&columns.name);
+    let short_names = cells(&columns.short_name);
+    let certs = cells(&columns.certification);
+    let excos = cells(&columns.exco);
+    let handles = cells(&columns.telegram);
+    let partners = cells(&columns.partners);
+
+    let mut problems = Vec::new();
+    if handles.is_empty() {
+        problems.push(format!(
+            "no \"{}\" column, /start will not find anyone by handle",
+            columns.telegram
+        ));
+    }
+
+    let mut seen_names: HashMap<&str, usize> = HashMap::new();
+    let mut seen_short: HashMap<&str, usize> = HashMap::new();
+    let mut seen_handles: HashMap<String, usize> = HashMap::new();
+
+    for (idx, name) in names.iter().enumerate() {
+        let row = idx + 2;
+        if name.is_empty() {
+            continue;
+        }
+
+        if let Some(first) = seen_names.insert(name, row) {
+            problems.push(format!(
+                "row {}: \"{}\" is also on row {}",
+                row, name, first
+            ));
+        }
+
+        let short = short_names.get(idx).filter(|s| !s.is_empty());
+        if let Some(first) = short.and_then(|s| seen_short.insert(s, row)) {
+            problems.push(format!(
+                "row {}: short name \"{}\" is also used on row {}",
+                row,
+                short.unwrap(),
+                first
+            ));
+        }
+
+        let cert = &certs[idx];
+        if cert.parse::<u8>().is_err() {
+            problems.push(format!(
+                "row {}: \"{}\" has certification \"{}\", which is not a number and is left out of logsheet counts",
+                row, name, cert
+            ));
+        }
+
+        let exco = &excos[idx];
+        if !exco.is_empty() && exco.parse::<u8>().is_err() {
+            problems.push(format!(
+                "row {}: \"{}\" has exco \"{}\", which is not a number and is treated as not exco",
+                row, name, exco
+            ));
+        }
+
+        for partner in partners
+            .get(idx)
+            .map(|p| split_partners(p))
+            .into_iter()
+            .flatten()
+        {
+            if !names.iter().chain(short_names.iter()).any(|n| n == partner) {
+                problems.push(format!(
+                    "row {}: \"{}\" has partner \"{}\", who is not in the sheet",
+                    row, name, partner
+                ));
+            }
+        }
+
+        let handle = handles
+            .get(idx)
+            .map(|h| normalize_telegram_handle(h))
+            .filter(|h| !h.is_empty());
+        if let Some(handle) = handle {
+            if let Some(first) = seen_handles.insert(handle.clone(), row) {
+                problems.push(format!(
+                    "row {}: telegram handle \"{}\" is also on row {}",
+                    row, handle, first
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Lists problems in a fleet sheet, and allocated boats that are not in it
+fn check_fleet_df(df: &DataFrame, configs: &DataFrame) -> Vec<String> {
+    let (boats, mut problems) = match fleet_from_df(df) {
+        Ok(res) => res,
+        Err(e) => return vec![e.to_string()],
+    };
+
+    let settings = config::settings();
+    let columns = &settings.sheetscraper.columns.attd;
+    let Ok(allocated) = configs.columns([&columns.boat_primary, &columns.boat_alternate]) else {
+        // reported with the configs sheet
+        return problems;
+    };
+
+    let mut missing: Vec<String> = allocated
+        .iter()
+        .flat_map(|col| col.iter().map(dataframe_cell_to_string))
+        .filter(|b| !b.is_empty() && !boats.iter().any(|boat| boat.name == *b))
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect();
+    missing.sort();
+
+    problems.extend(missing.into_iter().map(|b| {
+        format!(
+            "\"{}\" is allocated in the configs sheet but not listed, and is treated as a single seater",
+            b
+        )
+    }));
+
+    problems
+}
+
+/// Check the configs sheets for problems, without loading them.
+///
+/// Returns a list of problems, prefixed with the config they were found in.
+pub async fn check() -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+    let configuration_sheet = config::settings().sheetscraper.configuration_sheet.clone();
+    let fleet_sheet = config::settings().sheetscraper.fleet_sheet.clone();
+    let mut problems = Vec::new();
+
+    for (idx, sheet_id) in attendance_sheets().iter().enumerate() {
+        let conf: Config = idx.into();
+        let Some(id) = sheet_id else {
+            continue;
+        };
+
+        let df = sheet_source()
+            .await
+            .get_as_dataframe(id, Some(&configuration_sheet))
+            .await
+            .map_err(|e| format!("{:?} configs sheet: {}", conf, e))?;
+
+        problems.extend(
+            check_config_df(&df)
+                .into_iter()
+                .map(|p| format!("{:?} configs sheet: {}", conf, p)),
+        );
+
+        if fleet_sheet.is_empty() {
+            continue;
+        }
+        let fleet = match sheet_source()
+            .await
+            .get_as_dataframe(id, Some(&fleet_sheet))
+            .await
+        {
+            Ok(fleet_df) => check_fleet_df(&fleet_df, &df),
+            Err(e) => vec![format!("not loaded: {}", e)],
+        };
+        problems.extend(
+            fleet
+                .into_iter()
+                .map(|p| format!("{:?} fleet sheet: {}", conf, p)),
+        );
+    }
+
+    Ok(problems)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use g_sheets::{DirSource, SheetSource};
+
+    #[tokio::test]
+    async fn test_check_config_df() {
+        let root = std::env::temp_dir().join("attd_test_check_config_df");
+        let spreadsheet = root.join("some_id");
+        std::fs::create_dir_all(&spreadsheet).unwrap();
+
+        let columns = config::settings().sheetscraper.columns.attd.clone();
+        let contents = format!(
+            "{},{},{},{},{},{},{},{}\n\
+            alice tan,alice,K1,,1,1,@alice,bob\n\
+            bob lim,bob,K2,,0,,@Alice,\"alice tan, zed\"\n\
+            alice tan,al,,,yes,0,,\n\
+            ,,,,,,,\n\
+            carl ng,bob,,,1,maybe,,\n",
+            columns.name,
+            columns.short_name,
+            columns.boat_primary,
+            columns.boat_alternate,
+            columns.certification,
+            columns.exco,
+            columns.telegram,
+            columns.partners,
+        );
+        std::fs::write(spreadsheet.join("configs.csv"), contents).unwrap();
+
+        let df = DirSource::new(&root)
+            .get_as_dataframe("some_id", Some("configs"))
+            .await
+            .unwrap();
+        let problems = check_config_df(&df);
+
+        let expected = [
+            "row 3: \"bob lim\" has partner \"zed\", who is not in the sheet",
+            "row 3: telegram handle \"alice\" is also on row 2",
+            "row 4: \"alice tan\" is also on row 2",
+            "row 4: \"alice tan\" has certification \"yes\"",
+            "row 6: short name \"bob\" is also used on row 3",
+            "row 6: \"carl ng\" has exco \"maybe\"",
+        ];
+        assert_eq!(problems.len(), expected.len(), "{:#?}", problems);
+        for e in expected {
+            assert!(
+                problems.iter().any(|p| p.starts_with(e)),
+                "missing: {}\nfound: {:#?}",
+                e,
+                problems
+            );
+        }
+
+        let df = df.drop(&columns.exco).unwrap();
+        assert_eq!(
+            check_config_df(&df),
+            vec![format!("missing column \"{}\"", columns.exco)]
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
 
     /// Test initializing all globals
     #[tokio::test]
     async fn test_init_statics() {
-        init().await;
+        init().await.unwrap();
 
         let x = SHORTENED_NAMES[0].read().await;
         println!("{:#?}", x);
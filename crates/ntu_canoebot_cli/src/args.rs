@@ -0,0 +1,180 @@
+//! Command line argument parsing
+
+use chrono::{NaiveDate, NaiveTime};
+
+pub const USAGE: &str = "\
+usage: canoebot-cli [options] <command> [date] [am|pm] [flags]
+
+commands:
+    namelist        names for a session
+    paddling        names with boat allocations and the training program
+    training        training program
+    breakdown       weekly attendance numbers
+    land            land training
+    logsheet        fill in the SCF logsheet without submitting it
+    check-configs   list problems in the configs sheets
+
+options:
+    --config-dir <dir>  read settings from this directory
+    --sheets-dir <dir>  read sheets from csv files in this directory
+
+flags:
+    --freshies          use the freshie sheet (namelist, paddling, land)
+    --no-deconflict     skip boat deconfliction (paddling)
+    --start <HHMM>      logsheet start time
+    --end <HHMM>        logsheet end time
+    --offset <n>        add to (or subtract from) the logsheet participant count
+
+date is YYYY-MM-DD, and defaults to tomorrow. the session defaults to am.";
+
+/// Something to run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Namelist,
+    Paddling,
+    Training,
+    Breakdown,
+    Land,
+    Logsheet,
+    CheckConfigs,
+}
+
+impl std::str::FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let command = match s {
+            "namelist" => Command::Namelist,
+            "paddling" => Command::Paddling,
+            "training" => Command::Training,
+            "breakdown" => Command::Breakdown,
+            "land" => Command::Land,
+            "logsheet" => Command::Logsheet,
+            "check-configs" => Command::CheckConfigs,
+            other => return Err(format!("unknown command \"{}\"", other)),
+        };
+
+        Ok(command)
+    }
+}
+
+/// Parsed command line arguments
+#[derive(Clone, Debug, PartialEq)]
+pub struct Args {
+    pub command: Command,
+    pub date: NaiveDate,
+    /// `false` for AM, `true` for PM
+    pub time_slot: bool,
+    pub freshies: bool,
+    pub deconflict: bool,
+    pub start: Option<NaiveTime>,
+    pub end: Option<NaiveTime>,
+    pub offset: i32,
+    pub config_dir: Option<String>,
+    pub sheets_dir: Option<String>,
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s, "%H%M").map_err(|_| format!("invalid time \"{}\"", s))
+}
+
+impl Args {
+    /// Parse arguments, excluding the program name.
+    ///
+    /// `today` is used for the default date.
+    pub fn parse<I>(args: I, today: NaiveDate) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter();
+
+        let mut command = None;
+        let mut date = None;
+        let mut time_slot = None;
+        let mut parsed = Args {
+            command: Command::Namelist,
+            date: today,
+            time_slot: false,
+            freshies: false,
+            deconflict: true,
+            start: None,
+            end: None,
+            offset: 0,
+            config_dir: None,
+            sheets_dir: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| args.next().ok_or(format!("{} needs a value", flag));
+
+            match arg.as_str() {
+                "--freshies" => parsed.freshies = true,
+                "--no-deconflict" => parsed.deconflict = false,
+                "--start" => parsed.start = Some(parse_time(&value(&arg)?)?),
+                "--end" => parsed.end = Some(parse_time(&value(&arg)?)?),
+                "--offset" => {
+                    let v = value(&arg)?;
+                    parsed.offset = v.parse().map_err(|_| format!("invalid offset \"{}\"", v))?;
+                }
+                "--config-dir" => parsed.config_dir = Some(value(&arg)?),
+                "--sheets-dir" => parsed.sheets_dir = Some(value(&arg)?),
+                "am" | "AM" if time_slot.is_none() => time_slot = Some(false),
+                "pm" | "PM" if time_slot.is_none() => time_slot = Some(true),
+                flag if flag.starts_with("--") => return Err(format!("unknown flag {}", flag)),
+                other if command.is_none() => command = Some(other.parse()?),
+                other if date.is_none() => {
+                    date = Some(
+                        NaiveDate::parse_from_str(other, "%Y-%m-%d")
+                            .map_err(|_| format!("invalid date \"{}\"", other))?,
+                    )
+                }
+                other => return Err(format!("unexpected argument \"{}\"", other)),
+            }
+        }
+
+        parsed.command = command.ok_or("no command given")?;
+        parsed.date = date.unwrap_or(today + chrono::Duration::days(1));
+        parsed.time_slot = time_slot.unwrap_or(false);
+
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Args, String> {
+        let today = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        Args::parse(args.split_whitespace().map(String::from), today)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse("namelist").unwrap();
+        assert_eq!(args.command, Command::Namelist);
+        assert_eq!(args.date, NaiveDate::from_ymd_opt(2024, 2, 1).unwrap());
+        assert!(!args.time_slot);
+
+        let args = parse("--sheets-dir fixtures paddling 2024-03-02 pm --freshies --no-deconflict")
+            .unwrap();
+        assert_eq!(args.command, Command::Paddling);
+        assert_eq!(args.date, NaiveDate::from_ymd_opt(2024, 3, 2).unwrap());
+        assert!(args.time_slot);
+        assert!(args.freshies);
+        assert!(!args.deconflict);
+        assert_eq!(args.sheets_dir.as_deref(), Some("fixtures"));
+
+        let args = parse("logsheet pm --start 1545 --offset -2").unwrap();
+        assert_eq!(args.start, NaiveTime::from_hms_opt(15, 45, 0));
+        assert_eq!(args.end, None);
+        assert_eq!(args.offset, -2);
+
+        assert!(parse("").is_err());
+        assert!(parse("nameslist").is_err());
+        assert!(parse("namelist 2024-02-30").is_err());
+        assert!(parse("namelist --start").is_err());
+        assert!(parse("namelist --verbose").is_err());
+        assert!(parse("namelist am pm").is_err());
+    }
+}
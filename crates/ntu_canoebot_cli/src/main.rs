@@ -0,0 +1,141 @@
+//! Command line access to attendance operations, for debugging
+//! and scripting without going through telegram.
+//!
+//! Uses the same settings as the bot. Run with `--help` for usage.
+
+mod args;
+
+use std::error::Error;
+
+use g_sheets::DirSource;
+use ntu_canoebot_attd::NameList;
+
+use ntu_canoebot_config as config;
+
+use args::{Args, Command, USAGE};
+
+#[tokio::main]
+async fn main() {
+    pretty_env_logger::init();
+
+    let raw: Vec<String> = std::env::args().skip(1).collect();
+    if raw.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let args = match Args::parse(raw, chrono::Local::now().date_naive()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(dir) = &args.config_dir {
+        std::env::set_var(config::CONFIG_DIR_ENV, dir);
+    }
+    // settings are loaded lazily, check them here instead of panicking later
+    if let Err(e) = config::load() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    if let Some(dir) = &args.sheets_dir {
+        ntu_canoebot_attd::set_sheet_source(DirSource::new(dir)).await;
+    }
+
+    if let Err(e) = run(&args).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: &Args) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if args.command == Command::CheckConfigs {
+        let problems = ntu_canoebot_attd::check_configs().await?;
+        if problems.is_empty() {
+            println!("no problems found");
+            return Ok(());
+        }
+
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        return Err(format!("{} problem(s) found", problems.len()).into());
+    }
+
+    // short names, boats and certifications come from the configs sheet
+    ntu_canoebot_attd::init().await?;
+
+    let text = match args.command {
+        Command::Namelist => namelist(args).await?.to_string(),
+        Command::Paddling => {
+            let mut name_list = namelist(args).await?;
+            let unseated = name_list.assign_boats(args.deconflict).await;
+            // freshies do not follow prog
+            if !args.freshies {
+                name_list.fill_prog(false).await?;
+            }
+
+            let mut text = name_list.to_string();
+            if !unseated.is_empty() {
+                text += "\n\nno boat:";
+                for u in unseated {
+                    text += &format!("\n{}", u);
+                }
+            }
+            text
+        }
+        Command::Training => ntu_canoebot_attd::training_prog(args.date)
+            .await?
+            .get_formatted_prog(args.date, args.time_slot)
+            .unwrap_or_default(),
+        Command::Breakdown => ntu_canoebot_attd::breakdown(args.date, args.time_slot)
+            .await?
+            .to_string(),
+        Command::Land => {
+            let mut name_list = ntu_canoebot_attd::land(args.date, args.freshies).await?;
+            name_list.fill_prog(true).await?;
+
+            name_list.to_string()
+        }
+        Command::Logsheet => {
+            let filled = ntu_canoebot_attd::logsheet::dry_run(
+                args.date,
+                args.time_slot,
+                args.start,
+                args.end,
+                args.offset,
+            )
+            .await?;
+
+            filled
+                .answers
+                .iter()
+                .map(|(question, answer)| format!("{}: {}", question, answer))
+                .collect::<Vec<String>>()
+                .join("\n")
+        }
+        Command::CheckConfigs => unreachable!(),
+    };
+
+    println!("{}", text);
+    Ok(())
+}
+
+/// Namelist for a session, blank if the date is not in the sheet
+async fn namelist(args: &Args) -> Result<NameList, Box<dyn Error + Send + Sync>> {
+    let name_list = ntu_canoebot_attd::namelist(args.date, args.time_slot, args.freshies)
+        .await?
+        .unwrap_or_else(|| {
+            log::warn!(
+                "{} {} is not in the attendance sheet",
+                args.date,
+                if args.time_slot { "PM" } else { "AM" }
+            );
+            NameList::from_date_time(args.date, args.time_slot)
+        });
+
+    Ok(name_list)
+}
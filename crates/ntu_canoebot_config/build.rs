@@ -1,3 +0,0 @@
-fn main() {
-    toml_const::run();
-}
@@ -0,0 +1,67 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use crate::Problem;
+
+/// Errors encountered while loading settings
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A config file could not be read
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+
+    /// A config file is not valid toml
+    Parse {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+
+    /// An environment variable override could not be applied
+    Env { var: String, reason: String },
+
+    /// The merged settings do not match what the bot expects
+    Invalid {
+        source: Option<PathBuf>,
+        error: toml::de::Error,
+    },
+
+    /// The merged settings failed validation
+    Problems(Vec<Problem>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => {
+                write!(f, "unable to read {}: {}", path.display(), error)
+            }
+            ConfigError::Parse { path, error } => {
+                write!(f, "unable to parse {}: {}", path.display(), error)
+            }
+            ConfigError::Env { var, reason } => write!(f, "invalid override {}: {}", var, reason),
+            ConfigError::Invalid { source, error } => {
+                let message = error.to_string();
+                match source {
+                    Some(path) => write!(
+                        f,
+                        "invalid settings (template + {}): {}",
+                        path.display(),
+                        message.trim_end()
+                    ),
+                    None => write!(f, "invalid settings (template): {}", message.trim_end()),
+                }
+            }
+            ConfigError::Problems(problems) => {
+                write!(f, "{} problem(s) in settings:", problems.len())?;
+                for problem in problems {
+                    write!(f, "\n{}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
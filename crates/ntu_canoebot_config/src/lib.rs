@@ -1,51 +1,153 @@
 //! A crate for accessing variables inside configuration files.
 //!
 //! ## Configuration sources
-//! Configuration settings are read in from these 3 sources,
-//! in the `.configs` directory (project root):
+//! Configuration settings are read in at runtime from these 3 sources,
+//! in the config directory (see [config_dir]):
 //! - `botsettings.template.toml` (already exists)
-//! - `botsettings.template.debug.toml` (optional)
-//! - `botsettings.template.deploy.toml` (optional)
+//! - `botsettings.debug.toml` (optional)
+//! - `botsettings.deploy.toml` (optional)
 //!
 //! Either `debug` or `deploy` should exist.
 //! If both do not exist, the template file will be used.
 //! This **will** result in runtime panics!
 //!
+//! If only one file exists, it is used.
 //! If both files exist, the first that contains the key-value pair
-//! `use = true` will be used.
-//! `deploy` will be checked first, followed by `debug`.
+//! `use = true` will be used. `deploy` will be checked first, followed by `debug`.
 //!
-//! If neither file has the `use = true` pair set, the build process will exit.
+//! Keys in the chosen file override matching keys in the template.
 //!
-//! ## Usage
-//! - All nested tables are destructured to become absolute keys.
-//! - Each inner table (namespace) is separated by an underscore `_`
-//! - Absolute keys can be any toml type except for tables
-//! - Absolute keys are stored directly as their toml data type
-//! - Absolute keys that point to arrays need to have each array element to be the same type
-//! - Last level tables (tables that do not contain tables) are also stored as a const hashmap. All hashmap values are strings.
-//! - All keys are stored in uppercase.
+//! ## Environment overrides
+//! Single keys can be overridden with environment variables prefixed with `CANOEBOT__`,
+//! with nested keys separated by double underscores.
+//! Values are parsed as toml, or used as strings if that fails.
 //!
+//! ```sh
+//! CANOEBOT__SHEETSCRAPER__CACHE__ATTD=45
+//! CANOEBOT__CANOEBOT__ERROR_REPORTS__ENABLED=false
 //! ```
-//! use ntu_canoebot_config as config;
 //!
-//! /// retrieve the api key by absolute key
-//! let key: &str = *config::CANOEBOT_APIKEY;
+//! `[[events]]` is an array and cannot be overridden this way. Variables for the
+//! old event tables, like `CANOEBOT__EVENTS__DAILY__LOGSHEET_PROMPT__TIME`,
+//! are still applied to the matching event.
+//!
+//! ## Usage
+//! Settings are loaded on first use or by calling [init],
+//! and can be reloaded while running with [reload].
+//! Hold on to the returned [Arc] only as long as needed, so reloaded values are picked up.
 //!
-//! /// retrieve a config by it's absolute key
-//! let is_enabled: bool = *config::EVENTS_DAILY_LOGSHEET_PROMPT_ENABLE;
+//! ```no_run
+//! use ntu_canoebot_config as config;
 //!
-//! /// retrieve the same config from a lookup table as a string
-//! let is_enabled: &str = config::EVENTS_DAILY_LOGSHEET_PROMPT.get("ENABLE").unwrap();
+//! /// retrieve the api key
+//! let key: String = config::settings().canoebot.apikey.clone();
 //!
+//! /// retrieve a nested setting
+//! let use_deconflict: bool = config::settings().sheetscraper.use_deconflict;
 //! ```
-#![recursion_limit = "256"]
-#![allow(unused)]
-include!("../generated.rs");
-
-#[cfg(test)]
-mod tests {
-    // included here so that build.rs can unit test as well
-    #![allow(unused)]
-    include!("../build.rs");
+
+mod error;
+mod load;
+mod schedule;
+mod settings;
+mod validate;
+
+use std::sync::{Arc, RwLock};
+
+use lazy_static::lazy_static;
+use toml::Table;
+
+pub use error::ConfigError;
+pub use load::{
+    apply_env_overrides, changed_keys, config_dir, config_files, from_table, load, load_from,
+    load_table, merge_tables, read_layers, CONFIG_DIR_ENV, DEBUG_FILE, DEPLOY_FILE, ENV_PREFIX,
+    TEMPLATE_FILE,
+};
+pub use schedule::Schedule;
+pub use settings::*;
+pub use validate::{check_from, Problem};
+
+/// Settings along with the table they were read from
+struct Loaded {
+    settings: Arc<Settings>,
+    table: Table,
+}
+
+impl Loaded {
+    /// Load settings without validating them
+    fn load() -> Result<Self, ConfigError> {
+        let (table, source) = load_table(&config_dir(), std::env::vars())?;
+        let settings = from_table(table.clone(), source)?;
+
+        Ok(Self {
+            settings: Arc::new(settings),
+            table,
+        })
+    }
+
+    /// Load and validate settings
+    fn check() -> Result<Self, ConfigError> {
+        let (settings, table) = validate::check_table(&config_dir(), std::env::vars())?;
+
+        Ok(Self {
+            settings: Arc::new(settings),
+            table,
+        })
+    }
+}
+
+lazy_static! {
+    /// Currently loaded settings
+    static ref SETTINGS: RwLock<Option<Loaded>> = RwLock::new(None);
+}
+
+/// Validate settings in the config directory, leaving the current settings as they are
+pub fn check() -> Result<Settings, ConfigError> {
+    check_from(&config_dir(), std::env::vars())
+}
+
+/// Load and validate settings from the config directory, replacing any loaded settings.
+pub fn init() -> Result<(), ConfigError> {
+    let loaded = Loaded::check()?;
+    *SETTINGS.write().unwrap() = Some(loaded);
+
+    Ok(())
+}
+
+/// Load settings again, swapping them in only if they are valid.
+///
+/// Returns the keys that changed. On error, the current settings are kept.
+pub fn reload() -> Result<Vec<String>, ConfigError> {
+    let loaded = Loaded::check()?;
+
+    let mut lock = SETTINGS.write().unwrap();
+    let changed = match lock.as_ref() {
+        Some(current) => changed_keys(&current.table, &loaded.table),
+        None => changed_keys(&Table::new(), &loaded.table),
+    };
+    *lock = Some(loaded);
+
+    Ok(changed)
+}
+
+/// Returns the current settings.
+///
+/// Settings are loaded on first use if [init] has not been called.
+/// These are not validated, see [check].
+///
+/// ## Panics
+/// If settings have to be loaded and are invalid.
+pub fn settings() -> Arc<Settings> {
+    if let Some(loaded) = SETTINGS.read().unwrap().as_ref() {
+        return loaded.settings.clone();
+    }
+
+    SETTINGS
+        .write()
+        .unwrap()
+        .get_or_insert_with(|| {
+            Loaded::load().unwrap_or_else(|e| panic!("unable to load settings: {}", e))
+        })
+        .settings
+        .clone()
 }
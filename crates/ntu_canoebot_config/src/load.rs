@@ -0,0 +1,528 @@
+//! Reading and layering of config files and environment overrides.
+
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use crate::{ConfigError, Settings};
+
+/// Defaults, always read
+pub const TEMPLATE_FILE: &str = "botsettings.template.toml";
+pub const DEBUG_FILE: &str = "botsettings.debug.toml";
+pub const DEPLOY_FILE: &str = "botsettings.deploy.toml";
+
+/// Overrides the directory config files are read from
+pub const CONFIG_DIR_ENV: &str = "CANOEBOT_CONFIG_DIR";
+
+/// Prefix of environment variables that override single keys,
+/// e.g. `CANOEBOT__SHEETSCRAPER__CACHE__ATTD=45`
+pub const ENV_PREFIX: &str = "CANOEBOT__";
+
+/// Separates keys in environment variable overrides
+const ENV_SEPARATOR: &str = "__";
+
+/// Old `[events.<period>.<name>]` tables, with the action of the event that replaced each
+const LEGACY_EVENTS: [(&str, &str, &str); 3] = [
+    ("daily", "logsheet_prompt", "logsheet_prompt"),
+    ("daily", "attendance_reminder", "paddling_prompt"),
+    ("weekly", "breakdown", "breakdown"),
+];
+
+/// Directory containing the config files.
+///
+/// In order: [CONFIG_DIR_ENV], `.configs` in the working directory,
+/// then `.configs` in the project root.
+pub fn config_dir() -> PathBuf {
+    if let Ok(dir) = std::env::var(CONFIG_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+
+    let local = PathBuf::from(".configs");
+    if local.join(TEMPLATE_FILE).exists() {
+        return local;
+    }
+
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../.configs")
+}
+
+/// Read a toml file, returning `None` if it does not exist.
+fn read_table(path: &Path) -> Result<Option<Table>, ConfigError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            return Err(ConfigError::Io {
+                path: path.to_owned(),
+                error,
+            })
+        }
+    };
+
+    toml::from_str(&contents)
+        .map(Some)
+        .map_err(|error| ConfigError::Parse {
+            path: path.to_owned(),
+            error,
+        })
+}
+
+/// Read a toml file that has to exist
+pub(crate) fn read_required(path: &Path) -> Result<Table, ConfigError> {
+    read_table(path)?.ok_or(ConfigError::Io {
+        path: path.to_owned(),
+        error: std::io::ErrorKind::NotFound.into(),
+    })
+}
+
+fn uses(table: &Table) -> bool {
+    table.get("use").and_then(Value::as_bool).unwrap_or(false)
+}
+
+/// Pick the override file to merge over the template.
+///
+/// If only one of debug or deploy exists, it is used.
+/// If both exist, the one with `use = true` is used, deploy first.
+fn select_override(
+    debug: Option<(PathBuf, Table)>,
+    deploy: Option<(PathBuf, Table)>,
+) -> Option<(PathBuf, Table)> {
+    match (debug, deploy) {
+        (Some(debug), Some(deploy)) => match (uses(&debug.1), uses(&deploy.1)) {
+            (_, true) => Some(deploy),
+            (true, false) => Some(debug),
+            (false, false) => {
+                log::warn!("both debug and deploy files exist, but neither has `use = true`");
+                None
+            }
+        },
+        (Some(file), None) | (None, Some(file)) => Some(file),
+        (None, None) => {
+            log::warn!("debug/deploy file missing, using template defaults");
+            None
+        }
+    }
+}
+
+/// Merge matching keys from changes into template, recursing into tables.
+pub fn merge_tables(template: &Table, changes: &Table) -> Table {
+    let mut merged = template.clone();
+
+    for (key, value) in changes {
+        if let (Some(Value::Table(existing)), Value::Table(changes)) = (merged.get(key), value) {
+            let sub = merge_tables(existing, changes);
+            merged.insert(key.clone(), Value::Table(sub));
+            continue;
+        }
+
+        merged.insert(key.clone(), value.clone());
+    }
+
+    merged
+}
+
+/// Rewrite old `[events.daily.*]` and `[events.weekly.*]` tables in an
+/// override file as an `[[events]]` array.
+///
+/// Each old table sets `enable` and the time of the template event with the
+/// same action. The weekly breakdown was sent on wednesdays.
+fn migrate_events(template: &Table, changes: &mut Table) {
+    let Some(Value::Table(legacy)) = changes.get("events") else {
+        return;
+    };
+    let Some(Value::Array(events)) = template.get("events") else {
+        return;
+    };
+    log::warn!("[events.daily.*] and [events.weekly.*] are deprecated, move them to [[events]]");
+
+    let mut events = events.clone();
+    for (period, name, action) in LEGACY_EVENTS {
+        let Some(Value::Table(old)) = legacy.get(period).and_then(|p| p.get(name)) else {
+            continue;
+        };
+        let Some(event) = events
+            .iter_mut()
+            .filter_map(Value::as_table_mut)
+            .find(|e| e.get("action").and_then(Value::as_str) == Some(action))
+        else {
+            continue;
+        };
+
+        if let Some(enable) = old.get("enable") {
+            event.insert("enable".to_string(), enable.clone());
+        }
+        if let Some(Value::Datetime(toml::value::Datetime {
+            time: Some(time), ..
+        })) = old.get("time")
+        {
+            let day = match period {
+                "weekly" => "weekly wed",
+                _ => "daily",
+            };
+            let schedule = format!("{} {:02}:{:02}", day, time.hour, time.minute);
+            event.insert("schedule".to_string(), Value::String(schedule));
+        }
+    }
+
+    changes.insert("events".to_string(), Value::Array(events));
+}
+
+/// Parse an override value as toml, falling back to a plain string.
+fn parse_env_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("v = {}", raw))
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or(Value::String(raw.to_string()))
+}
+
+/// Apply `CANOEBOT__SECTION__KEY=value` overrides.
+///
+/// Keys are matched ignoring case, new keys are lowercase.
+/// Variables for the old event tables are applied like the tables, see [migrate_events].
+pub fn apply_env_overrides<I>(table: &mut Table, vars: I) -> Result<(), ConfigError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let legacy_prefix = format!("{}EVENTS{}", ENV_PREFIX, ENV_SEPARATOR);
+    let (legacy, vars): (Vec<_>, Vec<_>) = vars
+        .into_iter()
+        .partition(|(var, _)| var.to_uppercase().starts_with(&legacy_prefix));
+
+    if !legacy.is_empty() {
+        let mut changes = Table::new();
+        apply_env_vars(&mut changes, legacy)?;
+        migrate_events(table, &mut changes);
+        *table = merge_tables(table, &changes);
+    }
+
+    apply_env_vars(table, vars)
+}
+
+fn apply_env_vars<I>(table: &mut Table, vars: I) -> Result<(), ConfigError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    for (var, raw) in vars {
+        let Some(path) = var.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        let keys: Vec<&str> = path.split(ENV_SEPARATOR).collect();
+        if keys.iter().any(|k| k.is_empty()) {
+            return Err(ConfigError::Env {
+                var,
+                reason: "empty key".to_string(),
+            });
+        }
+
+        let mut current = &mut *table;
+        for (idx, key) in keys.iter().enumerate() {
+            let key = current
+                .keys()
+                .find(|k| k.eq_ignore_ascii_case(key))
+                .cloned()
+                .unwrap_or(key.to_lowercase());
+
+            if idx == keys.len() - 1 {
+                current.insert(key, parse_env_value(&raw));
+                break;
+            }
+
+            let next = current
+                .entry(key.clone())
+                .or_insert(Value::Table(Table::new()));
+            current = match next {
+                Value::Table(t) => t,
+                _ => {
+                    return Err(ConfigError::Env {
+                        var,
+                        reason: format!("`{}` is not a table", key),
+                    })
+                }
+            };
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the template and override files in a directory, merged together.
+///
+/// Returns the merged table and the override file used, if any.
+pub fn read_layers(dir: &Path) -> Result<(Table, Option<PathBuf>), ConfigError> {
+    let template = read_required(&dir.join(TEMPLATE_FILE))?;
+
+    let read = |name: &str| -> Result<Option<(PathBuf, Table)>, ConfigError> {
+        let path = dir.join(name);
+        Ok(read_table(&path)?.map(|t| (path, t)))
+    };
+
+    match select_override(read(DEBUG_FILE)?, read(DEPLOY_FILE)?) {
+        Some((path, mut changes)) => {
+            migrate_events(&template, &mut changes);
+            Ok((merge_tables(&template, &changes), Some(path)))
+        }
+        None => Ok((template, None)),
+    }
+}
+
+/// Paths of all config files in a directory, whether they exist or not
+pub fn config_files(dir: &Path) -> [PathBuf; 3] {
+    [TEMPLATE_FILE, DEBUG_FILE, DEPLOY_FILE].map(|name| dir.join(name))
+}
+
+/// Read the merged settings table from a directory,
+/// applying overrides from environment variables.
+pub fn load_table<I>(dir: &Path, vars: I) -> Result<(Table, Option<PathBuf>), ConfigError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let (mut table, source) = read_layers(dir)?;
+    apply_env_overrides(&mut table, vars)?;
+
+    Ok((table, source))
+}
+
+/// Deserialize settings from a merged table
+pub fn from_table(table: Table, source: Option<PathBuf>) -> Result<Settings, ConfigError> {
+    let mut settings: Settings =
+        Value::Table(table)
+            .try_into()
+            .map_err(|error| ConfigError::Invalid {
+                source: source.clone(),
+                error,
+            })?;
+    settings.source = source;
+
+    Ok(settings)
+}
+
+/// Load settings from a directory, applying overrides from environment variables.
+pub fn load_from<I>(dir: &Path, vars: I) -> Result<Settings, ConfigError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let (table, source) = load_table(dir, vars)?;
+    from_table(table, source)
+}
+
+/// Dotted paths of keys that differ between two tables.
+///
+/// Tables are compared key by key, anything else (including arrays) as a whole.
+pub fn changed_keys(old: &Table, new: &Table) -> Vec<String> {
+    fn diff(prefix: &str, old: &Table, new: &Table, changed: &mut Vec<String>) {
+        let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+        keys.sort();
+        keys.dedup();
+
+        for key in keys {
+            let path = match prefix.len() {
+                0 => key.to_string(),
+                _ => format!("{}.{}", prefix, key),
+            };
+
+            match (old.get(key), new.get(key)) {
+                (Some(Value::Table(old)), Some(Value::Table(new))) => {
+                    diff(&path, old, new, changed)
+                }
+                (old, new) if old != new => changed.push(path),
+                _ => (),
+            }
+        }
+    }
+
+    let mut changed = Vec::new();
+    diff("", old, new, &mut changed);
+    changed
+}
+
+/// Load settings from [config_dir] and the process environment
+pub fn load() -> Result<Settings, ConfigError> {
+    load_from(&config_dir(), std::env::vars())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(s: &str) -> Table {
+        toml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_merge_tables() {
+        let template = table("a = 1\n[b]\nc = 2\nd = 3\n[e]\nf = [1, 2]");
+        let changes = table("a = 10\n[b]\nd = 30\n[e]\nf = [3]\n[g]\nh = 1");
+
+        let merged = merge_tables(&template, &changes);
+        assert_eq!(
+            merged,
+            table("a = 10\n[b]\nc = 2\nd = 30\n[e]\nf = [3]\n[g]\nh = 1")
+        );
+    }
+
+    #[test]
+    fn test_select_override() {
+        let file =
+            |name: &str, use_: bool| Some((PathBuf::from(name), table(&format!("use = {}", use_))));
+        let selected = |debug, deploy| select_override(debug, deploy).map(|(p, _)| p);
+
+        assert_eq!(selected(file("debug", false), None), Some("debug".into()));
+        assert_eq!(selected(None, file("deploy", false)), Some("deploy".into()));
+        assert_eq!(
+            selected(file("debug", true), file("deploy", true)),
+            Some("deploy".into())
+        );
+        assert_eq!(
+            selected(file("debug", true), file("deploy", false)),
+            Some("debug".into())
+        );
+        assert_eq!(selected(file("debug", false), file("deploy", false)), None);
+        assert_eq!(selected(None, None), None);
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let mut t =
+            table("[sheetscraper.cache]\nattd = 30\n[canoebot.misc_handlers]\nMISC_AYO = true");
+
+        let vars = [
+            ("CANOEBOT__SHEETSCRAPER__CACHE__ATTD", "45"),
+            ("CANOEBOT__CANOEBOT__MISC_HANDLERS__MISC_AYO", "false"),
+            ("CANOEBOT__CANOEBOT__APIKEY", "12345:abc"),
+            ("CANOEBOT__EVENTS__DAILY__TIME", "07:30:00"),
+            ("CANOEBOT_CONFIG_DIR", "ignored"),
+            ("PATH", "ignored"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        apply_env_overrides(&mut t, vars).unwrap();
+
+        assert_eq!(
+            t,
+            table(
+                "[sheetscraper.cache]\nattd = 45\n[canoebot]\napikey = \"12345:abc\"\n\
+                [canoebot.misc_handlers]\nMISC_AYO = false\n[events.daily]\ntime = 07:30:00"
+            )
+        );
+
+        let bad = [(
+            "CANOEBOT__SHEETSCRAPER__CACHE__ATTD__X".to_string(),
+            "1".to_string(),
+        )];
+        assert!(apply_env_overrides(&mut t, bad).is_err());
+    }
+
+    #[test]
+    fn test_migrate_events() {
+        let template = table(
+            "[[events]]\nname = \"logsheet prompt\"\nenable = true\nschedule = \"daily 07:00\"\n\
+            action = \"logsheet_prompt\"\n\
+            [[events]]\nname = \"weekly breakdown\"\nenable = true\nschedule = \"weekly wed 22:30\"\n\
+            action = \"breakdown\"",
+        );
+
+        let mut changes = table(
+            "use = true\n[events.daily.logsheet_prompt]\nenable = false\ntime = 06:30:00\n\
+            [events.weekly.breakdown]\ntime = 21:00:00",
+        );
+        migrate_events(&template, &mut changes);
+        assert_eq!(
+            changes,
+            table(
+                "use = true\n\
+                [[events]]\nname = \"logsheet prompt\"\nenable = false\nschedule = \"daily 06:30\"\n\
+                action = \"logsheet_prompt\"\n\
+                [[events]]\nname = \"weekly breakdown\"\nenable = true\nschedule = \"weekly wed 21:00\"\n\
+                action = \"breakdown\""
+            )
+        );
+
+        // already an array
+        let mut new = changes.clone();
+        migrate_events(&template, &mut new);
+        assert_eq!(new, changes);
+    }
+
+    #[test]
+    fn test_env_legacy_events() {
+        let mut t = table(
+            "[[events]]\nname = \"logsheet prompt\"\nenable = true\nschedule = \"daily 07:00\"\n\
+            action = \"logsheet_prompt\"",
+        );
+
+        let vars = [(
+            "CANOEBOT__EVENTS__DAILY__LOGSHEET_PROMPT__TIME".to_string(),
+            "07:30:00".to_string(),
+        )];
+        apply_env_overrides(&mut t, vars).unwrap();
+        assert_eq!(
+            t,
+            table(
+                "[[events]]\nname = \"logsheet prompt\"\nenable = true\nschedule = \"daily 07:30\"\n\
+                action = \"logsheet_prompt\""
+            )
+        );
+    }
+
+    #[test]
+    fn test_changed_keys() {
+        let old = table("use = true\n[a]\nb = 1\nc = [1, 2]\n[a.d]\ne = \"x\"\n[f]\ng = 1");
+        let new = table("use = true\n[a]\nb = 2\nc = [1]\n[a.d]\ne = \"x\"\n[h]\ni = 1");
+
+        assert_eq!(changed_keys(&old, &new), vec!["a.b", "a.c", "f", "h"]);
+        assert!(changed_keys(&old, &old).is_empty());
+    }
+
+    /// The template on its own has to be loadable
+    #[test]
+    fn test_load_template() {
+        let dir = std::env::temp_dir().join("config_test_load_template");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy(config_dir().join(TEMPLATE_FILE), dir.join(TEMPLATE_FILE)).unwrap();
+
+        let settings = load_from(&dir, []).unwrap();
+        assert!(!settings.use_);
+        assert!(settings.source.is_none());
+        assert_eq!(settings.sheetscraper.layout.attd.block_pre_padding, 3);
+        assert_eq!(
+            settings.sheetscraper.paddling.times.am.arrive,
+            chrono::NaiveTime::from_hms_opt(7, 20, 0).unwrap()
+        );
+        assert_eq!(settings.events.len(), 4);
+        assert_eq!(settings.events[2].action, crate::EventAction::Breakdown);
+
+        std::fs::write(
+            dir.join(DEPLOY_FILE),
+            "use = true\n[sheetscraper.cache]\nattd = \"soon\"",
+        )
+        .unwrap();
+        let err = load_from(&dir, []).unwrap_err().to_string();
+        assert!(err.contains(DEPLOY_FILE), "{}", err);
+        assert!(err.contains("sheetscraper.cache.attd"), "{}", err);
+
+        let vars = [(
+            "CANOEBOT__SHEETSCRAPER__CACHE__ATTD".to_string(),
+            "5".to_string(),
+        )];
+        let settings = load_from(&dir, vars).unwrap();
+        assert!(settings.use_);
+        assert_eq!(settings.sheetscraper.cache.attd, 5);
+        assert_eq!(settings.source, Some(dir.join(DEPLOY_FILE)));
+
+        // deploy files from before [[events]]
+        std::fs::write(
+            dir.join(DEPLOY_FILE),
+            "use = true\n[events.daily.attendance_reminder]\nenable = false\ntime = 18:00:00",
+        )
+        .unwrap();
+        let settings = load_from(&dir, []).unwrap();
+        assert_eq!(settings.events.len(), 4);
+        assert_eq!(
+            settings.events[1].action,
+            crate::EventAction::PaddlingPrompt
+        );
+        assert!(!settings.events[1].enable);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+//! Schedules for bot events.
+//!
+//! A schedule is written as one of:
+//! - `daily 07:00`
+//! - `weekly sat 22:30`
+//! - a cron expression, `minute hour day month weekday`, such as `30 22 * * sat`
+//!
+//! Times are local, and daily/weekly times may include seconds. Cron fields take
+//! `*`, numbers, ranges `1-5`, lists `1,3` and steps `*/15` or `0-30/10`.
+//! Months and weekdays may also be names, and weekdays 0 and 7 are both sunday.
+//! Like cron, if both the day and weekday are restricted, either one can match.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday};
+use serde::Deserialize;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Days searched for the next run, enough for every 29th of february
+const SEARCH_DAYS: i64 = 366 * 8;
+
+/// When an event runs
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Schedule {
+    /// As written in settings
+    spec: String,
+    second: u32,
+    /// A bit for each minute, hour, etc.
+    minutes: u64,
+    hours: u32,
+    /// Days of the month, from bit 1
+    days: u32,
+    /// Months, from bit 1
+    months: u16,
+    /// Weekdays, from sunday
+    weekdays: u8,
+    /// Both day fields are restricted, and either can match
+    either_day: bool,
+}
+
+/// Bits from `min` to `max`, every `step`
+fn bits(min: u32, max: u32, step: u32) -> u64 {
+    (min..=max)
+        .step_by(step as usize)
+        .fold(0, |acc, b| acc | 1 << b)
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .map_err(|_| format!("invalid time \"{}\", expected HH:MM", s))
+}
+
+/// Parse a cron field into bits
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let value = match names.iter().position(|n| s.eq_ignore_ascii_case(n)) {
+            Some(idx) => idx as u32 + min,
+            None => s
+                .parse()
+                .map_err(|_| format!("invalid value \"{}\" in \"{}\"", s, field))?,
+        };
+
+        match (min..=max).contains(&value) {
+            true => Ok(value),
+            false => Err(format!(
+                "{} in \"{}\" is not within {}-{}",
+                value, field, min, max
+            )),
+        }
+    };
+
+    let mut acc = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => return Err(format!("invalid step \"{}\" in \"{}\"", step, field)),
+            },
+            None => (part, None),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/10` is every 10 from 5
+            None if step.is_some() => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if start > end {
+            return Err(format!("range \"{}\" in \"{}\" is backwards", range, field));
+        }
+
+        acc |= bits(start, end, step.unwrap_or(1));
+    }
+
+    Ok(acc)
+}
+
+impl Schedule {
+    /// Every day at a time
+    fn at(spec: &str, time: NaiveTime) -> Self {
+        Self {
+            spec: spec.to_string(),
+            second: time.second(),
+            minutes: 1 << time.minute(),
+            hours: 1 << time.hour(),
+            days: bits(1, 31, 1) as u32,
+            months: bits(1, 12, 1) as u16,
+            weekdays: bits(0, 6, 1) as u8,
+            either_day: false,
+        }
+    }
+
+    fn parse_cron(spec: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = spec.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "\"{}\" should be \"daily HH:MM\", \"weekly <day> HH:MM\", \
+                or a cron expression with 5 fields",
+                spec
+            ));
+        };
+
+        let weekdays = parse_field(weekday, 0, 7, &WEEKDAYS)?;
+        Ok(Self {
+            spec: spec.to_string(),
+            second: 0,
+            minutes: parse_field(minute, 0, 59, &[])?,
+            hours: parse_field(hour, 0, 23, &[])? as u32,
+            days: parse_field(day, 1, 31, &[])? as u32,
+            months: parse_field(month, 1, 12, &MONTHS)? as u16,
+            // sunday is both 0 and 7
+            weekdays: ((weekdays | weekdays >> 7) & 0x7F) as u8,
+            either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+        })
+    }
+
+    fn on_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match self.either_day {
+            true => day || weekday,
+            false => day && weekday,
+        }
+    }
+
+    /// Times in a day, earliest first
+    fn times(&self) -> impl DoubleEndedIterator<Item = NaiveTime> + '_ {
+        (0..24)
+            .filter(|h| self.hours & (1 << h) != 0)
+            .flat_map(move |h| {
+                (0..60)
+                    .filter(|m| self.minutes & (1 << m) != 0)
+                    .map(move |m| (h, m))
+            })
+            .filter_map(|(h, m)| NaiveTime::from_hms_opt(h, m, self.second))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// First run after a time, [None] if it never runs (e.g. on the 31st of february)
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..SEARCH_DAYS)
+            .map(|d| after.date() + Duration::days(d))
+            .filter(|date| self.on_date(*date))
+            .find_map(|date| {
+                self.times()
+                    .map(|t| date.and_time(t))
+                    .find(|run| *run > after)
+            })
+    }
+
+    /// Latest run at or before a time
+    pub fn last_before(&self, at: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..SEARCH_DAYS)
+            .map(|d| at.date() - Duration::days(d))
+            .filter(|date| self.on_date(*date))
+            .find_map(|date| {
+                self.times()
+                    .rev()
+                    .map(|t| date.and_time(t))
+                    .find(|run| *run <= at)
+            })
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let spec = s.trim();
+        let words: Vec<&str> = spec.split_whitespace().collect();
+
+        match words[..] {
+            ["daily", time] => Ok(Self::at(spec, parse_time(time)?)),
+            ["weekly", day, time] => {
+                let day: Weekday = day
+                    .parse()
+                    .map_err(|_| format!("invalid weekday \"{}\"", day))?;
+
+                let mut schedule = Self::at(spec, parse_time(time)?);
+                schedule.weekdays = 1 << day.num_days_from_sunday();
+                Ok(schedule)
+            }
+            _ => Self::parse_cron(spec),
+        }
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        assert_eq!(
+            "daily 07:00".parse::<Schedule>(),
+            "0 7 * * *".parse::<Schedule>().map(|mut s| {
+                s.spec = "daily 07:00".to_string();
+                s
+            })
+        );
+        assert!("weekly Saturday 22:30:15".parse::<Schedule>().is_ok());
+        assert!("*/15 9-17 1,15 jan-jun mon-fri".parse::<Schedule>().is_ok());
+
+        for bad in [
+            "",
+            "daily",
+            "daily 25:00",
+            "weekly someday 07:00",
+            "* * * *",
+            "60 * * * *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * smarch *",
+        ] {
+            assert!(bad.parse::<Schedule>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_next_after() {
+        let daily: Schedule = "daily 07:00".parse().unwrap();
+        assert_eq!(
+            daily.next_after(at("2024-01-31 06:59:59")),
+            Some(at("2024-01-31 07:00:00"))
+        );
+        assert_eq!(
+            daily.next_after(at("2024-01-31 07:00:00")),
+            Some(at("2024-02-01 07:00:00"))
+        );
+
+        // 2024-02-03 is a saturday
+        let weekly: Schedule = "weekly sat 22:30:30".parse().unwrap();
+        assert_eq!(
+            weekly.next_after(at("2024-01-31 12:00:00")),
+            Some(at("2024-02-03 22:30:30"))
+        );
+
+        let cron: Schedule = "*/20 9,17 * * mon-fri".parse().unwrap();
+        assert_eq!(
+            cron.next_after(at("2024-02-02 17:40:00")),
+            Some(at("2024-02-05 09:00:00"))
+        );
+        assert_eq!(
+            cron.next_after(at("2024-02-05 09:00:00")),
+            Some(at("2024-02-05 09:20:00"))
+        );
+
+        // the 1st, or any sunday
+        let either: Schedule = "0 12 1 * 7".parse().unwrap();
+        assert_eq!(
+            either.next_after(at("2024-02-01 13:00:00")),
+            Some(at("2024-02-04 12:00:00"))
+        );
+        assert_eq!(
+            either.next_after(at("2024-02-25 13:00:00")),
+            Some(at("2024-03-01 12:00:00"))
+        );
+
+        let leap: Schedule = "0 0 29 feb *".parse().unwrap();
+        assert_eq!(
+            leap.next_after(at("2024-03-01 00:00:00")),
+            Some(at("2028-02-29 00:00:00"))
+        );
+
+        let never: Schedule = "0 0 31 feb *".parse().unwrap();
+        assert_eq!(never.next_after(at("2024-01-01 00:00:00")), None);
+    }
+
+    #[test]
+    fn test_last_before() {
+        let daily: Schedule = "daily 07:00".parse().unwrap();
+        assert_eq!(
+            daily.last_before(at("2024-01-31 07:00:00")),
+            Some(at("2024-01-31 07:00:00"))
+        );
+        assert_eq!(
+            daily.last_before(at("2024-01-31 06:59:59")),
+            Some(at("2024-01-30 07:00:00"))
+        );
+
+        let cron: Schedule = "30 */6 * * *".parse().unwrap();
+        assert_eq!(
+            cron.last_before(at("2024-01-31 17:00:00")),
+            Some(at("2024-01-31 12:30:00"))
+        );
+    }
+}
@@ -0,0 +1,511 @@
+//! Typed bot settings, mirroring the layout of `botsettings.template.toml`.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::Schedule;
+
+/// All bot settings
+#[derive(Clone, Debug, Deserialize)]
+pub struct Settings {
+    /// Set when a debug/deploy file overrides the template
+    #[serde(rename = "use")]
+    pub use_: bool,
+
+    pub canoebot: Canoebot,
+    pub sheetscraper: SheetScraper,
+    pub formfiller: FormFiller,
+    pub traininglog: TrainingLog,
+    pub src: Src,
+    pub events: Vec<Event>,
+    pub misc: Misc,
+    pub logger: Logger,
+    pub facilities: Vec<Facility>,
+
+    /// Override file the settings were loaded from, if any
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Canoebot {
+    pub apikey: String,
+    /// Chat id, 0 if unset
+    pub exco_chat: i64,
+    /// Persistent bot data (linked users, etc.)
+    pub data_dir: String,
+    /// Alias -> chat id
+    pub known_chats: HashMap<String, i64>,
+    /// Alias -> telegram user id
+    pub admins: HashMap<String, u64>,
+    /// Alias -> telegram user id, for exco-only commands
+    pub exco_members: HashMap<String, u64>,
+    pub misc_handlers: HashMap<String, bool>,
+    pub callback_store: CallbackStore,
+    pub webhook: Webhook,
+    pub monitor: Monitor,
+    pub error_reports: ErrorReports,
+}
+
+impl Canoebot {
+    /// Chat id for an alias in `known_chats`, or [EXCO_CHAT]
+    pub fn chat_id(&self, alias: &str) -> Option<i64> {
+        match self.known_chats.get(alias) {
+            Some(id) => Some(*id),
+            None if alias == EXCO_CHAT && self.exco_chat != 0 => Some(self.exco_chat),
+            None => None,
+        }
+    }
+}
+
+/// Storage for callback data that does not fit in a button
+#[derive(Clone, Debug, Deserialize)]
+pub struct CallbackStore {
+    pub enabled: bool,
+    /// Maximum number of stored callbacks
+    pub capacity: usize,
+    pub expiry_days: i64,
+    /// Save stored callbacks in the data dir
+    pub persist: bool,
+}
+
+/// Webhook server, with the `webhook` feature
+#[derive(Clone, Debug, Deserialize)]
+pub struct Webhook {
+    pub address: SocketAddr,
+    /// Public url for telegram, empty if unset
+    pub url: String,
+    /// Generated if empty
+    pub secret_token: String,
+}
+
+/// Health check and metrics endpoints
+#[derive(Clone, Debug, Deserialize)]
+pub struct Monitor {
+    pub enabled: bool,
+    pub address: SocketAddr,
+}
+
+/// Forwarding errors from handlers to an admin chat
+#[derive(Clone, Debug, Deserialize)]
+pub struct ErrorReports {
+    pub enabled: bool,
+    /// Alias in `known_chats`, or [EXCO_CHAT]
+    pub chat: String,
+    /// Seconds before the same error is forwarded again
+    pub cooldown: u64,
+    /// Most errors forwarded in an hour
+    pub max_per_hour: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SheetScraper {
+    pub use_deconflict: bool,
+    pub configuration_sheet: String,
+    /// Optional sheet of boats, in the same spreadsheet as the configs sheet
+    pub fleet_sheet: String,
+
+    /// Sheet name format
+    pub sheet_label: String,
+    /// Format of the date cell in the top left of the sheet
+    pub date_format: String,
+    /// Date column format
+    pub date_format_prog: String,
+
+    /// Switch from old -> new on this date
+    #[serde(deserialize_with = "de::date")]
+    pub changeover_date: NaiveDate,
+
+    pub cache: Cache,
+    pub source: Source,
+    pub layout: Layout,
+    pub columns: Columns,
+    pub old: SheetIds,
+    pub new: SheetIds,
+    pub paddling: Paddling,
+}
+
+/// Cache lifetimes, in minutes
+#[derive(Clone, Debug, Deserialize)]
+pub struct Cache {
+    pub attd: i64,
+    pub prog: i64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Source {
+    /// One of "gviz", "dir" or "api"
+    pub backend: String,
+    pub dir: String,
+    pub api: SourceApi,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SourceApi {
+    pub key_file: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Layout {
+    pub attd: AttdLayout,
+    pub land: LandLayout,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AttdLayout {
+    pub fencing: Fencing,
+    /// Non-data columns before each 7-day block
+    pub block_pre_padding: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LandLayout {
+    pub fencing: Fencing,
+}
+
+/// Rows/cols of the sheet that are cut out prior to processing
+#[derive(Clone, Debug, Deserialize)]
+pub struct Fencing {
+    pub left: usize,
+    pub top: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Columns {
+    pub attd: AttdColumns,
+    pub prog: ProgColumns,
+    pub fleet: FleetColumns,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AttdColumns {
+    pub name: String,
+    pub short_name: String,
+    pub boat_primary: String,
+    pub boat_alternate: String,
+    pub certification: String,
+    pub exco: String,
+    /// Optional column
+    pub telegram: String,
+    /// Optional column, comma separated names of preferred crew partners
+    pub partners: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FleetColumns {
+    pub name: String,
+    /// K1, K2, K4, C1 or C2
+    pub class: String,
+    /// Defaults to the class's seats if blank
+    pub seats: String,
+    /// Blank or "ok" if the boat can be used
+    pub status: String,
+    /// Optional column
+    pub notes: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProgColumns {
+    pub date: String,
+    pub am: String,
+    pub pm: String,
+}
+
+/// Sheet ids for one AY configuration. Empty strings are unfilled.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SheetIds {
+    pub attendance_sheet: String,
+    pub program_sheet: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Paddling {
+    pub format: String,
+    pub header_format: String,
+    pub footer_format: String,
+
+    pub sub_session: String,
+    pub sub_date: String,
+    pub sub_boatallo: String,
+    pub sub_exclude: String,
+    pub sub_prog: String,
+    pub sub_fetch: String,
+    pub sub_header: String,
+    pub sub_footer: String,
+    pub sub_arrive: String,
+    pub sub_start: String,
+    pub sub_finish: String,
+
+    pub freshie_sheet_suffix: String,
+
+    pub times: PaddlingTimes,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PaddlingTimes {
+    /// Time between arrival time and start time in minutes
+    pub arrive_to_start_delta_mins: i64,
+    pub am: ArriveFinish,
+    pub pm: ArriveFinish,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ArriveFinish {
+    #[serde(deserialize_with = "de::time")]
+    pub arrive: NaiveTime,
+    #[serde(deserialize_with = "de::time")]
+    pub finish: NaiveTime,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FormFiller {
+    /// Mock google forms submission
+    pub mock: bool,
+    pub form_id: String,
+    pub times: FormTimes,
+    pub particulars: Vec<Particulars>,
+    pub fields: Vec<FormField>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FormTimes {
+    pub am: StartEnd,
+    pub pm: StartEnd,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StartEnd {
+    #[serde(deserialize_with = "de::time")]
+    pub start: NaiveTime,
+    #[serde(deserialize_with = "de::time")]
+    pub end: NaiveTime,
+}
+
+/// Name and number of an exco, filled into logsheets
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Particulars {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, deserialize_with = "de::string_or_number")]
+    pub number: String,
+}
+
+/// A question in the logsheet form and the value it is filled with
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct FormField {
+    /// Position of the question in the form, from 0
+    pub index: Option<usize>,
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "de::option_string_or_number")]
+    pub entry: Option<String>,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TrainingLog {
+    pub form_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Src {
+    /// Cache refresh interval, minutes
+    pub cache_refresh: i64,
+}
+
+/// Alias for `canoebot.exco_chat` in event chats
+pub const EXCO_CHAT: &str = "exco";
+
+/// Something the bot does on a schedule
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Event {
+    /// Unique, used to remember when the event last ran
+    pub name: String,
+    pub enable: bool,
+    pub schedule: Schedule,
+    /// Alias in `canoebot.known_chats`, or [EXCO_CHAT]
+    pub chat: String,
+    pub action: EventAction,
+    /// Text for [EventAction::Message]
+    #[serde(default)]
+    pub text: String,
+    /// Run a missed event after a restart if it was due this many hours ago or less.
+    /// 0 to skip missed runs.
+    #[serde(default)]
+    pub catch_up_hours: i64,
+}
+
+/// What an event does
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventAction {
+    /// Paddling attendance for the next day
+    PaddlingPrompt,
+    /// Attendance breakdown for the next week
+    Breakdown,
+    /// Logsheet for the day, unless it was already sent
+    LogsheetPrompt,
+    /// Counts of errors reported since the last digest, if there were any
+    ErrorDigest,
+    /// The event's text
+    Message,
+}
+
+impl Display for EventAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match self {
+            EventAction::PaddlingPrompt => "paddling_prompt",
+            EventAction::Breakdown => "breakdown",
+            EventAction::LogsheetPrompt => "logsheet_prompt",
+            EventAction::ErrorDigest => "error_digest",
+            EventAction::Message => "message",
+        };
+
+        write!(f, "{}", action)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Misc {
+    pub handlers: MiscHandlers,
+    pub silence: Silence,
+}
+
+/// Replies to plain messages
+#[derive(Clone, Debug, Deserialize)]
+pub struct MiscHandlers {
+    /// Seconds between replies from a handler in the same chat
+    pub cooldown: u64,
+    /// Characters in a message for `MISC_LONGMSG`
+    pub long_message: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Silence {
+    /// The laser crab template, png
+    pub crab_url: String,
+    pub text: SilenceText,
+    pub pic: SilencePic,
+    /// Lifetime of downloaded images, minutes
+    pub fs_cache_lifetime: u64,
+    pub offensive_names: Vec<String>,
+}
+
+/// Relative starting position of text, from top left of template
+#[derive(Clone, Debug, Deserialize)]
+pub struct SilenceText {
+    pub x_frac: f64,
+    pub y_frac: f64,
+}
+
+/// Location and size of the profile picture, relative to the crab image
+#[derive(Clone, Debug, Deserialize)]
+pub struct SilencePic {
+    pub row_frac: f64,
+    pub col_frac: f64,
+    pub width_frac: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Logger {
+    /// Log to file + stderr
+    pub log_to_file: bool,
+    pub file: String,
+    pub log_level: String,
+}
+
+/// An SRC facility
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Facility {
+    pub name: String,
+    pub shortname: String,
+    pub codename: String,
+    pub courts: u8,
+}
+
+/// Deserializers for values that toml does not map directly
+mod de {
+    use super::*;
+
+    /// Toml datetimes, which may also arrive as strings
+    fn datetime<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<toml::value::Datetime, D::Error> {
+        match toml::Value::deserialize(deserializer)? {
+            toml::Value::Datetime(datetime) => Ok(datetime),
+            toml::Value::String(s) => s.parse().map_err(serde::de::Error::custom),
+            other => Err(serde::de::Error::custom(format!(
+                "expected a date or time, found {}",
+                other.type_str()
+            ))),
+        }
+    }
+
+    /// Local time, such as `07:15:00`
+    pub fn time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let datetime = datetime(deserializer)?;
+
+        datetime
+            .time
+            .filter(|_| datetime.date.is_none())
+            .and_then(|t| {
+                NaiveTime::from_hms_nano_opt(
+                    t.hour.into(),
+                    t.minute.into(),
+                    t.second.into(),
+                    t.nanosecond,
+                )
+            })
+            .ok_or(serde::de::Error::custom(format!(
+                "expected a time like 07:15:00, found {}",
+                datetime
+            )))
+    }
+
+    /// Local date, such as `1970-01-01`
+    pub fn date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+        let datetime = datetime(deserializer)?;
+
+        datetime
+            .date
+            .filter(|_| datetime.time.is_none())
+            .and_then(|d| NaiveDate::from_ymd_opt(d.year.into(), d.month.into(), d.day.into()))
+            .ok_or(serde::de::Error::custom(format!(
+                "expected a date like 1970-01-01, found {}",
+                datetime
+            )))
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(i64),
+    }
+
+    impl From<StringOrNumber> for String {
+        fn from(value: StringOrNumber) -> Self {
+            match value {
+                StringOrNumber::String(s) => s,
+                StringOrNumber::Number(n) => n.to_string(),
+            }
+        }
+    }
+
+    /// Phone numbers and ids may be written with or without quotes
+    pub fn string_or_number<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<String, D::Error> {
+        Ok(StringOrNumber::deserialize(deserializer)?.into())
+    }
+
+    pub fn option_string_or_number<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<String>, D::Error> {
+        Ok(Option::<StringOrNumber>::deserialize(deserializer)?.map(String::from))
+    }
+}
@@ -0,0 +1,617 @@
+//! Checks on the merged settings, beyond what deserializing them catches.
+//!
+//! Every problem is collected, along with the file (or environment variable)
+//! that set the offending key.
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use crate::load::{
+    changed_keys, from_table, read_layers, read_required, DEBUG_FILE, DEPLOY_FILE, ENV_PREFIX,
+};
+use crate::{
+    apply_env_overrides, ConfigError, EventAction, Schedule, Settings, EXCO_CHAT, TEMPLATE_FILE,
+};
+
+/// Tables with user defined keys, and the type of their values
+const OPEN_TABLES: [(&str, &str); 4] = [
+    ("canoebot.known_chats", "integer"),
+    ("canoebot.admins", "integer"),
+    ("canoebot.exco_members", "integer"),
+    ("canoebot.misc_handlers", "boolean"),
+];
+
+/// Strings that must not be empty
+const NON_EMPTY: [&str; 2] = ["canoebot.apikey", "formfiller.form_id"];
+
+/// Placeholder keys in `sheetscraper.paddling`, and the format they are substituted into
+const PLACEHOLDERS: [(&str, &str); 11] = [
+    ("sub_session", "format"),
+    ("sub_date", "format"),
+    ("sub_boatallo", "format"),
+    ("sub_exclude", "format"),
+    ("sub_prog", "format"),
+    ("sub_fetch", "format"),
+    ("sub_header", "format"),
+    ("sub_footer", "format"),
+    ("sub_arrive", "header_format"),
+    ("sub_start", "header_format"),
+    ("sub_finish", "footer_format"),
+];
+
+/// A problem with a single key
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    /// File or environment variable the key was set in
+    pub source: String,
+    /// Dotted key path, with array indices
+    pub key: String,
+    pub message: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: `{}` {}", self.source, self.key, self.message)
+    }
+}
+
+/// Where keys in the merged table came from
+struct Sources {
+    template: PathBuf,
+    file: Option<(PathBuf, Table)>,
+    /// Keys set by environment variables
+    env: Vec<String>,
+}
+
+impl Sources {
+    fn of(&self, key: &str) -> String {
+        if let Some(env) = self
+            .env
+            .iter()
+            .find(|k| key == *k || key.starts_with(&format!("{}.", k)))
+        {
+            return format!(
+                "{}{}",
+                ENV_PREFIX,
+                env.replace('.', "__").to_ascii_uppercase()
+            );
+        }
+
+        match &self.file {
+            Some((path, table)) if contains(table, key) => path.display().to_string(),
+            _ => self.template.display().to_string(),
+        }
+    }
+}
+
+/// Checks if a table sets a key. Arrays count as set as a whole.
+fn contains(table: &Table, key: &str) -> bool {
+    let mut current = table;
+    for segment in key.split('.') {
+        let segment = segment.split('[').next().unwrap_or(segment);
+        match current.get(segment) {
+            Some(Value::Table(t)) => current = t,
+            Some(_) => return true,
+            None => return false,
+        }
+    }
+
+    true
+}
+
+/// Type of a value, distinguishing dates from times
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Datetime(dt) => match (dt.date, dt.time, dt.offset) {
+            (Some(_), None, _) => "date",
+            (None, Some(_), _) => "time",
+            (Some(_), Some(_), None) => "local datetime",
+            _ => "datetime",
+        },
+        other => other.type_str(),
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    match prefix.len() {
+        0 => key.to_string(),
+        _ => format!("{}.{}", prefix, key),
+    }
+}
+
+/// Compare the merged table against the template, which has every key the bot reads.
+fn check_schema(
+    prefix: &str,
+    template: &Table,
+    merged: &Table,
+    problems: &mut Vec<(String, String)>,
+) {
+    for (key, value) in merged {
+        let path = join(prefix, key);
+        let Some(expected) = template.get(key) else {
+            problems.push((path, "is not a known setting".to_string()));
+            continue;
+        };
+
+        match (expected, value) {
+            (Value::Table(expected), Value::Table(value)) => {
+                match OPEN_TABLES.iter().find(|(table, _)| *table == path) {
+                    Some((_, value_kind)) => {
+                        for (k, v) in value {
+                            if kind(v) != *value_kind {
+                                problems.push((
+                                    join(&path, k),
+                                    format!("should be {}, found {}", value_kind, kind(v)),
+                                ));
+                            }
+                        }
+                    }
+                    None => check_schema(&path, expected, value, problems),
+                }
+            }
+            (Value::Array(expected), Value::Array(value)) => {
+                // arrays of tables are left to the typed settings
+                let Some(element) = expected.first().filter(|e| !e.is_table()) else {
+                    continue;
+                };
+
+                for (idx, v) in value.iter().enumerate() {
+                    if kind(v) != kind(element) {
+                        problems.push((
+                            format!("{}[{}]", path, idx),
+                            format!("should be {}, found {}", kind(element), kind(v)),
+                        ));
+                    }
+                }
+            }
+            (expected, value) if kind(expected) != kind(value) => problems.push((
+                path,
+                format!("should be {}, found {}", kind(expected), kind(value)),
+            )),
+            _ => (),
+        }
+    }
+}
+
+fn get<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let (first, rest) = key.split_once('.').unwrap_or((key, ""));
+    match (table.get(first)?, rest) {
+        (value, "") => Some(value),
+        (Value::Table(t), rest) => get(t, rest),
+        _ => None,
+    }
+}
+
+/// Checks that need more than the type of a key
+fn check_values(merged: &Table, problems: &mut Vec<(String, String)>) {
+    for key in NON_EMPTY {
+        if get(merged, key)
+            .and_then(Value::as_str)
+            .is_some_and(|s| s.trim().is_empty())
+        {
+            problems.push((key.to_string(), "must not be empty".to_string()));
+        }
+    }
+
+    match get(merged, "formfiller.particulars").and_then(Value::as_array) {
+        Some(particulars) if particulars.is_empty() => problems.push((
+            "formfiller.particulars".to_string(),
+            "needs at least one entry".to_string(),
+        )),
+        Some(particulars) => {
+            for (idx, entry) in particulars.iter().enumerate() {
+                for field in ["name", "number"] {
+                    let present = match entry.get(field) {
+                        Some(Value::String(s)) => !s.trim().is_empty(),
+                        Some(Value::Integer(_)) => field == "number",
+                        _ => false,
+                    };
+
+                    if !present {
+                        problems.push((
+                            format!("formfiller.particulars[{}].{}", idx, field),
+                            "is missing or empty".to_string(),
+                        ));
+                    }
+                }
+            }
+        }
+        None => (),
+    }
+
+    check_events(merged, problems);
+    check_webhook(merged, problems);
+    check_sheet_source(merged, problems);
+
+    let key = "canoebot.error_reports.chat";
+    if let Some(chat) = get(merged, key).and_then(Value::as_str) {
+        if !known_chat(merged, chat) {
+            problems.push((
+                key.to_string(),
+                format!("\"{}\" is not in canoebot.known_chats", chat),
+            ));
+        }
+    }
+
+    let paddling = "sheetscraper.paddling";
+    for (placeholder, format) in PLACEHOLDERS {
+        let sub = get(merged, &join(paddling, placeholder)).and_then(Value::as_str);
+        let format_str = get(merged, &join(paddling, format)).and_then(Value::as_str);
+
+        if let (Some(sub), Some(format_str)) = (sub, format_str) {
+            if !format_str.contains(sub) {
+                problems.push((
+                    join(paddling, placeholder),
+                    format!("\"{}\" does not appear in {}.{}", sub, paddling, format),
+                ));
+            }
+        }
+    }
+}
+
+/// Telegram only sends updates to https, with secret tokens of a limited charset
+fn check_webhook(merged: &Table, problems: &mut Vec<(String, String)>) {
+    let webhook = "canoebot.webhook";
+
+    if let Some(url) = get(merged, &join(webhook, "url")).and_then(Value::as_str) {
+        if !url.is_empty() && !url.starts_with("https://") {
+            problems.push((
+                join(webhook, "url"),
+                format!("\"{}\" is not an https url", url),
+            ));
+        }
+    }
+
+    let key = join(webhook, "secret_token");
+    if let Some(token) = get(merged, &key).and_then(Value::as_str) {
+        if token.len() > 256 {
+            problems.push((key.clone(), "must be at most 256 characters".to_string()));
+        }
+        if !token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            problems.push((key, "must only contain a-z, A-Z, 0-9, _ and -".to_string()));
+        }
+    }
+}
+
+/// The sheet source backend is known, and the api backend has a usable key file
+fn check_sheet_source(merged: &Table, problems: &mut Vec<(String, String)>) {
+    let source = "sheetscraper.source";
+    let setting = |key: &str| get(merged, &join(source, key)).and_then(Value::as_str);
+
+    match setting("backend") {
+        Some("gviz") | None => (),
+        Some("dir") => {
+            if setting("dir").unwrap_or("").trim().is_empty() {
+                problems.push((
+                    join(source, "dir"),
+                    "must be set for the dir backend".to_string(),
+                ));
+            }
+        }
+        Some("api") => {
+            let key = join(source, "api.key_file");
+            match setting("api.key_file").unwrap_or("") {
+                "" => problems.push((key, "must be set for the api backend".to_string())),
+                path => {
+                    if let Err(e) = check_key_file(Path::new(path)) {
+                        problems.push((key, format!("\"{}\" {}", path, e)));
+                    }
+                }
+            }
+        }
+        Some(other) => problems.push((
+            join(source, "backend"),
+            format!("\"{}\" is not one of gviz, dir or api", other),
+        )),
+    }
+}
+
+/// A service account key file has the fields needed to authenticate
+fn check_key_file(path: &Path) -> Result<(), String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("cannot be read: {}", e))?;
+    let key: serde_json::Value =
+        serde_json::from_str(&contents).map_err(|e| format!("is not valid json: {}", e))?;
+
+    match ["client_email", "private_key", "token_uri"]
+        .into_iter()
+        .find(|field| !key[field].is_string())
+    {
+        Some(field) => Err(format!("is missing `{}`", field)),
+        None => Ok(()),
+    }
+}
+
+/// An alias in `canoebot.known_chats`, or the exco chat
+fn known_chat(merged: &Table, chat: &str) -> bool {
+    chat == EXCO_CHAT
+        || get(merged, "canoebot.known_chats")
+            .and_then(Value::as_table)
+            .is_some_and(|c| c.contains_key(chat))
+}
+
+/// Events need a unique name, a valid schedule and action, and a known chat
+fn check_events(merged: &Table, problems: &mut Vec<(String, String)>) {
+    let Some(events) = get(merged, "events").and_then(Value::as_array) else {
+        return;
+    };
+    let mut names = HashSet::new();
+
+    for (idx, event) in events.iter().enumerate() {
+        let key = |field: &str| format!("events[{}].{}", idx, field);
+        let field = |field: &str| event.get(field).and_then(Value::as_str);
+
+        match field("name") {
+            Some(name) if !names.insert(name) => problems.push((
+                key("name"),
+                format!("\"{}\" is used by another event", name),
+            )),
+            Some(_) => (),
+            None => problems.push((key("name"), "is missing".to_string())),
+        }
+
+        match field("schedule").map(str::parse::<Schedule>) {
+            Some(Ok(_)) => (),
+            Some(Err(e)) => problems.push((key("schedule"), e)),
+            None => problems.push((key("schedule"), "is missing".to_string())),
+        }
+
+        match field("chat") {
+            Some(chat) if known_chat(merged, chat) => (),
+            Some(chat) => problems.push((
+                key("chat"),
+                format!("\"{}\" is not in canoebot.known_chats", chat),
+            )),
+            None => problems.push((key("chat"), "is missing".to_string())),
+        }
+
+        match event
+            .get("action")
+            .cloned()
+            .map(Value::try_into::<EventAction>)
+        {
+            Some(Ok(EventAction::Message)) if field("text").unwrap_or("").trim().is_empty() => {
+                problems.push((key("text"), "must not be empty for messages".to_string()))
+            }
+            Some(Ok(_)) => (),
+            Some(Err(e)) => problems.push((key("action"), e.to_string().trim_end().to_string())),
+            None => problems.push((key("action"), "is missing".to_string())),
+        }
+    }
+}
+
+/// Validate settings in a directory, applying overrides from environment variables.
+///
+/// Unlike [crate::load_from], a debug or deploy file with `use = true` is required.
+pub fn check_from<I>(dir: &Path, vars: I) -> Result<Settings, ConfigError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    check_table(dir, vars).map(|(settings, _)| settings)
+}
+
+/// Validate settings, also returning the merged table they were read from
+pub(crate) fn check_table<I>(dir: &Path, vars: I) -> Result<(Settings, Table), ConfigError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let template_path = dir.join(TEMPLATE_FILE);
+    let template = read_required(&template_path)?;
+    let (merged_files, source) = read_layers(dir)?;
+
+    let mut merged = merged_files.clone();
+    apply_env_overrides(&mut merged, vars)?;
+
+    let file = match &source {
+        Some(path) => Some((path.clone(), read_required(path)?)),
+        None => None,
+    };
+    let sources = Sources {
+        template: template_path,
+        file,
+        env: changed_keys(&merged_files, &merged),
+    };
+
+    let mut found: Vec<(String, String)> = Vec::new();
+
+    if !merged.get("use").and_then(Value::as_bool).unwrap_or(false) {
+        found.push((
+            "use".to_string(),
+            format!("must be true in {} or {}", DEBUG_FILE, DEPLOY_FILE),
+        ));
+    }
+    check_schema("", &template, &merged, &mut found);
+    check_values(&merged, &mut found);
+
+    if !found.is_empty() {
+        let problems = found
+            .into_iter()
+            .map(|(key, message)| Problem {
+                source: sources.of(&key),
+                key,
+                message,
+            })
+            .collect();
+
+        return Err(ConfigError::Problems(problems));
+    }
+
+    Ok((from_table(merged.clone(), source)?, merged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_dir;
+
+    /// Copy the template into a fresh directory, with a deploy file
+    fn setup(name: &str, deploy: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy(config_dir().join(TEMPLATE_FILE), dir.join(TEMPLATE_FILE)).unwrap();
+        std::fs::write(dir.join(DEPLOY_FILE), deploy).unwrap();
+
+        dir
+    }
+
+    const VALID: &str = r#"
+use = true
+
+[canoebot]
+apikey = "12345:abc"
+
+[canoebot.admins]
+someone = 1234
+
+[formfiller]
+form_id = "abc"
+
+[[formfiller.particulars]]
+name = "someone"
+number = 98765432
+"#;
+
+    #[test]
+    fn test_check_valid() {
+        let dir = setup("config_test_check_valid", VALID);
+
+        let settings = check_from(&dir, []).unwrap();
+        assert_eq!(settings.formfiller.particulars[0].number, "98765432");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_key_file() {
+        let dir = std::env::temp_dir().join("config_test_check_key_file");
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = |name: &str, contents: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, contents).unwrap();
+            check_key_file(&path)
+        };
+
+        assert_eq!(
+            key(
+                "valid.json",
+                r#"{"client_email": "a@b.com", "private_key": "-----", "token_uri": "https://x"}"#
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            key("partial.json", r#"{"client_email": "a@b.com"}"#),
+            Err("is missing `private_key`".to_string())
+        );
+        assert!(key("broken.json", "{")
+            .unwrap_err()
+            .starts_with("is not valid json"));
+        assert!(check_key_file(&dir.join("missing.json"))
+            .unwrap_err()
+            .starts_with("cannot be read"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_problems() {
+        let deploy = r#"
+use = false
+
+[canoebot]
+apikey = "12345:abc"
+
+[canoebot.admins]
+someone = "1234"
+
+[canoebot.webhook]
+url = "http://example.com/canoebot"
+secret_token = "not so secret"
+
+[canoebot.error_reports]
+chat = "nowhere"
+
+[sheetscraper.source]
+backend = "api"
+
+[sheetscraper.source.api]
+key_file = "/nonexistent/key.json"
+
+[sheetscraper.columns.attd]
+nmae = "Name"
+
+[sheetscraper.paddling]
+format = "{session} {date}"
+
+[formfiller]
+form_id = "abc"
+
+[[formfiller.particulars]]
+name = "someone"
+
+[formfiller.times.am]
+start = "7am"
+
+[[events]]
+name = "reminder"
+enable = true
+schedule = "daily 7am"
+chat = "somewhere"
+action = "message"
+
+[[events]]
+name = "reminder"
+enable = true
+schedule = "weekly sat 07:00"
+chat = "exco"
+action = "dance"
+"#;
+        let dir = setup("config_test_check_problems", deploy);
+
+        let vars = [(
+            "CANOEBOT__SHEETSCRAPER__CACHE__ATTD".to_string(),
+            "soon".to_string(),
+        )];
+        let Err(ConfigError::Problems(problems)) = check_from(&dir, vars) else {
+            panic!("expected problems");
+        };
+        let found: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+
+        let deploy_path = dir.join(DEPLOY_FILE).display().to_string();
+        let template_path = dir.join(TEMPLATE_FILE).display().to_string();
+        let expected = [
+            format!("{}: `use` must be true", deploy_path),
+            format!("{}: `canoebot.admins.someone` should be integer, found string", deploy_path),
+            format!("{}: `sheetscraper.columns.attd.nmae` is not a known setting", deploy_path),
+            format!("{}: `canoebot.webhook.url` \"http://example.com/canoebot\" is not an https url", deploy_path),
+            format!("{}: `canoebot.webhook.secret_token` must only contain", deploy_path),
+            format!("{}: `canoebot.error_reports.chat` \"nowhere\" is not in canoebot.known_chats", deploy_path),
+            format!("{}: `sheetscraper.source.api.key_file` \"/nonexistent/key.json\" cannot be read", deploy_path),
+            format!("{}: `formfiller.times.am.start` should be time, found string", deploy_path),
+            format!("{}: `formfiller.particulars[0].number` is missing or empty", deploy_path),
+            format!("{}: `sheetscraper.paddling.sub_boatallo` \"{{boatallo}}\" does not appear", template_path),
+            format!("{}: `events[0].schedule` invalid time \"7am\"", deploy_path),
+            format!("{}: `events[0].chat` \"somewhere\" is not in canoebot.known_chats", deploy_path),
+            format!("{}: `events[0].text` must not be empty", deploy_path),
+            format!("{}: `events[1].name` \"reminder\" is used by another event", deploy_path),
+            format!("{}: `events[1].action` unknown variant `dance`", deploy_path),
+            "CANOEBOT__SHEETSCRAPER__CACHE__ATTD: `sheetscraper.cache.attd` should be integer, found string".to_string(),
+        ];
+
+        for e in expected {
+            assert!(
+                found.iter().any(|f| f.starts_with(&e)),
+                "missing: {}\nfound: {:#?}",
+                e,
+                found
+            );
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
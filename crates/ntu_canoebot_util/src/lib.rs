@@ -1,6 +1,7 @@
 //! Library for common utility functions that are used by other crates.
 
 mod macros;
+pub mod metrics;
 
 use std::{
     error::Error,
@@ -0,0 +1,266 @@
+//! Counters, gauges and histograms, exported in the prometheus text format.
+//!
+//! Metrics are declared as constants where they are recorded, e.g.
+//! ```
+//! use ntu_canoebot_util::metrics::{self, Counter};
+//!
+//! const REQUESTS: Counter = Counter::new("requests_total", "requests handled");
+//!
+//! REQUESTS.increment(&[("path", "/")]);
+//! assert!(metrics::render().contains("requests_total{path=\"/\"} 1"));
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Buckets for durations, in seconds
+pub const LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Label names and values of a series
+pub type Labels<'a> = &'a [(&'static str, &'a str)];
+
+static REGISTRY: Mutex<BTreeMap<&'static str, Family>> = Mutex::new(BTreeMap::new());
+
+/// All series of a metric
+struct Family {
+    help: &'static str,
+    kind: &'static str,
+    series: BTreeMap<Vec<(&'static str, String)>, Series>,
+}
+
+enum Series {
+    Counter(u64),
+    Gauge(f64),
+    Histogram {
+        buckets: &'static [f64],
+        /// Observations in each bucket, not cumulative
+        counts: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+/// Update a series, creating it if needed
+fn update(
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    labels: Labels,
+    new: impl FnOnce() -> Series,
+    update: impl FnOnce(&mut Series),
+) {
+    let mut registry = REGISTRY.lock().unwrap();
+    let family = registry.entry(name).or_insert_with(|| Family {
+        help,
+        kind,
+        series: BTreeMap::new(),
+    });
+
+    let key = labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+    update(family.series.entry(key).or_insert_with(new));
+}
+
+/// A value that only goes up
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Self { name, help }
+    }
+
+    pub fn increment(&self, labels: Labels) {
+        update(
+            self.name,
+            self.help,
+            "counter",
+            labels,
+            || Series::Counter(0),
+            |series| {
+                if let Series::Counter(count) = series {
+                    *count += 1;
+                }
+            },
+        );
+    }
+}
+
+/// A value that can go up and down
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Self { name, help }
+    }
+
+    pub fn set(&self, labels: Labels, value: f64) {
+        update(
+            self.name,
+            self.help,
+            "gauge",
+            labels,
+            || Series::Gauge(0.0),
+            |series| {
+                if let Series::Gauge(gauge) = series {
+                    *gauge = value;
+                }
+            },
+        );
+    }
+}
+
+/// Counts of observations within buckets
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    /// Upper bounds, ascending
+    buckets: &'static [f64],
+}
+
+impl Histogram {
+    pub const fn new(name: &'static str, help: &'static str, buckets: &'static [f64]) -> Self {
+        Self {
+            name,
+            help,
+            buckets,
+        }
+    }
+
+    pub fn observe(&self, labels: Labels, value: f64) {
+        let buckets = self.buckets;
+        update(
+            self.name,
+            self.help,
+            "histogram",
+            labels,
+            || Series::Histogram {
+                buckets,
+                counts: vec![0; buckets.len()],
+                sum: 0.0,
+                count: 0,
+            },
+            |series| {
+                if let Series::Histogram {
+                    buckets,
+                    counts,
+                    sum,
+                    count,
+                } = series
+                {
+                    if let Some(idx) = buckets.iter().position(|b| value <= *b) {
+                        counts[idx] += 1;
+                    }
+                    *sum += value;
+                    *count += 1;
+                }
+            },
+        );
+    }
+}
+
+/// Labels as `{a="1",b="2"}`, with an extra label if given
+fn format_labels(labels: &[(&'static str, String)], extra: Option<(&str, String)>) -> String {
+    let escape = |v: &str| {
+        v.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    };
+
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| (*k, v.clone()))
+        .chain(extra)
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(&v)))
+        .collect();
+
+    match pairs.is_empty() {
+        true => String::new(),
+        false => format!("{{{}}}", pairs.join(",")),
+    }
+}
+
+/// Every metric recorded so far
+pub fn render() -> String {
+    let registry = REGISTRY.lock().unwrap();
+    let mut out = String::new();
+
+    for (name, family) in registry.iter() {
+        let _ = writeln!(out, "# HELP {} {}", name, family.help);
+        let _ = writeln!(out, "# TYPE {} {}", name, family.kind);
+
+        for (labels, series) in family.series.iter() {
+            match series {
+                Series::Counter(count) => {
+                    let _ = writeln!(out, "{}{} {}", name, format_labels(labels, None), count);
+                }
+                Series::Gauge(value) => {
+                    let _ = writeln!(out, "{}{} {}", name, format_labels(labels, None), value);
+                }
+                Series::Histogram {
+                    buckets,
+                    counts,
+                    sum,
+                    count,
+                } => {
+                    let mut cumulative = 0;
+                    for (bound, bucket) in buckets.iter().zip(counts) {
+                        cumulative += bucket;
+                        let labels = format_labels(labels, Some(("le", bound.to_string())));
+                        let _ = writeln!(out, "{}_bucket{} {}", name, labels, cumulative);
+                    }
+                    let inf = format_labels(labels, Some(("le", "+Inf".to_string())));
+                    let _ = writeln!(out, "{}_bucket{} {}", name, inf, count);
+
+                    let labels = format_labels(labels, None);
+                    let _ = writeln!(out, "{}_sum{} {}", name, labels, sum);
+                    let _ = writeln!(out, "{}_count{} {}", name, labels, count);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        const HITS: Counter = Counter::new("test_hits_total", "hits");
+        const AGE: Gauge = Gauge::new("test_age_seconds", "age");
+        const LATENCY: Histogram = Histogram::new("test_latency_seconds", "latency", &[0.5, 1.0]);
+
+        HITS.increment(&[("path", "/a")]);
+        HITS.increment(&[("path", "/a")]);
+        HITS.increment(&[("path", "say \"hi\"")]);
+        AGE.set(&[], 12.5);
+        for value in [0.25, 0.75, 3.0] {
+            LATENCY.observe(&[("sheet", "configs")], value);
+        }
+
+        let rendered = render();
+        let expected = [
+            "# HELP test_hits_total hits\n# TYPE test_hits_total counter\n",
+            "test_hits_total{path=\"/a\"} 2\n",
+            "test_hits_total{path=\"say \\\"hi\\\"\"} 1\n",
+            "# TYPE test_age_seconds gauge\ntest_age_seconds 12.5\n",
+            "test_latency_seconds_bucket{sheet=\"configs\",le=\"0.5\"} 1\n\
+            test_latency_seconds_bucket{sheet=\"configs\",le=\"1\"} 2\n\
+            test_latency_seconds_bucket{sheet=\"configs\",le=\"+Inf\"} 3\n\
+            test_latency_seconds_sum{sheet=\"configs\"} 4\n\
+            test_latency_seconds_count{sheet=\"configs\"} 3\n",
+        ];
+
+        for e in expected {
+            assert!(rendered.contains(e), "missing:\n{}\nin:\n{}", e, rendered);
+        }
+    }
+}
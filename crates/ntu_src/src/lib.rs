@@ -39,8 +39,11 @@ lazy_static::lazy_static! {
         // let toml_val: HashMap<String, Vec<SrcFacility>> = toml::from_str(&tomlfile).expect("failed to parse toml");
 
         // let inner_vec = toml_val.values().next().expect("map should have one entry");
-        let serialized_str = serde_json::to_string(&*config::FACILITIES).unwrap();
-        let inner_vec: Vec<SrcFacility> = serde_json::from_str(&serialized_str).unwrap();
+        let inner_vec: Vec<SrcFacility> = config::settings()
+            .facilities
+            .iter()
+            .map(SrcFacility::from)
+            .collect();
 
         debug_println!("constructed global static SRC_FACILITIES");
         SrcFacilities {
@@ -626,6 +629,17 @@ where
     T::from_str(&s).map_err(de::Error::custom)
 }
 
+impl From<&config::Facility> for SrcFacility {
+    fn from(value: &config::Facility) -> Self {
+        Self {
+            name: value.name.clone(),
+            short_name: value.shortname.clone(),
+            code_name: value.codename.clone(),
+            courts: value.courts,
+        }
+    }
+}
+
 /// Wrapper around a vector of [SrcFacility]
 #[derive(Debug)]
 pub struct SrcFacilities {
@@ -910,11 +924,12 @@ mod tests {
     fn test_config_fetch() {
         use ntu_canoebot_config as config;
 
-        let x = config::FACILITIES.clone();
+        let x = config::settings().facilities.clone();
         let serialized = serde_json::to_string_pretty(&x).unwrap();
 
         println!("{}", serialized);
-        let des: Vec<SrcFacility> = serde_json::from_str(&serialized).unwrap();
+        let des: Vec<SrcFacility> = x.iter().map(SrcFacility::from).collect();
         println!("{:#?}", des);
+        assert_eq!(des.len(), x.len());
     }
 }